@@ -0,0 +1,385 @@
+/*!
+Initial populations from census-style age/sex tables.
+
+A table is CSV with a header naming `age`, `males` and `females` columns, in any order and
+alongside any others, which are ignored:
+
+```text
+age,males,females
+0-4,30512,29113
+5-9,31200,29870
+...
+95-99,1210,3011
+100+,160,610
+```
+
+Ages are single years (`37`), five-year groups (`35-39`), or an open-ended terminal group
+(`100+`), youngest first with no gaps. Five-year groups are split into single years with Sprague
+or Beers multipliers, which keep every group's total. An open group is spread over the ages above
+it in proportion to the survivors of a stationary population under the default mortality schedule.
+
+Counts are taken to be at the start of the reference year, so someone aged `a` in the table was
+born in `reference_year - 1 - a`, as with `Population::new`.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use population::Population;
+use population::schedule::Schedule;
+
+/// An open group is spread over ages up to this one.
+const OPEN_GROUP_LIMIT: usize = 130;
+
+#[derive(Debug)]
+pub struct CensusError { problem: String }
+
+impl CensusError {
+    pub fn new<S: Into<String>>(problem: S) -> CensusError {
+        CensusError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for CensusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for CensusError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+/// How five-year groups are split into single years.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    /// Sprague's fifth-difference osculatory multipliers, which reproduce a quartic exactly.
+    Sprague,
+    /// Beers' ordinary multipliers, which also minimize fifth differences of the result.
+    Beers,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AgeGroup {
+    pub start: usize,
+    /// The number of single years in the group, or None for the open terminal group.
+    pub width: Option<usize>,
+    pub males: f64,
+    pub females: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Census {
+    pub reference_year: i32,
+    pub groups: Vec<AgeGroup>,
+}
+
+impl Census {
+    pub fn new(reference_year: i32, groups: Vec<AgeGroup>) -> Result<Census, CensusError> {
+        let census = Census { reference_year, groups };
+        census.validate()?;
+        Ok(census)
+    }
+
+    pub fn from_csv(csv: &str, reference_year: i32) -> Result<Census, CensusError> {
+        let mut lines = csv.lines()
+            .enumerate()
+            .filter(|&(_, line)| !line.trim().is_empty());
+        let header: Vec<String> = match lines.next() {
+            Some((_, line)) => line.split(',').map(|h| h.trim().to_lowercase()).collect(),
+            None => return Err(CensusError::new("The census table is empty.")),
+        };
+        let column = |names: &[&str]| {
+            header.iter().position(|h| names.contains(&h.as_str())).ok_or_else(|| {
+                CensusError::new(format!("The census table has no {} column.", names[0]))
+            })
+        };
+        let age = column(&["age", "ages", "age group"])?;
+        let males = column(&["males", "male", "m"])?;
+        let females = column(&["females", "female", "f"])?;
+
+        let mut groups = Vec::new();
+        for (index, line) in lines {
+            let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+            let field = |column: usize| fields.get(column).cloned().unwrap_or("");
+            let count = |column: usize| {
+                field(column).replace('_', "").parse::<f64>().ok()
+                    .filter(|count| *count >= 0.0)
+                    .ok_or_else(|| CensusError::new(
+                        format!("Line {}: '{}' isn't a count.", index + 1, field(column))))
+            };
+            let (start, width) = parse_age(field(age)).ok_or_else(|| CensusError::new(
+                format!("Line {}: '{}' isn't an age or age group.", index + 1, field(age))))?;
+            groups.push(AgeGroup { start, width, males: count(males)?, females: count(females)? });
+        }
+        Census::new(reference_year, groups)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, reference_year: i32) -> Result<Census, CensusError> {
+        let path = path.as_ref();
+        let mut csv = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut csv))
+            .map_err(|e| CensusError::new(format!("Couldn't open {}: {}", path.display(), e)))?;
+        Census::from_csv(&csv, reference_year)
+    }
+
+    pub fn validate(&self) -> Result<(), CensusError> {
+        let mut next = 0;
+        for (index, group) in self.groups.iter().enumerate() {
+            if group.start != next {
+                return Err(CensusError::new(format!(
+                    "The group starting at age {} should start at {}.", group.start, next)));
+            }
+            match group.width {
+                Some(1) | Some(5) => next += group.width.unwrap(),
+                Some(width) => return Err(CensusError::new(
+                    format!("Age groups must be single years or five years, not {}.", width))),
+                None if index + 1 == self.groups.len() => {},
+                None => return Err(CensusError::new("Only the last age group can be open.")),
+            }
+        }
+        let closed = self.closed_groups();
+        let five_year = closed.iter().filter(|group| group.width == Some(5)).count();
+        if five_year != 0 && five_year != closed.len() {
+            Err(CensusError::new("Age groups can't mix single years and five-year groups."))
+        } else if five_year != 0 && five_year < 5 {
+            Err(CensusError::new("Interpolation needs at least five five-year groups."))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn closed_groups(&self) -> &[AgeGroup] {
+        match self.groups.last() {
+            Some(last) if last.width.is_none() => &self.groups[..self.groups.len() - 1],
+            _ => &self.groups,
+        }
+    }
+
+    /// Males and females at each single year of age, starting from zero.
+    pub fn single_years(&self, interpolation: Interpolation) -> Vec<(f64, f64)> {
+        let closed = self.closed_groups();
+        let (males, females) = if closed.iter().all(|group| group.width == Some(1)) {
+            (closed.iter().map(|group| group.males).collect(),
+             closed.iter().map(|group| group.females).collect())
+        } else {
+            let multipliers = match interpolation {
+                Interpolation::Sprague => &SPRAGUE,
+                Interpolation::Beers => &BEERS,
+            };
+            let males: Vec<f64> = closed.iter().map(|group| group.males).collect();
+            let females: Vec<f64> = closed.iter().map(|group| group.females).collect();
+            (split(&males, multipliers), split(&females, multipliers))
+        };
+        let mut ages: Vec<(f64, f64)> = males.into_iter().zip(females).collect();
+
+        if let Some(open) = self.groups.last().filter(|group| group.width.is_none()) {
+            let schedule = Schedule::default();
+            let male_weights = survivors(open.start, |age| schedule.male_mortality.at_age(age));
+            let female_weights = survivors(open.start, |age| schedule.female_mortality.at_age(age));
+            for (m, f) in male_weights.into_iter().zip(female_weights) {
+                ages.push((open.males * m, open.females * f));
+            }
+        }
+        ages
+    }
+
+    /// Whole counts at each single year of age, rounded.
+    pub fn counts(&self, interpolation: Interpolation) -> Vec<(usize, usize)> {
+        self.single_years(interpolation).into_iter()
+            .map(|(males, females)| (males.round() as usize, females.round() as usize))
+            .collect()
+    }
+
+    /// A population starting in the reference year, with the default rates.
+    pub fn population(&self, interpolation: Interpolation) -> Population {
+        Population::new(self.counts(interpolation)).with_start_year(self.reference_year)
+    }
+}
+
+/// `37`, `35-39` or `100+`, as a start and width.
+fn parse_age(label: &str) -> Option<(usize, Option<usize>)> {
+    let label = label.trim_matches('"').trim();
+    if let Some(start) = label.strip_suffix('+') {
+        return Some((start.trim().parse().ok()?, None));
+    }
+    let mut bounds = label.splitn(2, ['-', '–']);
+    let start: usize = bounds.next()?.trim().parse().ok()?;
+    match bounds.next() {
+        None => Some((start, Some(1))),
+        Some(end) => {
+            let end: usize = end.trim().parse().ok()?;
+            if end < start { None } else { Some((start, Some(end - start + 1))) }
+        }
+    }
+}
+
+/// The proportion of an open group at each age from `start`, as in a stationary population.
+fn survivors<F: Fn(i32) -> f32>(start: usize, mortality: F) -> Vec<f64> {
+    let mut alive = 1.0;
+    let mut weights = Vec::new();
+    for age in start..OPEN_GROUP_LIMIT + 1 {
+        weights.push(alive);
+        alive *= 1.0 - mortality(age as i32) as f64;
+    }
+    let total: f64 = weights.iter().sum();
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+type Panel = [[f64; 5]; 5];
+
+/// The first, second and middle panels of a set of multipliers. Each row gives one single year
+/// as a combination of five neighbouring groups; the last two panels mirror the first two.
+struct Multipliers {
+    first: Panel,
+    second: Panel,
+    middle: Panel,
+}
+
+const SPRAGUE: Multipliers = Multipliers {
+    first:
+        [ [ 0.3616, -0.2768,  0.1488, -0.0336,  0.0000 ]
+        , [ 0.2640, -0.0960,  0.0400, -0.0080,  0.0000 ]
+        , [ 0.1840,  0.0400, -0.0320,  0.0080,  0.0000 ]
+        , [ 0.1200,  0.1360, -0.0720,  0.0160,  0.0000 ]
+        , [ 0.0704,  0.1968, -0.0848,  0.0176,  0.0000 ] ],
+    second:
+        [ [ 0.0336,  0.2272, -0.0752,  0.0144,  0.0000 ]
+        , [ 0.0080,  0.2320, -0.0480,  0.0080,  0.0000 ]
+        , [-0.0080,  0.2160, -0.0080,  0.0000,  0.0000 ]
+        , [-0.0160,  0.1840,  0.0400, -0.0080,  0.0000 ]
+        , [-0.0176,  0.1408,  0.0912, -0.0144,  0.0000 ] ],
+    middle:
+        [ [-0.0128,  0.0848,  0.1504, -0.0240,  0.0016 ]
+        , [-0.0016,  0.0144,  0.2224, -0.0416,  0.0064 ]
+        , [ 0.0064, -0.0336,  0.2544, -0.0336,  0.0064 ]
+        , [ 0.0064, -0.0416,  0.2224,  0.0144, -0.0016 ]
+        , [ 0.0016, -0.0240,  0.1504,  0.0848, -0.0128 ] ],
+};
+
+const BEERS: Multipliers = Multipliers {
+    first:
+        [ [ 0.3333, -0.1636, -0.0210,  0.0796, -0.0283 ]
+        , [ 0.2595, -0.0780,  0.0130,  0.0100, -0.0045 ]
+        , [ 0.1924,  0.0064,  0.0184, -0.0256,  0.0084 ]
+        , [ 0.1329,  0.0844,  0.0054, -0.0356,  0.0129 ]
+        , [ 0.0819,  0.1508, -0.0158, -0.0284,  0.0115 ] ],
+    second:
+        [ [ 0.0404,  0.2000, -0.0344, -0.0128,  0.0068 ]
+        , [ 0.0093,  0.2268, -0.0402,  0.0028,  0.0013 ]
+        , [-0.0108,  0.2272, -0.0248,  0.0112, -0.0028 ]
+        , [-0.0198,  0.1992,  0.0172,  0.0072, -0.0038 ]
+        , [-0.0191,  0.1468,  0.0822, -0.0084, -0.0015 ] ],
+    middle:
+        [ [-0.0117,  0.0804,  0.1570, -0.0284,  0.0027 ]
+        , [-0.0020,  0.0160,  0.2200, -0.0400,  0.0060 ]
+        , [ 0.0050, -0.0280,  0.2460, -0.0280,  0.0050 ]
+        , [ 0.0060, -0.0400,  0.2200,  0.0160, -0.0020 ]
+        , [ 0.0027, -0.0284,  0.1570,  0.0804, -0.0117 ] ],
+};
+
+/// Split five-year group totals into single years. Interpolation can dip below zero where counts
+/// fall steeply; those years are clamped to zero.
+fn split(groups: &[f64], multipliers: &Multipliers) -> Vec<f64> {
+    let count = groups.len();
+    let mut years = Vec::with_capacity(count * 5);
+    for group in 0..count {
+        let (first, reversed, panel) = match group {
+            0 => (0, false, &multipliers.first),
+            1 => (0, false, &multipliers.second),
+            g if g + 2 == count => (count - 5, true, &multipliers.second),
+            g if g + 1 == count => (count - 5, true, &multipliers.first),
+            g => (g - 2, false, &multipliers.middle),
+        };
+        for year in 0..5 {
+            let row = if reversed { &panel[4 - year] } else { &panel[year] };
+            let value: f64 = (0..5).map(|column| {
+                let weight = if reversed { row[4 - column] } else { row[column] };
+                weight * groups[first + column]
+            }).sum();
+            years.push(value.max(0.0));
+        }
+    }
+    years
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Census, Interpolation};
+
+    fn five_year_table() -> String {
+        let mut csv = String::from("Age,Females,Males,Notes\n");
+        for group in 0..20 {
+            let start = group * 5;
+            let count = 50_000.0 * (-0.004 * start as f64 * start as f64 / 10.0).exp();
+            csv.push_str(&format!("{}-{},{},{},\n", start, start + 4, count * 0.98, count));
+        }
+        csv.push_str("100+,900,2100,oldest\n");
+        csv
+    }
+
+    #[test]
+    fn interpolation_keeps_group_totals() {
+        let census = Census::from_csv(&five_year_table(), 2020).unwrap();
+        for &method in [Interpolation::Sprague, Interpolation::Beers].iter() {
+            let ages = census.single_years(method);
+            assert!(ages.len() > 100);
+            for (group, expected) in census.groups.iter().take(20).enumerate() {
+                let females: f64 = ages[group * 5..group * 5 + 5].iter().map(|a| a.1).sum();
+                assert!((females - expected.females).abs() < 1e-6 * expected.females.max(1.0),
+                    "{:?} group {}: {} vs {}", method, group, females, expected.females);
+            }
+            let open: f64 = ages[100..].iter().map(|a| a.1).sum();
+            assert!((open - 900.0).abs() < 1e-9);
+            assert!(ages[100].1 > ages[105].1);
+        }
+    }
+
+    #[test]
+    fn reproduces_smooth_curves() {
+        // Both methods are exact for low-order polynomials away from the ends.
+        let exact = |age: f64| 1000.0 + 10.0 * age - 0.1 * age * age;
+        let mut csv = String::from("age,male,female\n");
+        for group in 0..10 {
+            let total: f64 = (0..5).map(|year| exact((group * 5 + year) as f64 + 0.5)).sum();
+            csv.push_str(&format!("{}-{},{},{}\n", group * 5, group * 5 + 4, total, total));
+        }
+        let census = Census::from_csv(&csv, 2000).unwrap();
+        let ages = census.single_years(Interpolation::Sprague);
+        for (age, &(males, _)) in ages.iter().enumerate().take(40).skip(10) {
+            let expected = exact(age as f64 + 0.5);
+            assert!((males - expected).abs() < 0.5, "{}: {}", age, males);
+        }
+    }
+
+    #[test]
+    fn single_years_start_in_the_reference_year() {
+        let csv = "age,males,females\n0,100,90\n1,110,95\n2,120,99\n3+,300,400\n";
+        let census = Census::from_csv(csv, 2021).unwrap();
+        let counts = census.counts(Interpolation::Sprague);
+        assert_eq!(&counts[..3], &[ (100, 90), (110, 95), (120, 99) ]);
+
+        let pop = census.population(Interpolation::Sprague);
+        assert_eq!(pop.current_year(), 2021);
+        assert_eq!(pop.cohorts()[0].birth_year, 2020);
+        let total = counts.iter().map(|&(m, f)| m + f).sum::<usize>();
+        assert_eq!(pop.total_pop(), total);
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        assert!(Census::from_csv("age,males\n0,1\n", 2000).is_err());
+        assert!(Census::from_csv("age,males,females\n0,1,1\n2,1,1\n", 2000).is_err());
+        assert!(Census::from_csv("age,males,females\n0,1,1\n1+,1,1\n2,1,1\n", 2000).is_err());
+        assert!(Census::from_csv("age,males,females\n0-9,1,1\n", 2000).is_err());
+        assert!(Census::from_csv("age,males,females\n0,-1,1\n", 2000).is_err());
+        assert!(Census::from_csv("age,males,females\n0-4,1,1\n5,1,1\n", 2000).is_err());
+    }
+}
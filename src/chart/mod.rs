@@ -0,0 +1,323 @@
+/*!
+Charts of a run, as SVG or PNG files, drawn without a window.
+
+There are age pyramids for a year, pyramids of two scenarios laid over each other, the total
+population with its growth rate, and births and deaths over time. Each takes plain counts, or
+comes from a `History` recorded during the run:
+
+```
+use pop_sim::chart::Chart;
+use pop_sim::population::Population;
+
+let mut pop = Population::new(vec![ ( 1_000, 1_000 ); 50 ]).with_history();
+for _ in 0..20 {
+    pop = pop.advance_year();
+}
+let history = pop.history().unwrap();
+let chart = Chart::totals_of(history);
+assert!(chart.svg().starts_with("<svg"));
+let pyramid = Chart::pyramid_of(history, 10).unwrap();
+```
+
+PNGs are rasterized from the SVG, with text in the system's fonts. Without any fonts, the text is
+left out.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use resvg;
+use resvg::{tiny_skia, usvg};
+
+use population::completed_ages;
+use population::count::Count;
+use population::history::History;
+
+mod svg;
+
+use self::svg::{count_label, extent, Plot, Svg, FEMALE, MALE, PALETTE};
+
+pub const WIDTH: u32 = 800;
+pub const HEIGHT: u32 = 600;
+
+#[derive(Debug)]
+pub struct ChartError { problem: String }
+
+impl ChartError {
+    pub fn new<S: Into<String>>(problem: S) -> ChartError {
+        ChartError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for ChartError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+/// A drawn chart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    svg: String,
+}
+
+impl Chart {
+    /// Males to the left and females to the right, at each completed age.
+    pub fn pyramid<C: Count>(title: &str, distribution: &[(C, C)]) -> Chart {
+        let distribution = reals(distribution);
+        let mut svg = Svg::new(WIDTH, HEIGHT);
+        svg.title(title);
+        let plot = pyramid_plot(&distribution);
+        pyramid_axes(&mut svg, &plot);
+        let height = plot.height / plot.y.1;
+        for (age, &(males, females)) in distribution.iter().enumerate() {
+            let y = plot.y(age as f64 + 1.0);
+            svg.rect(plot.x(-males), y, plot.x(0.0) - plot.x(-males), height * 0.9, MALE);
+            svg.rect(plot.x(0.0), y, plot.x(females) - plot.x(0.0), height * 0.9, FEMALE);
+        }
+        svg.legend(&[("Males", MALE), ("Females", FEMALE)]);
+        Chart { svg: svg.finish() }
+    }
+
+    /// The first scenario's pyramid in bars, and the second's in outline over it.
+    pub fn compare_pyramids<C: Count>(title: &str, first: (&str, &[(C, C)]),
+                                      second: (&str, &[(C, C)])) -> Chart
+    {
+        let (first_label, first) = (first.0, reals(first.1));
+        let (second_label, second) = (second.0, reals(second.1));
+        let mut svg = Svg::new(WIDTH, HEIGHT);
+        svg.title(title);
+        let both: Vec<(f64, f64)> = first.iter().chain(second.iter()).cloned().collect();
+        let mut plot = pyramid_plot(&both);
+        plot.y.1 = first.len().max(second.len()).max(1) as f64;
+        pyramid_axes(&mut svg, &plot);
+        let height = plot.height / plot.y.1;
+        for (age, &(males, females)) in first.iter().enumerate() {
+            let y = plot.y(age as f64 + 1.0);
+            svg.rect(plot.x(-males), y, plot.x(0.0) - plot.x(-males), height, "#b9c9d9");
+            svg.rect(plot.x(0.0), y, plot.x(females) - plot.x(0.0), height, "#e3bcc2");
+        }
+        let outline = |side: f64| -> Vec<(f64, f64)> {
+            let mut points = vec![(plot.x(0.0), plot.bottom())];
+            for (age, &(males, females)) in second.iter().enumerate() {
+                let x = plot.x(side * if side < 0.0 { males } else { females });
+                points.push((x, plot.y(age as f64)));
+                points.push((x, plot.y(age as f64 + 1.0)));
+            }
+            points.push((plot.x(0.0), plot.y(second.len() as f64)));
+            points
+        };
+        svg.polyline(&outline(-1.0), PALETTE[0], 1.5);
+        svg.polyline(&outline(1.0), PALETTE[0], 1.5);
+        svg.legend(&[(first_label, "#c8c2cc"), (second_label, PALETTE[0])]);
+        Chart { svg: svg.finish() }
+    }
+
+    /// The total population each year, and below it the growth rate from each year to the next.
+    pub fn totals<C: Count>(title: &str, totals: &[(i32, C)]) -> Chart {
+        let totals: Vec<(f64, f64)> =
+            totals.iter().map(|&(year, total)| (year as f64, total.to_f64())).collect();
+        let growth: Vec<(f64, f64)> = totals.windows(2)
+            .filter(|pair| pair[0].1 > 0.0 && pair[1].1 > 0.0)
+            .map(|pair| {
+                (pair[0].0, 100.0 * (pair[1].1 / pair[0].1).ln() / (pair[1].0 - pair[0].0))
+            })
+            .collect();
+
+        let mut svg = Svg::new(WIDTH, HEIGHT);
+        svg.title(title);
+        let years = year_range(&totals);
+        let upper = Plot { left: 80.0, top: 50.0, width: 680.0, height: 300.0, x: years,
+                           y: extent(totals.iter().map(|&(_, total)| total)) };
+        line_panel(&mut svg, &upper, "Population", &[&totals], count_label);
+        let lower = Plot { left: 80.0, top: 410.0, width: 680.0, height: 150.0, x: years,
+                           y: extent(growth.iter().map(|&(_, rate)| rate)) };
+        line_panel(&mut svg, &lower, "Growth rate (% a year)", &[&growth], |rate| {
+            format!("{:.2}", rate).trim_end_matches('0').trim_end_matches('.').to_string()
+        });
+        if lower.y.0 < 0.0 {
+            let zero = lower.y(0.0);
+            svg.line((lower.left, zero), (lower.left + lower.width, zero), "#999999");
+        }
+        Chart { svg: svg.finish() }
+    }
+
+    /// Births and deaths each year.
+    pub fn flows<C: Count>(title: &str, flows: &[(i32, C, C)]) -> Chart {
+        let births: Vec<(f64, f64)> =
+            flows.iter().map(|&(year, births, _)| (year as f64, births.to_f64())).collect();
+        let deaths: Vec<(f64, f64)> =
+            flows.iter().map(|&(year, _, deaths)| (year as f64, deaths.to_f64())).collect();
+        let mut svg = Svg::new(WIDTH, HEIGHT);
+        svg.title(title);
+        let plot = Plot {
+            left: 80.0, top: 50.0, width: 680.0, height: 480.0,
+            x: year_range(&births),
+            y: extent(births.iter().chain(deaths.iter()).map(|&(_, count)| count)),
+        };
+        line_panel(&mut svg, &plot, "Per year", &[&births, &deaths], count_label);
+        svg.legend(&[("Births", PALETTE[0]), ("Deaths", PALETTE[1])]);
+        Chart { svg: svg.finish() }
+    }
+
+    /// The pyramid at the start of a recorded year.
+    pub fn pyramid_of<C: Count>(history: &History<C>, year: i32) -> Option<Chart> {
+        let pyramid = history.pyramid(year)?;
+        Some(Chart::pyramid(&format!("Population by age, {}", year), &pyramid))
+    }
+
+    pub fn totals_of<C: Count>(history: &History<C>) -> Chart {
+        let totals: Vec<(i32, C)> = history.years()
+            .filter_map(|year| history.total(year).map(|total| (year, total)))
+            .collect();
+        Chart::totals("Total population", &totals)
+    }
+
+    pub fn flows_of<C: Count>(history: &History<C>) -> Chart {
+        let flows: Vec<(i32, C, C)> = history.years()
+            .filter_map(|year| Some((year, history.births(year)?, history.deaths(year)?)))
+            .collect();
+        Chart::flows("Births and deaths", &flows)
+    }
+
+    pub fn svg(&self) -> &str {
+        &self.svg
+    }
+
+    pub fn png(&self) -> Result<Vec<u8>, ChartError> {
+        let mut options = usvg::Options::default();
+        options.fontdb_mut().load_system_fonts();
+        let tree = usvg::Tree::from_str(&self.svg, &options)
+            .map_err(|e| ChartError::new(format!("Couldn't read the chart: {}", e)))?;
+        let size = tree.size().to_int_size();
+        let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+            .ok_or_else(|| ChartError::new("The chart has no area."))?;
+        resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+        pixmap.encode_png().map_err(|e| ChartError::new(format!("Couldn't encode a PNG: {}", e)))
+    }
+
+    /// Save as SVG or PNG, as the file's extension asks.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ChartError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+        let bytes = match extension.as_deref() {
+            Some("svg") => self.svg.clone().into_bytes(),
+            Some("png") => self.png()?,
+            _ => return Err(ChartError::new(format!(
+                "{} should end in .svg or .png.", path.display()))),
+        };
+        fs::write(path, bytes)
+            .map_err(|e| ChartError::new(format!("Couldn't write {}: {}", path.display(), e)))
+    }
+}
+
+/// A distribution from `age_sex_distribution` as reals, by completed age.
+fn reals<C: Count>(distribution: &[(C, C)]) -> Vec<(f64, f64)> {
+    completed_ages(distribution).iter()
+        .map(|&(males, females)| (males.to_f64(), females.to_f64()))
+        .collect()
+}
+
+fn year_range(points: &[(f64, f64)]) -> (f64, f64) {
+    match (points.first(), points.last()) {
+        (Some(first), Some(last)) if last.0 > first.0 => (first.0, last.0),
+        (Some(first), _) => (first.0, first.0 + 1.0),
+        _ => (0.0, 1.0),
+    }
+}
+
+/// Ages up the middle, and counts out to each side.
+fn pyramid_plot(distribution: &[(f64, f64)]) -> Plot {
+    let most = distribution.iter().fold(0.0f64, |most, &(m, f)| most.max(m).max(f));
+    let most = if most > 0.0 { most * 1.05 } else { 1.0 };
+    Plot { left: 60.0, top: 50.0, width: 700.0, height: 500.0, x: (-most, most),
+           y: (0.0, distribution.len().max(1) as f64) }
+}
+
+fn pyramid_axes(svg: &mut Svg, plot: &Plot) {
+    plot.axes(svg, |count| count_label(count.abs()), |age| format!("{}", age));
+    svg.text(plot.left - 40.0, plot.top - 10.0, "start", 12.0, "Age");
+    svg.line((plot.x(0.0), plot.top), (plot.x(0.0), plot.bottom()), "#999999");
+}
+
+fn line_panel<Y: Fn(f64) -> String>(svg: &mut Svg, plot: &Plot, label: &str,
+                                    series: &[&[(f64, f64)]], y_label: Y)
+{
+    plot.axes(svg, |year| format!("{}", year), y_label);
+    svg.text(plot.left, plot.top - 8.0, "start", 12.0, label);
+    for (index, points) in series.iter().enumerate() {
+        let points: Vec<(f64, f64)> = points.iter().map(|&(x, y)| (plot.x(x), plot.y(y))).collect();
+        svg.polyline(&points, PALETTE[index % PALETTE.len()], 1.5);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+
+    use super::Chart;
+    use super::svg::{count_label, ticks};
+    use population::{completed_ages, Population};
+
+    fn history_run() -> Population {
+        let mut pop = Population::new(vec![ ( 2_000, 2_000 ); 60 ]).with_history();
+        for _ in 0..40 {
+            pop = pop.advance_year();
+        }
+        pop
+    }
+
+    #[test]
+    fn ticks_are_round() {
+        assert_eq!(ticks(0.0, 10.0), vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert_eq!(ticks(1990.0, 2020.0), vec![1990.0, 2000.0, 2010.0, 2020.0]);
+        assert_eq!(ticks(-0.3, 0.3).len(), 3);
+        assert_eq!(count_label(1_500_000.0), "1.5M");
+        assert_eq!(count_label(950.0), "950");
+    }
+
+    #[test]
+    fn charts_come_from_history() {
+        let pop = history_run();
+        let history = pop.history().unwrap();
+        let pyramid = Chart::pyramid_of(history, 40).unwrap();
+        // A bar for each sex at each age, and two for the legend.
+        let bars = pyramid.svg().matches("<rect").count();
+        assert!(bars >= 2 * completed_ages(&pop.age_sex_distribution()).len() + 2);
+        assert!(pyramid.svg().contains("Population by age, 40"));
+        assert_eq!(Chart::pyramid_of(history, 41), None);
+
+        let totals = Chart::totals_of(history);
+        assert_eq!(totals.svg().matches("<polyline").count(), 2);
+        let flows = Chart::flows_of(history);
+        assert!(flows.svg().contains(">Births<") && flows.svg().contains(">Deaths<"));
+
+        let other = Population::new(vec![ ( 3_000, 1_000 ); 30 ]).age_sex_distribution();
+        let compared = Chart::compare_pyramids(
+            "Scenarios", ("Baseline", &pop.age_sex_distribution()), ("Skewed", &other));
+        assert!(compared.svg().contains(">Skewed<"));
+    }
+
+    #[test]
+    fn saves_svg_and_png() {
+        let chart = Chart::totals_of(history_run().history().unwrap());
+        let svg = temp_dir().join("popsim-chart.svg");
+        chart.save(&svg).unwrap();
+        assert_eq!(fs::read_to_string(&svg).unwrap(), chart.svg());
+
+        let png = temp_dir().join("popsim-chart.png");
+        chart.save(&png).unwrap();
+        assert_eq!(&fs::read(&png).unwrap()[1..4], b"PNG");
+        assert!(chart.save(temp_dir().join("popsim-chart.gif")).is_err());
+    }
+}
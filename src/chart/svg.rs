@@ -0,0 +1,165 @@
+//! A small SVG writer, with the axes and scales the charts share.
+
+use std::fmt::Write;
+
+pub const FONT: &str = "DejaVu Sans, Helvetica, Arial, sans-serif";
+pub const MALE: &str = "#4a7fb5";
+pub const FEMALE: &str = "#c45a6a";
+/// Colours for lines, in order.
+pub const PALETTE: [&str; 6] = ["#333333", "#c45a6a", "#4a7fb5", "#5b9a5b", "#d08a2e", "#7d5ba6"];
+
+pub struct Svg {
+    body: String,
+    width: u32,
+    height: u32,
+}
+
+impl Svg {
+    pub fn new(width: u32, height: u32) -> Svg {
+        let mut svg = Svg { body: String::new(), width, height };
+        svg.rect(0.0, 0.0, width as f64, height as f64, "white");
+        svg
+    }
+
+    pub fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, fill: &str) {
+        writeln!(self.body,
+            "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>",
+            x, y, width.max(0.0), height.max(0.0), fill).unwrap();
+    }
+
+    pub fn line(&mut self, from: (f64, f64), to: (f64, f64), stroke: &str) {
+        writeln!(self.body,
+            "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\"/>",
+            from.0, from.1, to.0, to.1, stroke).unwrap();
+    }
+
+    pub fn polyline(&mut self, points: &[(f64, f64)], stroke: &str, width: f64) {
+        let points: Vec<String> =
+            points.iter().map(|&(x, y)| format!("{:.2},{:.2}", x, y)).collect();
+        writeln!(self.body,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>",
+            points.join(" "), stroke, width).unwrap();
+    }
+
+    /// Text anchored at `start`, `middle` or `end`.
+    pub fn text(&mut self, x: f64, y: f64, anchor: &str, size: f64, content: &str) {
+        writeln!(self.body,
+            "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"{}\" font-family=\"{}\" \
+             font-size=\"{}\" fill=\"#333\">{}</text>",
+            x, y, anchor, FONT, size, escape(content)).unwrap();
+    }
+
+    pub fn title(&mut self, title: &str) {
+        let middle = self.width as f64 / 2.0;
+        self.text(middle, 28.0, "middle", 18.0, title);
+    }
+
+    /// A key to the colours in the top right corner.
+    pub fn legend(&mut self, entries: &[(&str, &str)]) {
+        let right = self.width as f64 - 24.0;
+        for (index, &(label, colour)) in entries.iter().enumerate() {
+            let y = 48.0 + 18.0 * index as f64;
+            self.rect(right - 12.0, y - 10.0, 12.0, 12.0, colour);
+            self.text(right - 18.0, y, "end", 12.0, label);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                 viewBox=\"0 0 {} {}\">\n{}</svg>\n",
+            self.width, self.height, self.width, self.height, self.body)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// A rectangle of the chart, and the ranges of values it shows.
+#[derive(Debug, Clone, Copy)]
+pub struct Plot {
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+    pub x: (f64, f64),
+    pub y: (f64, f64),
+}
+
+impl Plot {
+    pub fn x(&self, value: f64) -> f64 {
+        self.left + (value - self.x.0) / span(self.x) * self.width
+    }
+
+    pub fn y(&self, value: f64) -> f64 {
+        self.top + self.height - (value - self.y.0) / span(self.y) * self.height
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.top + self.height
+    }
+
+    /// Axes along the left and bottom, with ticks labelled by `x_label` and `y_label`.
+    pub fn axes<X, Y>(&self, svg: &mut Svg, x_label: X, y_label: Y)
+        where X: Fn(f64) -> String, Y: Fn(f64) -> String
+    {
+        for tick in ticks(self.y.0, self.y.1) {
+            let y = self.y(tick);
+            svg.line((self.left, y), (self.left + self.width, y), "#eeeeee");
+            svg.text(self.left - 6.0, y + 4.0, "end", 11.0, &y_label(tick));
+        }
+        for tick in ticks(self.x.0, self.x.1) {
+            let x = self.x(tick);
+            svg.line((x, self.bottom()), (x, self.bottom() + 4.0), "#999999");
+            svg.text(x, self.bottom() + 18.0, "middle", 11.0, &x_label(tick));
+        }
+        svg.line((self.left, self.top), (self.left, self.bottom()), "#999999");
+        svg.line((self.left, self.bottom()), (self.left + self.width, self.bottom()), "#999999");
+    }
+}
+
+fn span(range: (f64, f64)) -> f64 {
+    if range.1 > range.0 { range.1 - range.0 } else { 1.0 }
+}
+
+/// Round tick values from `low` to `high`: multiples of 1, 2 or 5 times a power of ten.
+pub fn ticks(low: f64, high: f64) -> Vec<f64> {
+    if !low.is_finite() || !high.is_finite() || high <= low { return vec![low]; }
+    let rough = (high - low) / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|multiple| multiple * magnitude)
+        .find(|&step| step >= rough)
+        .unwrap_or(10.0 * magnitude);
+    let first = (low / step).ceil() as i64;
+    let last = (high / step + 1e-9).floor() as i64;
+    (first..=last).map(|index| index as f64 * step).collect()
+}
+
+/// A range from zero, or from the data's minimum if it's negative, to a little past the maximum.
+pub fn extent<I: IntoIterator<Item = f64>>(values: I) -> (f64, f64) {
+    let (low, high) = values.into_iter()
+        .filter(|value| value.is_finite())
+        .fold((0.0f64, 0.0f64), |(low, high), value| (low.min(value), high.max(value)));
+    let pad = (high - low).max(1e-9) * 0.05;
+    (if low < 0.0 { low - pad } else { 0.0 }, high + pad)
+}
+
+/// A count, shortened with a suffix: 1500000 is 1.5M.
+pub fn count_label(value: f64) -> String {
+    let size = value.abs();
+    let (scaled, suffix) = if size >= 1e12 {
+        (value / 1e12, "T")
+    } else if size >= 1e9 {
+        (value / 1e9, "B")
+    } else if size >= 1e6 {
+        (value / 1e6, "M")
+    } else if size >= 1e3 {
+        (value / 1e3, "k")
+    } else {
+        (value, "")
+    };
+    let text = format!("{:.2}", scaled);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", text, suffix)
+}
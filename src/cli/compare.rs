@@ -0,0 +1,141 @@
+//! `PopSim compare`: compare two runs year by year.
+
+use std::fs;
+use std::path::Path;
+
+use pop_sim::compare::{Comparison, DEFAULT_TOLERANCE};
+use pop_sim::population::Population;
+use pop_sim::population::checkpoint::Checkpoint;
+use pop_sim::population::indicators::AgeBand;
+use pop_sim::scenario::Scenario;
+
+use super::{number, real, unknown, Args, Failure};
+
+pub const USAGE: &str = "\
+Usage: PopSim compare A B [--years N] [--tolerance X] [--bands LIST] [--every N]
+                      [--format table|csv|components] [--output FILE]
+
+Compares two runs, A and B, over the years they both completed: the totals, births, deaths and
+net migration, the indicators, life expectancies (from the recorded deaths), and the count in
+each band of ages, with the difference B - A and the difference relative to A. Says when the runs
+first diverge beyond the tolerance, and splits the difference in the total into the difference
+they started with and the differences in births, deaths and net migration since.
+
+A and B are each a scenario, which is run with its history recorded, or a checkpoint saved by a
+run that recorded one (see PopSim run --history).
+
+  --years N             Years to run scenarios (default each scenario's)
+  --tolerance X         The relative difference that counts as diverging (default 0.01)
+  --bands LIST          Bands of ages to count, separated by commas, as in 0-14,15-64,65+
+                        (default five-year groups up to 85+)
+  --every N             Years between rows of the table (default 10); the last year is always
+                        shown
+  --format FMT          table (the default); csv, for a row for each measure in each year; or
+                        components, for the components of the difference in each year, as CSV
+  --output FILE         Write the comparison to FILE instead";
+
+#[derive(PartialEq)]
+enum Format { Table, Csv, Components }
+
+struct Options {
+    runs: Vec<String>,
+    years: Option<usize>,
+    tolerance: f64,
+    bands: Option<Vec<AgeBand>>,
+    every: usize,
+    format: Format,
+    output: Option<String>,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let mut options = Options {
+        runs: Vec::new(),
+        years: None,
+        tolerance: DEFAULT_TOLERANCE,
+        bands: None,
+        every: 10,
+        format: Format::Table,
+        output: None,
+    };
+    while let Some(flag) = args.next()? {
+        match flag.as_str() {
+            "--years" => options.years = Some(number(&args.value(&flag)?)?),
+            "--tolerance" => options.tolerance = real(&args.value(&flag)?)?,
+            "--bands" => options.bands = Some(args.value(&flag)?.split(',')
+                .map(|band| band.parse().map_err(Failure::Usage))
+                .collect::<Result<Vec<AgeBand>, Failure>>()?),
+            "--every" => options.every = number(&args.value(&flag)?)?,
+            "--format" => options.format = match args.value(&flag)?.as_str() {
+                "table" => Format::Table,
+                "csv" => Format::Csv,
+                "components" => Format::Components,
+                other => return Err(Failure::usage(format!("Unknown format {}.", other))),
+            },
+            "--output" => options.output = Some(args.value(&flag)?),
+            _ if !flag.starts_with('-') && options.runs.len() < 2 => options.runs.push(flag),
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    if options.runs.len() != 2 {
+        return Err(Failure::usage("compare needs two runs, each a scenario or a checkpoint."));
+    }
+    if options.tolerance.is_nan() || options.tolerance < 0.0 {
+        return Err(Failure::usage("--tolerance can't be negative."));
+    }
+    if options.every == 0 {
+        return Err(Failure::usage("--every needs to be at least one."));
+    }
+    Ok(options)
+}
+
+/// The population a checkpoint holds, or a scenario's after running it with its history.
+fn run(path: &str, years: Option<usize>) -> Result<Population, Failure> {
+    if !Path::new(path).is_file() {
+        return Err(Failure::Failed(format!("There's no file called {}.", path)));
+    }
+    let checkpoint = match Checkpoint::load(path) {
+        Ok(checkpoint) => checkpoint,
+        Err(checkpoint_error) => {
+            let scenario = Scenario::load(path).map_err(|scenario_error| Failure::Failed(format!(
+                "{} isn't a checkpoint ({}) or a scenario ({}).", path, checkpoint_error,
+                scenario_error)))?;
+            let pop = scenario.population().with_history();
+            let years = years.unwrap_or(scenario.years);
+            return Ok((0..years).fold(pop, |pop, _| pop.advance_year()));
+        },
+    };
+    if checkpoint.population.history().is_none() {
+        return Err(Failure::Failed(format!(
+            "{} has no history to compare; save it from a run with --history.", path)));
+    }
+    Ok(checkpoint.population)
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let a = run(&options.runs[0], options.years)?;
+    let b = run(&options.runs[1], options.years)?;
+    let mut comparison = Comparison::default().with_tolerance(options.tolerance);
+    if let Some(ref bands) = options.bands {
+        comparison = comparison.with_bands(bands.clone());
+    }
+    let (a, b) = (a.history().unwrap(), b.history().unwrap());
+    let result = comparison.histories(a, b).map_err(Failure::failed)?;
+
+    let written = match options.format {
+        Format::Table => format!("Comparing {} (a) with {} (b).\n{}", options.runs[0],
+                                 options.runs[1], result.to_table(options.every)),
+        Format::Csv => result.to_csv(),
+        Format::Components => result.components_to_csv(),
+    };
+    match options.output {
+        Some(ref path) => {
+            fs::write(path, written).map_err(|e| {
+                Failure::Failed(format!("Couldn't write {}: {}", path, e))
+            })?;
+            println!("Wrote the comparison of {} years to {}", result.years.len(), path);
+        },
+        None => print!("{}", written),
+    }
+    Ok(())
+}
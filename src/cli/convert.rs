@@ -0,0 +1,57 @@
+//! `PopSim convert`: rewrite a checkpoint in another format.
+
+use std::fs;
+
+use pop_sim::population::checkpoint::{Checkpoint, Format};
+
+use super::{unknown, Args, Failure};
+
+pub const USAGE: &str = "\
+Usage: PopSim convert INPUT OUTPUT [--from FMT] [--to FMT]
+
+Reads a checkpoint and writes it again, in the current version of the format OUTPUT's extension
+asks for: .yml or .yaml, .json, or anything else for binary. Older checkpoints are brought up to
+date on the way.
+
+  --from FMT            Read INPUT as yaml, json or binary, whatever its extension
+  --to FMT              Write OUTPUT as yaml, json or binary, whatever its extension";
+
+fn format(value: &str) -> Result<Format, Failure> {
+    match value {
+        "yaml" | "yml" => Ok(Format::Yaml),
+        "json" => Ok(Format::Json),
+        "binary" => Ok(Format::Binary),
+        _ => Err(Failure::usage(format!(
+            "Checkpoints can be yaml, json or binary, not {}.", value))),
+    }
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let mut args = Args::new(args);
+    let (mut paths, mut from, mut to) = (Vec::new(), None, None);
+    while let Some(arg) = args.next()? {
+        match arg.as_str() {
+            "--from" => from = Some(format(&args.value(&arg)?)?),
+            "--to" => to = Some(format(&args.value(&arg)?)?),
+            _ if !arg.starts_with('-') && paths.len() < 2 => paths.push(arg),
+            _ => return Err(unknown(&arg)),
+        }
+    }
+    if paths.len() != 2 {
+        return Err(Failure::usage("convert needs an INPUT and an OUTPUT checkpoint."));
+    }
+    let (input, output) = (&paths[0], &paths[1]);
+
+    let bytes = fs::read(input).map_err(|e| {
+        Failure::Failed(format!("Couldn't open {}: {}", input, e))
+    })?;
+    let from = from.unwrap_or_else(|| Format::of_path(input));
+    let to = to.unwrap_or_else(|| Format::of_path(output));
+    let checkpoint: Checkpoint = Checkpoint::from_bytes(&bytes, from).map_err(Failure::failed)?;
+    let converted = checkpoint.to_bytes(to).map_err(Failure::failed)?;
+    fs::write(output, converted).map_err(|e| {
+        Failure::Failed(format!("Couldn't write {}: {}", output, e))
+    })?;
+    println!("Converted {} (year {}) to {}", input, checkpoint.population.current_year(), output);
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! `PopSim fit`: fit Heligman-Pollard or fertility parameters to a table of rates by age.
+
+use std::fs;
+
+use pop_sim::population::cohort::fertility::Fertility;
+use pop_sim::population::cohort::heligman_pollard::{HPMortalityModel, PARAMETERS};
+
+use super::{unknown, Args, Failure};
+
+pub const USAGE: &str = "\
+Usage: PopSim fit mortality FILE [--start male|female] [--output FILE]
+       PopSim fit fertility FILE [--output FILE]
+
+Fits parameters to a CSV table by single year of age, and prints them as YAML, with the names a
+scenario gives them: a fragment of a scenario for fertility, and the parameters to go under its
+male_mortality or female_mortality for mortality.
+
+mortality fits the eight Heligman-Pollard parameters to an age column and either the probability
+of dying, q, or deaths and exposure, from which the central death rate m gives q = m / (1 + m/2).
+fertility fits the TFR, the ages the fertility curve starts and ends at, and its skew to an age
+column and either the birth rate, rate, or births and women.
+
+  --start SEX           Start the mortality search from the baseline male (the default) or
+                        female parameters
+  --output FILE         Write the parameters to FILE instead";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Mortality,
+    Fertility,
+}
+
+struct Options {
+    kind: Kind,
+    input: String,
+    female: bool,
+    output: Option<String>,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let kind = match args.next()?.as_deref() {
+        Some("mortality") => Kind::Mortality,
+        Some("fertility") => Kind::Fertility,
+        Some(kind) => return Err(Failure::usage(format!(
+            "fit can fit mortality or fertility, not {}.", kind))),
+        None => return Err(Failure::usage("fit needs mortality or fertility, and a file.")),
+    };
+    let mut options = Options { kind, input: String::new(), female: false, output: None };
+    while let Some(flag) = args.next()? {
+        match flag.as_str() {
+            "--start" if kind == Kind::Mortality => options.female =
+                match args.value(&flag)?.as_str() {
+                    "male" => false,
+                    "female" => true,
+                    sex => return Err(Failure::usage(format!(
+                        "The search can start from male or female, not {}.", sex))),
+                },
+            "--output" => options.output = Some(args.value(&flag)?),
+            _ if !flag.starts_with('-') && options.input.is_empty() => options.input = flag,
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    if options.input.is_empty() {
+        return Err(Failure::usage("fit needs a file of rates by age."));
+    }
+    Ok(options)
+}
+
+/// The rate at each age in a CSV table, from a column called one of `rates`, or the ratio of
+/// the columns called one of `numerators` and one of `denominators`, and whether it was the
+/// ratio.
+fn rates(csv: &str, rates: &[&str], numerators: &[&str], denominators: &[&str])
+    -> Result<(Vec<(usize, f64)>, bool), String>
+{
+    let mut lines = csv.lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty());
+    let header: Vec<String> = match lines.next() {
+        Some((_, line)) => line.split(',').map(|h| h.trim().to_lowercase()).collect(),
+        None => return Err(String::from("The table is empty.")),
+    };
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let age = column(&["age"]).ok_or("The table has no age column.")?;
+    let (top, bottom) = match (column(rates), column(numerators), column(denominators)) {
+        (Some(rate), _, _) => (rate, None),
+        (None, Some(numerator), Some(denominator)) => (numerator, Some(denominator)),
+        _ => return Err(format!("The table needs a {} column, or {} and {} columns.",
+                                rates[0], numerators[0], denominators[0])),
+    };
+
+    let mut table = Vec::new();
+    for (index, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        let field = |column: usize| fields.get(column).cloned().unwrap_or("");
+        let number = |column: usize| field(column).parse::<f64>().ok()
+            .filter(|number| *number >= 0.0)
+            .ok_or_else(|| format!("Line {}: '{}' isn't a number.", index + 1, field(column)));
+        let at = field(age).parse::<usize>().map_err(|_| {
+            format!("Line {}: '{}' isn't a single year of age.", index + 1, field(age))
+        })?;
+        let rate = match bottom {
+            None => number(top)?,
+            Some(bottom) if number(bottom)? > 0.0 => number(top)? / number(bottom)?,
+            // No one to have a rate.
+            Some(_) => continue,
+        };
+        table.push((at, rate));
+    }
+    Ok((table, bottom.is_some()))
+}
+
+fn mortality(csv: &str, female: bool) -> Result<String, Failure> {
+    let (table, central) = rates(csv, &["q", "qx"], &["deaths"], &["exposure", "population"])
+        .map_err(Failure::Failed)?;
+    let observed: Vec<(i32, f64)> = table.iter()
+        .map(|&(age, rate)| (age as i32, if central { rate / (1.0 + rate / 2.0) } else { rate }))
+        .collect();
+    let start = if female { HPMortalityModel::baseline_female() }
+        else { HPMortalityModel::baseline_male() };
+    let (model, measure) = HPMortalityModel::fit(&observed, &start).map_err(Failure::failed)?;
+
+    let ages = observed.iter().filter(|&&(_, q)| q > 0.0).count();
+    let mut yaml = format!(
+        "# Heligman-Pollard fit to {} ages, with a sum of (1 - fitted/q)² of {}\n", ages, measure);
+    for (name, value) in PARAMETERS.iter().zip(model.parameters().iter()) {
+        yaml.push_str(&format!("{}: {}\n", name, value));
+    }
+    Ok(yaml)
+}
+
+fn fertility(csv: &str) -> Result<String, Failure> {
+    let (table, _) = rates(csv, &["rate", "asfr"], &["births"], &["women", "exposure"])
+        .map_err(Failure::Failed)?;
+    let (fertility, tfr, skew, difference) = Fertility::fit(&table)
+        .ok_or_else(|| Failure::Failed(String::from("There are no births to fit.")))?;
+    Ok(format!("# Fertility fit to {} ages, with a squared difference of {}\n\
+                total_fertility_rate: {}\nfertility_ages: [{}, {}]\nfertility_skew: {}\n",
+               table.len(), difference, tfr, fertility.min_age(), fertility.max_age(), skew))
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let csv = fs::read_to_string(&options.input).map_err(|e| {
+        Failure::Failed(format!("Couldn't open {}: {}", options.input, e))
+    })?;
+    let yaml = match options.kind {
+        Kind::Mortality => mortality(&csv, options.female)?,
+        Kind::Fertility => fertility(&csv)?,
+    };
+    match options.output {
+        Some(ref path) => fs::write(path, yaml).map_err(|e| {
+            Failure::Failed(format!("Couldn't write {}: {}", path, e))
+        }),
+        None => { print!("{}", yaml); Ok(()) },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use pop_sim::population::cohort::fertility::Fertility;
+    use pop_sim::scenario::Scenario;
+
+    use super::fertility;
+
+    #[test]
+    fn fertility_fits_read_as_a_scenario() {
+        let curve = Fertility::skewed(18, 45, -2.0);
+        let mut csv = String::from("age,rate\n");
+        for age in 10..55 {
+            csv.push_str(&format!("{},{}\n", age, curve.birth_rate(age, 1.8)));
+        }
+        let yaml = format!("initial: [[1000, 1000]]\n{}", fertility(&csv).unwrap());
+        let scenario = Scenario::from_yaml(&yaml).unwrap();
+        assert_eq!(scenario.fertility_ages, Some((18, 45)));
+        assert!((scenario.fertility_skew.unwrap() + 2.0).abs() < 1e-3);
+        assert!((scenario.total_fertility_rate - 1.8).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,94 @@
+//! `PopSim indicators`: run a population and write its demographic indicators for each year.
+
+use std::io::{self, BufWriter};
+
+use pop_sim::export::{Column, Columns, Exporter, ExportError, Format};
+use pop_sim::population::indicators::AgeBand;
+
+use super::{number, unknown, Args, Failure, Start};
+
+pub const USAGE: &str = "\
+Usage: PopSim indicators [--scenario FILE | --resume FILE] [--years N] [--bands LIST]
+                         [--output FILE]
+
+Runs a population and writes its indicators for each year it completes: the total, crude birth,
+death and growth rates, mean and median ages, dependency ratios, sex ratios, life expectancies
+and realized TFR.
+
+  --scenario FILE       The scenario to run, as YAML. Without one or --resume, the default
+                        population
+  --resume FILE         Continue from a checkpoint instead
+  --years N             Years to run (default the scenario's, or 100)
+  --bands LIST          Also the share of the population in each band of ages, separated by
+                        commas, as in 15-49,65+
+  --output FILE         Write to FILE, .csv, .jsonl or .parquet, instead of CSV to the terminal";
+
+const INDICATORS: [Column; 15] = [
+    Column::Year,
+    Column::Total,
+    Column::CrudeBirthRate,
+    Column::CrudeDeathRate,
+    Column::CrudeGrowthRate,
+    Column::MeanAge,
+    Column::MedianAge,
+    Column::YouthDependency,
+    Column::OldAgeDependency,
+    Column::TotalDependency,
+    Column::SexRatio,
+    Column::SexRatios,
+    Column::MaleLifeExpectancy,
+    Column::FemaleLifeExpectancy,
+    Column::RealizedTfr,
+];
+
+struct Options {
+    start: Start,
+    years: Option<usize>,
+    bands: Vec<AgeBand>,
+    output: Option<String>,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let mut options = Options {
+        start: Start::default(),
+        years: None,
+        bands: Vec::new(),
+        output: None,
+    };
+    while let Some(flag) = args.next()? {
+        if options.start.flag(&flag, &mut args)? { continue; }
+        match flag.as_str() {
+            "--years" => options.years = Some(number(&args.value(&flag)?)?),
+            "--bands" => options.bands = args.value(&flag)?.split(',')
+                .map(|band| band.parse().map_err(Failure::Usage))
+                .collect::<Result<Vec<AgeBand>, Failure>>()?,
+            "--output" => options.output = Some(args.value(&flag)?),
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    Ok(options)
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let (mut pop, scenario_years) = options.start.population()?;
+    let years = options.years.or(scenario_years).unwrap_or(100);
+
+    let mut columns = INDICATORS.to_vec();
+    columns.extend(options.bands.iter().map(|&band| Column::Share(band)));
+    let columns = Columns(columns);
+    let exporter: Result<Exporter<usize>, ExportError> = match options.output {
+        Some(ref path) => Exporter::create(path, columns),
+        None => Exporter::new(BufWriter::new(io::stdout()), Format::Csv, columns),
+    };
+    let export = exporter.map_err(Failure::failed)?.observer();
+    pop = pop.with_observer(export.clone());
+    for _ in 0..years {
+        pop = pop.advance_year();
+    }
+    let rows = export.finish().map_err(Failure::failed)?;
+    if let Some(ref path) = options.output {
+        println!("Wrote {} years to {}", rows, path);
+    }
+    Ok(())
+}
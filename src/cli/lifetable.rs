@@ -0,0 +1,135 @@
+//! `PopSim lifetable`: print the life table for a set of Heligman-Pollard parameters.
+
+use pop_sim::population::cohort::heligman_pollard::{HPMortalityModel, PARAMETERS};
+use pop_sim::population::life_table::LifeTable;
+use pop_sim::population::schedule::Schedule;
+use serde_json;
+
+use super::{number, real, unknown, Args, Failure};
+
+pub const USAGE: &str = "\
+Usage: PopSim lifetable [--sex male|female] [--parameters A,B,C,D,E,F,G,H] [--set NAME=VALUE]...
+                        [--terminal-age N] [--max-age N] [--format table|csv|json]
+
+Prints the period life table for a Heligman-Pollard mortality schedule.
+
+  --sex SEX             Start from the baseline male (the default) or female parameters
+  --parameters LIST     All eight parameters, A to H, separated by commas
+  --set NAME=VALUE      One parameter by name: infant_mortality, first_year_mortality,
+                        infant_mortality_dropoff, accident_severity, accident_spread,
+                        accident_midpoint, adult_mortality or adult_mortality_increase
+  --terminal-age N      Close the table with an open age group from N
+  --max-age N           No one lives past N
+  --format FMT          table (the default), csv or json";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Table,
+    Csv,
+    Json,
+}
+
+struct Options {
+    female: bool,
+    parameters: Option<[f32; 8]>,
+    set: Vec<(String, f32)>,
+    terminal_age: Option<i32>,
+    max_age: Option<i32>,
+    output: Output,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let mut options = Options {
+        female: false,
+        parameters: None,
+        set: Vec::new(),
+        terminal_age: None,
+        max_age: None,
+        output: Output::Table,
+    };
+    while let Some(flag) = args.next()? {
+        match flag.as_str() {
+            "--sex" => options.female = match args.value(&flag)?.as_str() {
+                "male" => false,
+                "female" => true,
+                sex => return Err(Failure::usage(format!(
+                    "The sex can be male or female, not {}.", sex))),
+            },
+            "--parameters" => {
+                let values = args.value(&flag)?.split(',')
+                    .map(|value| real(value.trim()).map(|value| value as f32))
+                    .collect::<Result<Vec<f32>, Failure>>()?;
+                if values.len() != PARAMETERS.len() {
+                    return Err(Failure::usage(format!(
+                        "--parameters needs all {} parameters, not {}.",
+                        PARAMETERS.len(), values.len())));
+                }
+                let mut parameters = [0.0; 8];
+                parameters.copy_from_slice(&values);
+                options.parameters = Some(parameters);
+            },
+            "--set" => {
+                let value = args.value(&flag)?;
+                let mut parts = value.splitn(2, '=');
+                let name = parts.next().unwrap_or("").trim().to_string();
+                let number = parts.next().ok_or_else(|| Failure::usage(format!(
+                    "--set takes a NAME=VALUE, not {}.", value)))?;
+                options.set.push((name, real(number.trim())? as f32));
+            },
+            "--terminal-age" => options.terminal_age = Some(number(&args.value(&flag)?)? as i32),
+            "--max-age" => options.max_age = Some(number(&args.value(&flag)?)? as i32),
+            "--format" => options.output = match args.value(&flag)?.as_str() {
+                "table" => Output::Table,
+                "csv" => Output::Csv,
+                "json" => Output::Json,
+                format => return Err(Failure::usage(format!(
+                    "Life tables can be printed as table, csv or json, not {}.", format))),
+            },
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    Ok(options)
+}
+
+/// The table with its columns lined up, for reading.
+fn table(life_table: &LifeTable) -> String {
+    let mut lines = vec![format!("{:>5} {:>10} {:>10} {:>10} {:>10} {:>12} {:>7}",
+                                 "age", "q", "l", "d", "L", "T", "e")];
+    for (index, row) in life_table.rows.iter().enumerate() {
+        let open = life_table.open && index == life_table.rows.len() - 1;
+        let age = format!("{}{}", row.age, if open { "+" } else { "" });
+        lines.push(format!("{:>5} {:>10.6} {:>10.0} {:>10.0} {:>10.0} {:>12.0} {:>7.2}",
+                           age, row.q, row.l, row.d, row.person_years, row.remaining_years,
+                           row.e));
+    }
+    lines.join("\n")
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let baseline = if options.female { HPMortalityModel::baseline_female() }
+        else { HPMortalityModel::baseline_male() };
+    let mut model = match options.parameters {
+        Some(parameters) => HPMortalityModel::from_parameters(parameters)
+            .map_err(|e| Failure::usage(e.to_string()))?,
+        None => baseline,
+    };
+    for &(ref name, value) in options.set.iter() {
+        model = model.with_parameter(name, value).map_err(|e| Failure::usage(e.to_string()))?;
+    }
+
+    // Both sexes get the model; only the one asked for is tabled.
+    let mut schedule = Schedule::new(Schedule::default().fertility, model.clone(), model);
+    if let Some(age) = options.terminal_age { schedule = schedule.with_terminal_age(age); }
+    if let Some(age) = options.max_age { schedule = schedule.with_max_age(age); }
+    let life_table = if options.female { LifeTable::female(&schedule) }
+        else { LifeTable::male(&schedule) };
+
+    match options.output {
+        Output::Table => println!("{}", table(&life_table)),
+        Output::Csv => print!("{}", life_table.to_csv()),
+        Output::Json => println!("{}", serde_json::to_string_pretty(&life_table)
+            .map_err(Failure::failed)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! The subcommands of the PopSim binary. Each has a `main` that takes the arguments after its
+//! name, and a `USAGE` describing them.
+
+use std::fmt::Display;
+
+use pop_sim::population::Population;
+use pop_sim::scenario::Scenario;
+
+pub mod compare;
+pub mod convert;
+pub mod fit;
+pub mod indicators;
+pub mod lifetable;
+pub mod report;
+pub mod run;
+pub mod sensitivity;
+pub mod sweep;
+pub mod validate;
+
+/// Why a subcommand stopped, and so how the binary exits.
+#[derive(Debug)]
+pub enum Failure {
+    /// `--help` was asked for: print the usage and succeed.
+    Help,
+    /// The arguments were wrong: print the problem and the usage, and exit with 2.
+    Usage(String),
+    /// Something went wrong while running: print the problem, and exit with 1.
+    Failed(String),
+}
+
+impl Failure {
+    pub fn usage<S: Into<String>>(problem: S) -> Failure {
+        Failure::Usage(problem.into())
+    }
+
+    /// For an error from the library, while running.
+    pub fn failed<E: Display>(e: E) -> Failure {
+        Failure::Failed(e.to_string())
+    }
+}
+
+/// The arguments to a subcommand, flag by flag.
+pub struct Args<I> {
+    args: I,
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    pub fn new(args: I) -> Args<I> {
+        Args { args }
+    }
+
+    /// The next flag or argument, treating `-h` and `--help` as asking for help.
+    pub fn next(&mut self) -> Result<Option<String>, Failure> {
+        match self.args.next() {
+            Some(ref flag) if flag == "-h" || flag == "--help" => Err(Failure::Help),
+            next => Ok(next),
+        }
+    }
+
+    /// The value following `flag`.
+    pub fn value(&mut self, flag: &str) -> Result<String, Failure> {
+        self.args.next().ok_or_else(|| Failure::usage(format!("{} needs a value.", flag)))
+    }
+}
+
+pub fn unknown(flag: &str) -> Failure {
+    if flag.starts_with('-') {
+        Failure::usage(format!("Unknown option {}.", flag))
+    } else {
+        Failure::usage(format!("Unexpected argument {}.", flag))
+    }
+}
+
+pub fn number(value: &str) -> Result<usize, Failure> {
+    value.parse().map_err(|_| Failure::usage(format!("{} isn't a whole number.", value)))
+}
+
+pub fn real(value: &str) -> Result<f64, Failure> {
+    value.parse().map_err(|_| Failure::usage(format!("{} isn't a number.", value)))
+}
+
+pub fn years(value: &str) -> Result<Vec<i32>, Failure> {
+    value.split(',').map(|year| {
+        year.trim().parse().map_err(|_| Failure::usage(format!("{} isn't a year.", year)))
+    }).collect()
+}
+
+/// Where a run starts: a scenario, a checkpoint, or the default population.
+#[derive(Default)]
+pub struct Start {
+    pub scenario: Option<String>,
+    pub resume: Option<String>,
+}
+
+impl Start {
+    /// Read `--scenario` or `--resume`, if that's the flag, and say whether it was.
+    pub fn flag<I: Iterator<Item = String>>(&mut self, flag: &str, args: &mut Args<I>)
+        -> Result<bool, Failure>
+    {
+        match flag {
+            "--scenario" => self.scenario = Some(args.value(flag)?),
+            "--resume" => self.resume = Some(args.value(flag)?),
+            _ => return Ok(false),
+        }
+        if self.scenario.is_some() && self.resume.is_some() {
+            return Err(Failure::usage("A run can start from a --scenario or --resume, not both."));
+        }
+        Ok(true)
+    }
+
+    /// The population to start from, and the years the scenario asks for if there's one.
+    pub fn population(&self) -> Result<(Population, Option<usize>), Failure> {
+        if let Some(ref path) = self.scenario {
+            let scenario = Scenario::load(path).map_err(Failure::failed)?;
+            return Ok((scenario.population(), Some(scenario.years)));
+        }
+        if let Some(ref path) = self.resume {
+            return Ok((Population::resume(path).map_err(Failure::failed)?, None));
+        }
+        Ok((Population::new(vec![ ( 100000, 100000 ) ]), None))
+    }
+}
@@ -0,0 +1,70 @@
+//! `PopSim report`: run a scenario and write a page about it.
+
+use pop_sim::report::{Report, DEFAULT_MILESTONES};
+
+use super::{number, unknown, years, Args, Failure, Start};
+
+pub const USAGE: &str = "\
+Usage: PopSim report [--scenario FILE | --resume FILE] [--years N] [--milestones YEARS]
+                     [--title TEXT] [--output FILE]
+
+Runs a scenario and writes a single HTML page about it, with no external resources.
+
+  --scenario FILE       The scenario to run, as YAML. Without one or --resume, the default
+                        population
+  --resume FILE         Continue from a checkpoint instead
+  --years N             Years to run (default the scenario's, or 100)
+  --milestones YEARS    The years to show indicators and pyramids for, separated by commas
+                        (default five years spread over the run)
+  --title TEXT          The page's title (default PopSim report)
+  --output FILE         Where to write the page (default report.html)";
+
+struct Options {
+    start: Start,
+    years: Option<usize>,
+    milestones: Option<Vec<i32>>,
+    title: String,
+    output: String,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let mut options = Options {
+        start: Start::default(),
+        years: None,
+        milestones: None,
+        title: String::from("PopSim report"),
+        output: String::from("report.html"),
+    };
+    while let Some(flag) = args.next()? {
+        if options.start.flag(&flag, &mut args)? { continue; }
+        match flag.as_str() {
+            "--years" => options.years = Some(number(&args.value(&flag)?)?),
+            "--milestones" => options.milestones = Some(years(&args.value(&flag)?)?),
+            "--title" => options.title = args.value(&flag)?,
+            "--output" => options.output = args.value(&flag)?,
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    if options.years == Some(0) {
+        return Err(Failure::usage("A report needs at least one year."));
+    }
+    Ok(options)
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let (mut pop, scenario_years) = options.start.population()?;
+    let years = options.years.or(scenario_years).unwrap_or(100);
+    let first = pop.current_year();
+    let milestones = options.milestones.clone().unwrap_or_else(|| {
+        Report::spread(first, first + years as i32 - 1, DEFAULT_MILESTONES)
+    });
+    let report = Report::new(options.title.as_str()).with_milestones(milestones).observer();
+    pop = pop.with_observer(report.clone());
+    for _ in 0..years {
+        pop = pop.advance_year();
+    }
+    report.save(&options.output, &pop).map_err(Failure::failed)?;
+    println!("Wrote {}", options.output);
+    Ok(())
+}
@@ -0,0 +1,286 @@
+//! `PopSim run`: run a population, printing its total each year, with checkpoints, exports and
+//! charts along the way. It's also what PopSim does with no subcommand.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use pop_sim::chart::{Chart, ChartError};
+use pop_sim::export::{Columns, Exporter, ExportError, Format};
+use pop_sim::population::Population;
+use pop_sim::population::observer::ExtinctionLogger;
+use pop_sim::tui;
+
+use super::{number, unknown, years, Args, Failure, Start};
+
+pub const USAGE: &str = "\
+Usage: PopSim [run] [--scenario FILE | --resume FILE] [--years N] [--checkpoint FILE]
+                    [--checkpoint-every N] [--history] [--export FILE [--format FMT]
+                    [--columns LIST]]
+                    [--charts DIR [--chart-format svg|png] [--pyramids YEARS]] [--tui | --gui]
+
+  --scenario FILE       Start from a scenario, as YAML, instead of the default population
+  --resume FILE         Continue from a checkpoint instead
+  --years N             Years to run (default the scenario's, or 1000)
+  --checkpoint FILE     Save a checkpoint at the end of the run, and at --checkpoint-every years.
+                        A {year} in FILE is replaced by the year. The format follows the
+                        extension: .yml or .yaml, .json, or anything else for binary.
+  --checkpoint-every N  Also save a checkpoint every N years
+  --history             Record the run's history, so checkpoints carry it for PopSim compare
+  --export FILE         Write a row for each year to FILE: .csv, .jsonl, or .parquet if PopSim
+                        was built with the parquet feature
+  --format FMT          The export's format, csv, jsonl or parquet, whatever FILE ends in
+  --columns LIST        The columns to export, separated by commas (default
+                        year,total,males,females,births,deaths). Also male_births,
+                        female_births, male_deaths, female_deaths, immigrants, emigrants, and
+                        ages or sex_ages for counts by age, optionally with :WIDTH and :LAST
+                        for groups of WIDTH years and an open group from LAST, as in ages:5:85.
+                        Indicators: crude_birth_rate, crude_death_rate, crude_growth_rate,
+                        mean_age, median_age, youth_dependency, old_age_dependency,
+                        total_dependency, sex_ratio, sex_ratios (in five-year groups),
+                        male_life_expectancy, female_life_expectancy, realized_tfr, and share:
+                        with a band of ages, as in share:15-49 or share:65+
+  --charts DIR          Record the run's history, and draw charts of it into DIR at the end: the
+                        total population and growth rate, births and deaths, and age pyramids.
+                        With --resume and --years 0, draws the history a checkpoint recorded.
+  --chart-format FMT    svg (the default) or png
+  --pyramids YEARS      The years to draw pyramids for, separated by commas (default the first
+                        and last recorded years)
+  --tui                 Show a dashboard in the terminal to watch and steer the run, instead of
+                        printing totals. --years is ignored; the run goes on until it's quit,
+                        and then saves the --checkpoint, export and charts. Can't be used with
+                        --checkpoint-every.
+  --gui                 Open a window to watch and steer the run, instead of printing totals.
+                        Needs PopSim built with the gui feature, as it is by default. The window
+                        keeps the run, which goes on until it's closed, so only the export is
+                        written; can't be used with --tui, --years, --checkpoint, --history or
+                        --charts.";
+
+struct Options {
+    start: Start,
+    years: Option<usize>,
+    checkpoint: Option<String>,
+    checkpoint_every: Option<usize>,
+    history: bool,
+    export: Option<String>,
+    format: Option<Format>,
+    columns: Option<Columns>,
+    charts: Option<String>,
+    chart_format: String,
+    pyramids: Option<Vec<i32>>,
+    tui: bool,
+    #[cfg(feature = "gui")]
+    gui: bool,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let mut options = Options {
+        start: Start::default(),
+        years: None,
+        checkpoint: None,
+        checkpoint_every: None,
+        history: false,
+        export: None,
+        format: None,
+        columns: None,
+        charts: None,
+        chart_format: String::from("svg"),
+        pyramids: None,
+        tui: false,
+        #[cfg(feature = "gui")]
+        gui: false,
+    };
+    while let Some(flag) = args.next()? {
+        if options.start.flag(&flag, &mut args)? { continue; }
+        match flag.as_str() {
+            "--years" => options.years = Some(number(&args.value(&flag)?)?),
+            "--checkpoint" => options.checkpoint = Some(args.value(&flag)?),
+            "--checkpoint-every" => options.checkpoint_every = Some(number(&args.value(&flag)?)?),
+            "--history" => options.history = true,
+            "--export" => options.export = Some(args.value(&flag)?),
+            "--format" => options.format = Some(match args.value(&flag)?.as_str() {
+                "csv" => Format::Csv,
+                "jsonl" => Format::JsonLines,
+                "parquet" => Format::Parquet,
+                format => return Err(Failure::usage(format!(
+                    "Exports can be csv, jsonl or parquet, not {}.", format))),
+            }),
+            "--columns" => options.columns = Some(
+                Columns::parse(&args.value(&flag)?).map_err(|e| Failure::usage(e.to_string()))?),
+            "--charts" => options.charts = Some(args.value(&flag)?),
+            "--chart-format" => match args.value(&flag)?.as_str() {
+                format @ "svg" | format @ "png" => options.chart_format = String::from(format),
+                format => return Err(Failure::usage(format!(
+                    "Charts can be svg or png, not {}.", format))),
+            },
+            "--pyramids" => options.pyramids = Some(years(&args.value(&flag)?)?),
+            "--tui" => options.tui = true,
+            #[cfg(feature = "gui")]
+            "--gui" => options.gui = true,
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    if options.checkpoint_every == Some(0) {
+        return Err(Failure::usage("--checkpoint-every needs at least one year."));
+    }
+    if options.checkpoint_every.is_some() && options.checkpoint.is_none() {
+        return Err(Failure::usage("--checkpoint-every needs a --checkpoint file."));
+    }
+    if (options.columns.is_some() || options.format.is_some()) && options.export.is_none() {
+        return Err(Failure::usage("--columns and --format need an --export file."));
+    }
+    if options.pyramids.is_some() && options.charts.is_none() {
+        return Err(Failure::usage("--pyramids needs a --charts directory."));
+    }
+    if options.tui && options.checkpoint_every.is_some() {
+        return Err(Failure::usage(
+            "--checkpoint-every can't be used with --tui, which checkpoints only when it's quit."));
+    }
+    #[cfg(feature = "gui")]
+    {
+        if options.gui && options.tui {
+            return Err(Failure::usage("--tui and --gui can't be used together."));
+        }
+        if options.gui && options.years.is_some() {
+            return Err(Failure::usage(
+                "--years can't be used with --gui, which runs until its window is closed."));
+        }
+        if options.gui
+            && (options.checkpoint.is_some() || options.history || options.charts.is_some())
+        {
+            return Err(Failure::usage("--checkpoint, --history and --charts can't be used with \
+                                       --gui, whose window keeps the run."));
+        }
+    }
+    Ok(options)
+}
+
+fn checkpoint(pop: &Population, path: &str) -> Result<(), Failure> {
+    let path = path.replace("{year}", &pop.current_year().to_string());
+    pop.save_checkpoint(&path).map_err(Failure::failed)
+}
+
+fn exporter(path: &str, format: Option<Format>, columns: Columns)
+    -> Result<Exporter<usize>, ExportError>
+{
+    let format = match format {
+        Some(format) => format,
+        None => return Exporter::create(path, columns),
+    };
+    let file = File::create(path).map_err(|e| {
+        ExportError::new(format!("Couldn't create {}: {}", path, e))
+    })?;
+    Exporter::new(BufWriter::new(file), format, columns)
+}
+
+fn charts(pop: &Population, options: &Options, directory: &str) -> Result<(), ChartError> {
+    let history = match pop.history() {
+        Some(history) => history,
+        None => return Err(ChartError::new("There's no history to chart.")),
+    };
+    fs::create_dir_all(directory).map_err(|e| {
+        ChartError::new(format!("Couldn't create {}: {}", directory, e))
+    })?;
+    let path = |name: &str| Path::new(directory).join(format!("{}.{}", name, options.chart_format));
+    Chart::totals_of(history).save(path("totals"))?;
+    Chart::flows_of(history).save(path("births-deaths"))?;
+    let years = history.years();
+    let pyramids = options.pyramids.clone().unwrap_or_else(|| vec![*years.start(), *years.end()]);
+    for year in pyramids {
+        let chart = Chart::pyramid_of(history, year).ok_or_else(|| ChartError::new(format!(
+            "There's no pyramid for {}; the history covers {} to {}.",
+            year, years.start(), years.end())))?;
+        chart.save(path(&format!("pyramid-{}", year)))?;
+    }
+    Ok(())
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let (mut pop, scenario_years) = options.start.population()?;
+    let years = options.years.or(scenario_years).unwrap_or(1000);
+
+    // The dashboard has the terminal to itself.
+    if !options.tui {
+        pop = pop.with_observer(ExtinctionLogger);
+    }
+    if (options.history || options.charts.is_some()) && pop.history().is_none() {
+        pop = pop.with_history();
+    }
+    let export = match options.export {
+        Some(ref path) => {
+            let columns = options.columns.clone().unwrap_or_default();
+            let export = exporter(path, options.format, columns).map_err(Failure::failed)?;
+            let export = export.observer();
+            pop = pop.with_observer(export.clone());
+            Some(export)
+        },
+        None => None,
+    };
+
+    #[cfg(feature = "gui")]
+    {
+        if options.gui {
+            pop_sim::gui::show(pop);
+            if let Some(export) = export {
+                export.finish().map_err(Failure::failed)?;
+            }
+            return Ok(());
+        }
+    }
+
+    if options.tui {
+        pop = tui::run(pop).map_err(Failure::failed)?;
+    } else {
+        for year in 1..years + 1 {
+            pop = pop.advance_year();
+            println!("{:?}", pop.total_pop());
+            let every = options.checkpoint_every;
+            if let (Some(path), Some(every)) = (options.checkpoint.as_ref(), every) {
+                if year % every == 0 && year != years { checkpoint(&pop, path)?; }
+            }
+        }
+    }
+    if let Some(ref path) = options.checkpoint {
+        checkpoint(&pop, path)?;
+    }
+    if let Some(export) = export {
+        export.finish().map_err(Failure::failed)?;
+    }
+    if let Some(ref directory) = options.charts {
+        charts(&pop, &options, directory).map_err(Failure::failed)?;
+    }
+    println!("Males: {:?}", pop.total_male());
+    println!("Females: {:?}", pop.total_female());
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use cli::Args;
+    use super::parse_options;
+
+    fn parses(args: &[&str]) -> bool {
+        parse_options(Args::new(args.iter().map(|arg| arg.to_string()))).is_ok()
+    }
+
+    #[test]
+    fn tui_refuses_checkpoints_it_would_not_take() {
+        assert!(parses(&["--tui", "--checkpoint", "run.bin"]));
+        assert!(!parses(&["--tui", "--checkpoint", "run.bin", "--checkpoint-every", "10"]));
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn gui_refuses_what_it_would_ignore() {
+        assert!(parses(&["--gui", "--export", "run.csv"]));
+        for ignored in [&["--tui"][..], &["--history"], &["--years", "10"],
+                        &["--checkpoint", "run.bin"], &["--charts", "charts"]].iter()
+        {
+            let mut args = vec!["--gui"];
+            args.extend_from_slice(ignored);
+            assert!(!parses(&args), "{:?}", ignored);
+        }
+    }
+}
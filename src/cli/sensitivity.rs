@@ -0,0 +1,150 @@
+//! `PopSim sensitivity`: rank a scenario's parameters by how much its outcomes depend on them.
+
+use std::fs;
+
+use pop_sim::scenario::Scenario;
+use pop_sim::sensitivity::{Input, Sensitivity, DEFAULT_LEVELS, DEFAULT_SAMPLES,
+                           DEFAULT_TRAJECTORIES};
+
+use super::{number, real, unknown, Args, Failure};
+
+pub const USAGE: &str = "\
+Usage: PopSim sensitivity --scenario FILE [--method morris|sobol] [--samples N] [--levels N]
+                          [--seed N] [--years N] [--input NAME=LOW:HIGH]...
+                          [--relative NAME=LOW:HIGH]... [--threads N] [--format table|csv]
+                          [--output FILE]
+
+Varies a scenario's parameters together and ranks them, for each outcome at the end of the run
+(the population, the median age and the total dependency ratio), by how much the outcome depends
+on them: by Morris's elementary effects (μ*, with μ and σ), or by Sobol indices (the total index,
+with the first-order one).
+
+The parameters are total_fertility_rate, sex_ratio, fertility_min_age, fertility_max_age,
+fertility_skew, and the Heligman-Pollard parameters, for both sexes or with male. or female. in
+front, as in female.adult_mortality. Without any --input or --relative, the eight
+Heligman-Pollard parameters, the TFR and the fertility curve's skew and ages are each varied 10%
+either side of the scenario's value, and adult_mortality_increase 1%. The engine has no migration
+yet, so it can't be varied.
+
+  --scenario FILE           The base scenario, as YAML
+  --method morris|sobol     Elementary effects, or variance-based indices (default morris)
+  --samples N               Morris trajectories (default 10), or Sobol base samples (default
+                            256); Morris takes N × (inputs + 1) runs, Sobol N × (inputs + 2)
+  --levels N                Levels in Morris's grid, an even number (default 4)
+  --seed N                  Seed for choosing the settings (default 1)
+  --years N                 Years to run (default the scenario's)
+  --input NAME=LOW:HIGH     Vary NAME from LOW to HIGH
+  --relative NAME=LOW:HIGH  Vary NAME from LOW to HIGH times the scenario's value
+  --threads N               Runs at once (default 4)
+  --format table|csv        How to write the ranking (default table)
+  --output FILE             Write the ranking to FILE instead";
+
+#[derive(PartialEq)]
+enum Format { Table, Csv }
+
+struct Options {
+    scenario: String,
+    sobol: bool,
+    samples: Option<usize>,
+    levels: usize,
+    seed: u64,
+    years: Option<usize>,
+    inputs: Vec<Input>,
+    threads: usize,
+    format: Format,
+    output: Option<String>,
+}
+
+/// `NAME=LOW:HIGH`, as an input.
+fn input(value: &str, relative: bool) -> Result<Input, Failure> {
+    let bad = || Failure::usage(format!("{} isn't NAME=LOW:HIGH.", value));
+    let (name, range) = value.split_at(value.find('=').ok_or_else(bad)?);
+    let (low, high) = range[1..].split_at(range[1..].find(':').ok_or_else(bad)?);
+    let (low, high) = (real(low)?, real(&high[1..])?);
+    Ok(if relative { Input::relative(name, low, high) } else { Input::range(name, low, high) })
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let mut scenario = None;
+    let mut options = Options {
+        scenario: String::new(),
+        sobol: false,
+        samples: None,
+        levels: DEFAULT_LEVELS,
+        seed: 1,
+        years: None,
+        inputs: Vec::new(),
+        threads: 4,
+        format: Format::Table,
+        output: None,
+    };
+    while let Some(flag) = args.next()? {
+        match flag.as_str() {
+            "--scenario" => scenario = Some(args.value(&flag)?),
+            "--method" => options.sobol = match args.value(&flag)?.as_str() {
+                "morris" => false,
+                "sobol" => true,
+                other => return Err(Failure::usage(format!("Unknown method {}.", other))),
+            },
+            "--samples" => options.samples = Some(number(&args.value(&flag)?)?),
+            "--levels" => options.levels = number(&args.value(&flag)?)?,
+            "--seed" => options.seed = number(&args.value(&flag)?)? as u64,
+            "--years" => options.years = Some(number(&args.value(&flag)?)?),
+            "--input" => options.inputs.push(input(&args.value(&flag)?, false)?),
+            "--relative" => options.inputs.push(input(&args.value(&flag)?, true)?),
+            "--threads" => options.threads = number(&args.value(&flag)?)?,
+            "--format" => options.format = match args.value(&flag)?.as_str() {
+                "table" => Format::Table,
+                "csv" => Format::Csv,
+                other => return Err(Failure::usage(format!("Unknown format {}.", other))),
+            },
+            "--output" => options.output = Some(args.value(&flag)?),
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    options.scenario = scenario.ok_or_else(|| Failure::usage("sensitivity needs a --scenario."))?;
+    if options.threads == 0 {
+        return Err(Failure::usage("--threads needs to be at least one."));
+    }
+    if options.levels < 2 || options.levels % 2 == 1 {
+        return Err(Failure::usage("--levels needs to be an even number, at least two."));
+    }
+    Ok(options)
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let mut scenario = Scenario::load(&options.scenario).map_err(Failure::failed)?;
+    if let Some(years) = options.years { scenario.years = years; }
+
+    let analysis = if options.sobol {
+        Sensitivity::sobol(options.samples.unwrap_or(DEFAULT_SAMPLES), options.seed)
+    } else {
+        let trajectories = options.samples.unwrap_or(DEFAULT_TRAJECTORIES);
+        Sensitivity::morris(trajectories, options.levels, options.seed)
+    };
+    let inputs = if options.inputs.is_empty() {
+        Sensitivity::default_inputs()
+    } else {
+        options.inputs
+    };
+    let analysis = inputs.into_iter()
+        .fold(analysis, |analysis, input| analysis.with_input(input))
+        .with_threads(options.threads);
+    let result = analysis.run(&scenario).map_err(Failure::failed)?;
+
+    let ranking = match options.format {
+        Format::Table => result.to_table(),
+        Format::Csv => result.to_csv(),
+    };
+    match options.output {
+        Some(ref path) => {
+            fs::write(path, ranking).map_err(|e| {
+                Failure::Failed(format!("Couldn't write {}: {}", path, e))
+            })?;
+            println!("Wrote the ranking from {} runs to {}", result.runs, path);
+        },
+        None => print!("{}", ranking),
+    }
+    Ok(())
+}
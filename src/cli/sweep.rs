@@ -0,0 +1,96 @@
+//! `PopSim sweep`: run a scenario across settings of its parameters.
+
+use std::fs;
+
+use pop_sim::scenario::Scenario;
+use pop_sim::sweep::Sweep;
+
+use super::{number, unknown, Args, Failure};
+
+pub const USAGE: &str = "\
+Usage: PopSim sweep SWEEP --scenario FILE [--years N] [--threads N] [--every N] [--output FILE]
+
+Runs a base scenario at every setting a sweep file chooses, in parallel, and writes a CSV table
+with a row for each year of each run: the run, its parameter values, the year, the totals and the
+indicators. A sweep file, as YAML:
+
+  design: grid                  # or latin_hypercube, with samples: N and seed: N, or list
+  parameters:
+    - parameter: total_fertility_rate
+      values: [1.6, 1.8, 2.1]
+    - parameter: adult_mortality_increase
+      range: [1.08, 1.12]
+      steps: 5
+
+The parameters are total_fertility_rate, sex_ratio, fertility_min_age, fertility_max_age,
+fertility_skew, and the Heligman-Pollard parameters, for both sexes or with male. or female. in
+front, as in female.adult_mortality.
+
+  --scenario FILE       The base scenario, as YAML
+  --years N             Years to run (default the scenario's)
+  --threads N           Runs at once (default the sweep's, or 4)
+  --every N             Years between rows (default the sweep's, or 1); the last year is always
+                        written
+  --output FILE         Write the table to FILE instead";
+
+struct Options {
+    sweep: String,
+    scenario: String,
+    years: Option<usize>,
+    threads: Option<usize>,
+    every: Option<usize>,
+    output: Option<String>,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: Args<I>) -> Result<Options, Failure> {
+    let (mut sweep, mut scenario) = (None, None);
+    let mut options = Options {
+        sweep: String::new(),
+        scenario: String::new(),
+        years: None,
+        threads: None,
+        every: None,
+        output: None,
+    };
+    while let Some(flag) = args.next()? {
+        match flag.as_str() {
+            "--scenario" => scenario = Some(args.value(&flag)?),
+            "--years" => options.years = Some(number(&args.value(&flag)?)?),
+            "--threads" => options.threads = Some(number(&args.value(&flag)?)?),
+            "--every" => options.every = Some(number(&args.value(&flag)?)?),
+            "--output" => options.output = Some(args.value(&flag)?),
+            _ if !flag.starts_with('-') && sweep.is_none() => sweep = Some(flag),
+            _ => return Err(unknown(&flag)),
+        }
+    }
+    match (sweep, scenario) {
+        (Some(sweep), Some(scenario)) => { options.sweep = sweep; options.scenario = scenario; },
+        _ => return Err(Failure::usage("sweep needs a sweep file and a --scenario.")),
+    }
+    if options.threads == Some(0) || options.every == Some(0) {
+        return Err(Failure::usage("--threads and --every need to be at least one."));
+    }
+    Ok(options)
+}
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let options = parse_options(Args::new(args))?;
+    let mut sweep = Sweep::load(&options.sweep).map_err(Failure::failed)?;
+    let mut scenario = Scenario::load(&options.scenario).map_err(Failure::failed)?;
+    if let Some(years) = options.years { scenario.years = years; }
+    if let Some(threads) = options.threads { sweep = sweep.with_threads(threads); }
+    if let Some(every) = options.every { sweep = sweep.with_every(every); }
+
+    let result = sweep.run(&scenario).map_err(Failure::failed)?;
+    let csv = result.to_csv();
+    match options.output {
+        Some(ref path) => {
+            fs::write(path, csv).map_err(|e| {
+                Failure::Failed(format!("Couldn't write {}: {}", path, e))
+            })?;
+            println!("Wrote {} runs to {}", result.runs.len(), path);
+        },
+        None => print!("{}", csv),
+    }
+    Ok(())
+}
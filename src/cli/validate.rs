@@ -0,0 +1,52 @@
+//! `PopSim validate`: check scenario files without running them.
+
+use pop_sim::scenario::Scenario;
+
+use super::{unknown, Args, Failure};
+
+pub const USAGE: &str = "\
+Usage: PopSim validate FILE...
+
+Checks that each scenario file can be read and describes a run that can start, and says what's
+wrong with any that can't. Exits with 1 if any can't.";
+
+pub fn main<I: Iterator<Item = String>>(args: I) -> Result<(), Failure> {
+    let mut args = Args::new(args);
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next()? {
+        if arg.starts_with('-') { return Err(unknown(&arg)); }
+        paths.push(arg);
+    }
+    if paths.is_empty() {
+        return Err(Failure::usage("validate needs a scenario file."));
+    }
+
+    let mut invalid = 0;
+    for path in paths.iter() {
+        match Scenario::load(path) {
+            Ok(scenario) => {
+                let people = scenario.initial.iter().try_fold(0usize, |total, &(males, females)| {
+                    males.checked_add(females).and_then(|people| total.checked_add(people))
+                });
+                match people {
+                    Some(people) => println!("{}: ok, {} people over {} years from {}",
+                                             path, people, scenario.years, scenario.start_year),
+                    None => {
+                        println!("{}: initial has more people than can be counted", path);
+                        invalid += 1;
+                    },
+                }
+            },
+            Err(e) => {
+                println!("{}: {}", path, e);
+                invalid += 1;
+            },
+        }
+    }
+    match invalid {
+        0 => Ok(()),
+        1 if paths.len() == 1 => Err(Failure::Failed(String::from("The scenario isn't valid."))),
+        _ => Err(Failure::Failed(format!(
+            "{} of {} scenarios aren't valid.", invalid, paths.len()))),
+    }
+}
@@ -0,0 +1,447 @@
+/*!
+Comparing two runs year by year, from the histories they recorded.
+
+For every completed year both histories cover, each measure is taken from both runs, `a` and `b`,
+with the difference `b - a` and the difference relative to `a`: the totals, the year's births,
+deaths and net migration, the indicators (see `History::indicators`, so life expectancies are the
+ones the recorded deaths imply), and the count in each band of completed ages, which are
+five-year groups up to 85+ unless asked otherwise. The runs diverge in the first year any
+measure's relative difference is beyond the tolerance, or is defined in one run and not the other.
+
+The difference in the total is also split into the components of change: the difference the runs
+started with, and the differences in births (fertility), deaths (mortality) and net migration
+since, which together account for it. This is accounting rather than attribution: a run with more
+births goes on to have more deaths too, which count as mortality. Anything the flows don't explain
+is left as a residual, and is zero unless the histories are inconsistent.
+
+```
+use pop_sim::compare::Comparison;
+use pop_sim::population::Population;
+
+let mut a = Population::new(vec![ ( 1_000, 1_000 ); 60 ]).with_history();
+let mut b = Population::new(vec![ ( 1_000, 1_000 ); 60 ]).with_fertility_rate(2.5).with_history();
+for _ in 0..30 {
+    a = a.advance_year();
+    b = b.advance_year();
+}
+
+let result = Comparison::default()
+    .with_tolerance(0.05)
+    .histories(a.history().unwrap(), b.history().unwrap())
+    .unwrap();
+println!("{}", result.to_table(10));
+# assert!(result.divergence.is_some());
+```
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+
+use population::completed_ages;
+use population::count::Count;
+use population::history::History;
+use population::indicators::{AgeBand, Indicators};
+
+/// Relative differences up to this much aren't a divergence, unless asked otherwise.
+pub const DEFAULT_TOLERANCE: f64 = 0.01;
+/// The default bands of ages are groups of this many years...
+pub const GROUP_WIDTH: usize = 5;
+/// ...up to an open group from this age.
+pub const LAST_GROUP: usize = 85;
+
+#[derive(Debug)]
+pub struct CompareError { problem: String }
+
+impl CompareError {
+    pub fn new<S: Into<String>>(problem: S) -> CompareError {
+        CompareError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for CompareError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+/// How to compare two histories.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub tolerance: f64,
+    pub bands: Vec<AgeBand>,
+}
+
+impl Default for Comparison {
+    fn default() -> Comparison {
+        let groups = (0..LAST_GROUP / GROUP_WIDTH).map(|group| {
+            AgeBand::new(group * GROUP_WIDTH, Some((group + 1) * GROUP_WIDTH - 1))
+        });
+        Comparison {
+            tolerance: DEFAULT_TOLERANCE,
+            bands: groups.chain(Some(AgeBand::new(LAST_GROUP, None))).collect(),
+        }
+    }
+}
+
+/// A measure in one year of both runs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub measure: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+}
+
+impl Difference {
+    /// `b - a`.
+    pub fn absolute(&self) -> Option<f64> {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) => Some(b - a),
+            _ => None,
+        }
+    }
+
+    /// `b - a` as a proportion of `a`.
+    pub fn relative(&self) -> Option<f64> {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) if a != 0.0 => Some((b - a) / a.abs()),
+            _ => None,
+        }
+    }
+
+    fn diverges(&self, tolerance: f64) -> bool {
+        match (self.a, self.b) {
+            (None, None) => false,
+            (Some(a), Some(b)) => match self.relative() {
+                Some(relative) => relative.abs() > tolerance,
+                None => a != b,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// The difference in the total at the end of a year, `b - a`, by the components of change since
+/// the start of the comparison; see the module.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Components {
+    pub initial: f64,
+    pub fertility: f64,
+    pub mortality: f64,
+    pub migration: f64,
+    pub residual: f64,
+}
+
+impl Components {
+    pub fn total(&self) -> f64 {
+        self.initial + self.fertility + self.mortality + self.migration + self.residual
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct YearComparison {
+    pub year: i32,
+    pub differences: Vec<Difference>,
+    pub components: Components,
+}
+
+/// The first year the runs differed by more than the tolerance, and the first measure that did.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub year: i32,
+    pub measure: String,
+    pub relative: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ComparisonResult {
+    pub tolerance: f64,
+    pub years: Vec<YearComparison>,
+    pub divergence: Option<Divergence>,
+}
+
+impl Comparison {
+    pub fn with_tolerance(mut self, tolerance: f64) -> Comparison {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_bands(mut self, bands: Vec<AgeBand>) -> Comparison {
+        self.bands = bands;
+        self
+    }
+
+    /// Compare two histories over the completed years they have in common.
+    pub fn histories<C: Count, D: Count>(&self, a: &History<C>, b: &History<D>)
+        -> Result<ComparisonResult, CompareError>
+    {
+        // A history's last year is the one in progress.
+        let first = *a.years().start().max(b.years().start());
+        let last = *a.years().end().min(b.years().end()) - 1;
+        if first > last {
+            return Err(CompareError::new(format!(
+                "The runs have no completed year in common: one covers {} to {}, the other {} to \
+                 {}.", a.years().start(), a.years().end() - 1, b.years().start(),
+                b.years().end() - 1)));
+        }
+        let total = |year| (a.total(year).map_or(0.0, Count::to_f64),
+                            b.total(year).map_or(0.0, Count::to_f64));
+        let (start_a, start_b) = total(first);
+        let mut components = Components {
+            initial: start_b - start_a,
+            fertility: 0.0,
+            mortality: 0.0,
+            migration: 0.0,
+            residual: 0.0,
+        };
+
+        let mut years = Vec::new();
+        let mut divergence = None;
+        for year in first..=last {
+            let (measures_a, measures_b) = (self.measures(a, year), self.measures(b, year));
+            let differences: Vec<Difference> = measures_a.into_iter().zip(measures_b)
+                .map(|((measure, a), (_, b))| Difference { measure, a, b })
+                .collect();
+
+            let flow = |name: &str| differences.iter()
+                .find(|difference| difference.measure == name)
+                .and_then(Difference::absolute)
+                .unwrap_or(0.0);
+            components.fertility += flow("births");
+            components.mortality -= flow("deaths");
+            components.migration += flow("net_migration");
+            let (end_a, end_b) = total(year + 1);
+            components.residual = 0.0;
+            components.residual = (end_b - end_a) - components.total();
+
+            if divergence.is_none() {
+                divergence = differences.iter()
+                    .find(|difference| difference.diverges(self.tolerance))
+                    .map(|difference| Divergence {
+                        year,
+                        measure: difference.measure.clone(),
+                        relative: difference.relative(),
+                    });
+            }
+            years.push(YearComparison { year, differences, components });
+        }
+        Ok(ComparisonResult { tolerance: self.tolerance, years, divergence })
+    }
+
+    /// Every measure of a completed year, by name.
+    fn measures<C: Count>(&self, history: &History<C>, year: i32)
+        -> Vec<(String, Option<f64>)>
+    {
+        let indicators = history.indicators(year, &[]);
+        let distribution = history.pyramid(year + 1).unwrap_or_default();
+        let (males, females) = distribution.iter().fold((0.0, 0.0), |(m, f), &(males, females)| {
+            (m + males.to_f64(), f + females.to_f64())
+        });
+        let indicator = |value: fn(&Indicators) -> Option<f64>| {
+            indicators.as_ref().and_then(value)
+        };
+        let mut measures = vec![
+            ("total", Some(males + females)),
+            ("males", Some(males)),
+            ("females", Some(females)),
+            ("births", history.births(year).map(Count::to_f64)),
+            ("deaths", history.deaths(year).map(Count::to_f64)),
+            ("net_migration", history.net_migration(year)),
+            ("crude_birth_rate", indicator(|i| i.crude_birth_rate)),
+            ("crude_death_rate", indicator(|i| i.crude_death_rate)),
+            ("mean_age", indicator(|i| i.mean_age)),
+            ("median_age", indicator(|i| i.median_age)),
+            ("youth_dependency", indicator(|i| i.youth_dependency)),
+            ("old_age_dependency", indicator(|i| i.old_age_dependency)),
+            ("total_dependency", indicator(|i| i.total_dependency)),
+            ("sex_ratio", indicator(|i| i.sex_ratio)),
+            ("male_life_expectancy", indicator(|i| Some(i.male_life_expectancy))),
+            ("female_life_expectancy", indicator(|i| Some(i.female_life_expectancy))),
+        ].into_iter()
+            .map(|(name, value)| (String::from(name), value))
+            .collect::<Vec<_>>();
+        let ages = completed_ages(&distribution);
+        measures.extend(self.bands.iter().map(|band| {
+            let count = ages.iter().enumerate()
+                .filter(|&(age, _)| band.contains(age))
+                .fold(0.0, |total, (_, &(males, females))| {
+                    total + males.to_f64() + females.to_f64()
+                });
+            let name = match band.end {
+                Some(end) => format!("age_{}_{}", band.start, end),
+                None => format!("age_{}_plus", band.start),
+            };
+            (name, Some(count))
+        }));
+        measures
+    }
+}
+
+impl ComparisonResult {
+    /// A measure in a year, if both runs completed it.
+    pub fn difference(&self, year: i32, measure: &str) -> Option<&Difference> {
+        self.years.iter()
+            .find(|compared| compared.year == year)
+            .and_then(|compared| compared.differences.iter().find(|d| d.measure == measure))
+    }
+
+    /// A tidy table: a row for each measure in each year, with both values, the difference and
+    /// the relative difference. Undefined values are left empty.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("year,measure,a,b,difference,relative\n");
+        for compared in self.years.iter() {
+            for difference in compared.differences.iter() {
+                writeln!(csv, "{},{},{},{},{},{}", compared.year, difference.measure,
+                         cell(difference.a), cell(difference.b), cell(difference.absolute()),
+                         cell(difference.relative())).unwrap();
+            }
+        }
+        csv
+    }
+
+    /// The components of change in each year, as CSV.
+    pub fn components_to_csv(&self) -> String {
+        let mut csv = String::from("year,difference,initial,fertility,mortality,migration,\
+                                    residual\n");
+        for compared in self.years.iter() {
+            let c = compared.components;
+            writeln!(csv, "{},{},{},{},{},{},{}", compared.year, c.total(), c.initial,
+                     c.fertility, c.mortality, c.migration, c.residual).unwrap();
+        }
+        csv
+    }
+
+    /// A summary for reading: where the runs diverged, the total and its components every
+    /// `every` years and in the last, and every measure in the last year.
+    pub fn to_table(&self, every: usize) -> String {
+        let mut table = String::new();
+        let tolerance = 100.0 * self.tolerance;
+        match self.divergence {
+            Some(ref divergence) => {
+                let by = divergence.relative
+                    .map_or(String::from("is defined in only one run, or from zero"),
+                            |relative| format!("differs by {:+.2}%", 100.0 * relative));
+                writeln!(table, "The runs diverge beyond {}% in {}: {} {}.", tolerance,
+                         divergence.year, divergence.measure, by).unwrap();
+            },
+            None => writeln!(table, "The runs stay within {}% of each other.", tolerance).unwrap(),
+        }
+
+        writeln!(table, "\n{:>6}  {:>12}  {:>12}  {:>12}  {:>9}  {:>12}  {:>12}  {:>12}  {:>12}  \
+                         {:>12}", "year", "a", "b", "difference", "relative", "initial",
+                 "fertility", "mortality", "migration", "residual").unwrap();
+        let every = every.max(1);
+        let count = self.years.len();
+        for (index, compared) in self.years.iter().enumerate() {
+            if (index + 1) % every != 0 && index + 1 != count { continue; }
+            let total = &compared.differences[0];
+            let c = compared.components;
+            writeln!(table, "{:>6}  {:>12}  {:>12}  {:>12}  {:>9}  {:>12.1}  {:>12.1}  {:>12.1}  \
+                             {:>12.1}  {:>12.1}", compared.year, number(total.a),
+                     number(total.b), number(total.absolute()), percent(total.relative()),
+                     c.initial, c.fertility, c.mortality, c.migration, c.residual).unwrap();
+        }
+
+        if let Some(last) = self.years.last() {
+            writeln!(table, "\nIn {}:", last.year).unwrap();
+            writeln!(table, "{:<24}  {:>12}  {:>12}  {:>12}  {:>9}", "measure", "a", "b",
+                     "difference", "relative").unwrap();
+            for difference in last.differences.iter() {
+                writeln!(table, "{:<24}  {:>12}  {:>12}  {:>12}  {:>9}", difference.measure,
+                         number(difference.a), number(difference.b),
+                         number(difference.absolute()), percent(difference.relative())).unwrap();
+            }
+        }
+        table
+    }
+}
+
+fn cell(value: Option<f64>) -> String {
+    value.map_or(String::new(), |value| value.to_string())
+}
+
+fn number(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value == value.round() => format!("{}", value),
+        Some(value) => format!("{:.3}", value),
+        None => String::from("-"),
+    }
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), |value| format!("{:+.2}%", 100.0 * value))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Comparison;
+    use population::Population;
+
+    fn run<C: ::population::count::Count>(pop: Population<C>, years: usize) -> Population<C> {
+        (0..years).fold(pop.with_history(), |pop, _| pop.advance_year())
+    }
+
+    #[test]
+    fn identical_runs_never_diverge() {
+        let a = run(Population::new(vec![ ( 1_000, 1_000 ); 70 ]).stochastic(3), 40);
+        let b = run(Population::new(vec![ ( 1_000, 1_000 ); 70 ]).stochastic(3), 40);
+        let result = Comparison::default().with_tolerance(0.0)
+            .histories(a.history().unwrap(), b.history().unwrap())
+            .unwrap();
+        assert_eq!(result.years.len(), 40);
+        assert_eq!(result.divergence, None);
+        let last = result.years.last().unwrap();
+        assert!(last.differences.iter().all(|d| d.absolute().is_none_or(|d| d == 0.0)));
+        assert_eq!(last.components.total(), 0.0);
+        assert!(result.to_table(10).contains("stay within 0%"));
+    }
+
+    #[test]
+    fn differences_are_split_into_components() {
+        let a = run(Population::<f64>::from_counts(vec![ ( 1_000.0, 1_000.0 ); 70 ]), 60);
+        let b = run(Population::<f64>::from_counts(vec![ ( 1_000.0, 1_000.0 ); 70 ])
+                    .with_fertility_rate(2.6), 50);
+        let result = Comparison::default()
+            .histories(a.history().unwrap(), b.history().unwrap())
+            .unwrap();
+        // Only the years both completed.
+        assert_eq!(result.years.len(), 50);
+        let divergence = result.divergence.as_ref().unwrap();
+        assert_eq!((divergence.year, divergence.measure.as_str()), (0, "births"));
+        assert!(divergence.relative.unwrap() > 0.2);
+
+        let last = result.years.last().unwrap();
+        let total = result.difference(49, "total").unwrap();
+        let c = last.components;
+        assert!(c.initial == 0.0 && c.fertility > 0.0 && c.mortality < 0.0);
+        assert_eq!(c.migration, 0.0);
+        assert!(c.residual.abs() < 1e-6 * total.a.unwrap(), "{:?}", c);
+        assert!((c.total() - total.absolute().unwrap()).abs() < 1e-6);
+        assert!(result.difference(49, "median_age").unwrap().relative().unwrap() < 0.0);
+        // The same mortality, so the same life expectancy, in every year but the first few,
+        // where the newborns are the only children.
+        let e = result.difference(49, "female_life_expectancy").unwrap();
+        assert!(e.relative().unwrap().abs() < 1e-6, "{:?}", e);
+        assert!(result.difference(49, "age_0_4").unwrap().relative().unwrap() > 0.2);
+
+        let csv = result.to_csv();
+        assert!(csv.starts_with("year,measure,a,b,difference,relative\n0,total,"));
+        assert_eq!(csv.lines().count(), 1 + 50 * (16 + 18));
+        assert_eq!(result.components_to_csv().lines().count(), 51);
+    }
+
+    #[test]
+    fn histories_need_a_year_in_common() {
+        let a = run(Population::new(vec![ ( 100, 100 ); 10 ]), 5);
+        let b = run(Population::new(vec![ ( 100, 100 ); 10 ]).with_start_year(10), 5);
+        assert!(Comparison::default().histories(a.history().unwrap(), b.history().unwrap())
+                .is_err());
+    }
+}
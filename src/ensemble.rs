@@ -0,0 +1,305 @@
+/*!
+Monte Carlo ensembles: many replicates of one scenario, summarized year by year.
+
+Each replicate runs in stochastic mode with its own seed, derived from the ensemble seed (the
+scenario's own seed is not used), and can also draw its own TFR to represent uncertainty in the
+parameter itself. Replicates are spread over a fixed number of threads, and the results are always
+stored in replicate order, so an ensemble is as reproducible as a single stochastic run.
+
+```
+use pop_sim::scenario::Scenario;
+use pop_sim::ensemble::{Ensemble, Series};
+
+let mut scenario = Scenario::new(vec![ ( 1_000, 1_000 ); 40 ]);
+scenario.years = 50;
+
+let result = Ensemble::new(100, 7).with_threads(4).run(&scenario);
+let median = result.quantile(Series::Total, 0.5);
+println!("{}", result.fan_chart_csv(Series::Total, &[0.05, 0.25, 0.5, 0.75, 0.95]));
+# assert_eq!(median.len(), 50);
+```
+*/
+
+use random::Random;
+use scenario::Scenario;
+use sweep::in_parallel;
+
+/// A per-year statistic tracked across replicates.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Series {
+    Total,
+    Births,
+    Deaths,
+    /// Members of the `n`th age group, counting from zero.
+    AgeGroup(usize),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ensemble {
+    pub replicates: usize,
+    pub seed: u64,
+    pub threads: usize,
+    /// Standard deviation of a normal perturbation applied to the TFR, once per replicate.
+    pub tfr_uncertainty: f64,
+    /// Whether replicates get demographic stochasticity, or only their sampled parameters.
+    pub stochastic: bool,
+    pub age_group_width: usize,
+}
+
+/// The trajectory of one replicate.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Replicate {
+    pub total_fertility_rate: f32,
+    pub totals: Vec<usize>,
+    pub births: Vec<usize>,
+    pub deaths: Vec<usize>,
+    pub age_groups: Vec<Vec<usize>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnsembleResult {
+    pub years: Vec<i32>,
+    pub age_group_width: usize,
+    pub replicates: Vec<Replicate>,
+}
+
+impl Ensemble {
+    pub fn new(replicates: usize, seed: u64) -> Ensemble {
+        Ensemble {
+            replicates,
+            seed,
+            threads: 4,
+            tfr_uncertainty: 0.0,
+            stochastic: true,
+            age_group_width: 5,
+        }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Ensemble {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_tfr_uncertainty(mut self, standard_deviation: f64) -> Ensemble {
+        self.tfr_uncertainty = standard_deviation;
+        self
+    }
+
+    pub fn with_age_group_width(mut self, width: usize) -> Ensemble {
+        self.age_group_width = width.max(1);
+        self
+    }
+
+    /// Only vary sampled parameters between replicates; each one runs deterministically.
+    pub fn deterministic(mut self) -> Ensemble {
+        self.stochastic = false;
+        self
+    }
+
+    pub fn run(&self, scenario: &Scenario) -> EnsembleResult {
+        let scenarios = (0..self.replicates).map(|index| self.sample(scenario, index)).collect();
+        let width = self.age_group_width;
+        let replicates = in_parallel(scenarios, self.threads, move |sample| {
+            replicate(sample, width)
+        });
+
+        // Each year is labelled as completed, like a history's.
+        let start = scenario.population().current_year();
+        EnsembleResult {
+            years: (0..scenario.years as i32).map(|offset| start + offset).collect(),
+            age_group_width: self.age_group_width,
+            replicates,
+        }
+    }
+
+    /// The scenario for a replicate, with its own seed and, if it's uncertain, its own TFR.
+    fn sample(&self, scenario: &Scenario, index: usize) -> Scenario {
+        let mut seeds = Random::new(self.seed ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let demographic_seed = seeds.next_u64();
+
+        let mut total_fertility_rate = scenario.total_fertility_rate;
+        if self.tfr_uncertainty > 0.0 {
+            let drawn = total_fertility_rate as f64 + self.tfr_uncertainty * seeds.normal();
+            total_fertility_rate = drawn.max(0.0) as f32;
+        }
+
+        // Everything else comes from the scenario as it is.
+        Scenario {
+            total_fertility_rate,
+            seed: if self.stochastic { Some(demographic_seed) } else { None },
+            ..scenario.clone()
+        }
+    }
+}
+
+fn replicate(scenario: &Scenario, age_group_width: usize) -> Replicate {
+    let mut pop = scenario.population();
+    let mut replicate = Replicate {
+        total_fertility_rate: scenario.total_fertility_rate,
+        totals: Vec::with_capacity(scenario.years),
+        births: Vec::with_capacity(scenario.years),
+        deaths: Vec::with_capacity(scenario.years),
+        age_groups: Vec::with_capacity(scenario.years),
+    };
+    for _ in 0..scenario.years {
+        pop = pop.advance_year();
+        replicate.totals.push(pop.total_pop());
+        replicate.births.push(pop.births());
+        replicate.deaths.push(pop.deaths());
+        replicate.age_groups.push(pop.age_groups(age_group_width));
+    }
+    replicate
+}
+
+impl Replicate {
+    fn value(&self, series: Series, year_index: usize) -> f64 {
+        let value = match series {
+            Series::Total => self.totals[year_index],
+            Series::Births => self.births[year_index],
+            Series::Deaths => self.deaths[year_index],
+            Series::AgeGroup(group) => *self.age_groups[year_index].get(group).unwrap_or(&0),
+        };
+        value as f64
+    }
+}
+
+/// The `q`th quantile of already sorted values, interpolating linearly between order statistics.
+fn sorted_quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() { return f64::NAN; }
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+impl EnsembleResult {
+    /// The number of age groups in the widest year of any replicate.
+    pub fn age_group_count(&self) -> usize {
+        self.replicates.iter()
+            .flat_map(|replicate| replicate.age_groups.iter().map(|groups| groups.len()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Every replicate's value of `series` in each year.
+    pub fn values(&self, series: Series) -> Vec<Vec<f64>> {
+        (0..self.years.len()).map(|year_index| {
+            self.replicates.iter().map(|replicate| replicate.value(series, year_index)).collect()
+        }).collect()
+    }
+
+    pub fn mean(&self, series: Series) -> Vec<f64> {
+        self.values(series).iter().map(|values| {
+            values.iter().sum::<f64>() / values.len() as f64
+        }).collect()
+    }
+
+    pub fn median(&self, series: Series) -> Vec<f64> {
+        self.quantile(series, 0.5)
+    }
+
+    pub fn quantile(&self, series: Series, q: f64) -> Vec<f64> {
+        self.quantiles(series, &[q]).into_iter().map(|row| row[0]).collect()
+    }
+
+    /// Several quantiles at once, one row per year, in the order they were asked for.
+    pub fn quantiles(&self, series: Series, qs: &[f64]) -> Vec<Vec<f64>> {
+        self.values(series).into_iter().map(|mut values| {
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            qs.iter().map(|&q| sorted_quantile(&values, q)).collect()
+        }).collect()
+    }
+
+    /// A CSV table for fan charts: the year, the mean, then one column per requested quantile.
+    pub fn fan_chart_csv(&self, series: Series, qs: &[f64]) -> String {
+        let mut csv = String::from("year,mean");
+        for q in qs {
+            csv.push_str(&format!(",q{}", q));
+        }
+        csv.push('\n');
+
+        let (means, quantiles) = (self.mean(series), self.quantiles(series, qs));
+        for ((year, mean), quantiles) in self.years.iter().zip(means).zip(quantiles) {
+            csv.push_str(&format!("{},{}", year, mean));
+            for value in quantiles {
+                csv.push_str(&format!(",{}", value));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Ensemble, Series, sorted_quantile};
+    use scenario::Scenario;
+
+    fn scenario() -> Scenario {
+        let mut scenario = Scenario::new(vec![ ( 200, 200 ); 40 ]);
+        scenario.years = 30;
+        scenario
+    }
+
+    #[test]
+    fn thread_count_does_not_change_results() {
+        let one = Ensemble::new(12, 5).with_threads(1).run(&scenario());
+        let many = Ensemble::new(12, 5).with_threads(5).run(&scenario());
+        assert_eq!(one.replicates, many.replicates);
+        assert_ne!(one.replicates[0], one.replicates[1]);
+        let none = Ensemble { threads: 0, ..Ensemble::new(12, 5) }.run(&scenario());
+        assert_eq!(none.replicates, one.replicates);
+    }
+
+    #[test]
+    fn deterministic_replicates_follow_the_scenario() {
+        let mut scenario = scenario();
+        scenario.start_year = 2000;
+        scenario.terminal_age = Some(60);
+        scenario.fertility_ages = Some((18, 45));
+        scenario.seed = Some(3);
+        let result = Ensemble::new(2, 5).deterministic().run(&scenario);
+        assert_eq!(result.years[0], 2000);
+
+        scenario.seed = None;
+        let mut pop = scenario.population();
+        for (year, replicate) in (0..scenario.years).zip(result.replicates.iter().cycle()) {
+            pop = pop.advance_year();
+            assert_eq!(result.years[year], pop.current_year() - 1);
+            assert_eq!(replicate.totals[year], pop.total_pop());
+            assert_eq!(replicate.births[year], pop.births());
+            assert_eq!(replicate.age_groups[year], pop.age_groups(5));
+        }
+    }
+
+    #[test]
+    fn replicates_keep_the_scenario_mortality() {
+        let deadlier = scenario().with_parameter("adult_mortality", 0.001).unwrap();
+        let swept = Ensemble::new(1, 5).deterministic().run(&deadlier);
+        let baseline = Ensemble::new(1, 5).deterministic().run(&scenario());
+        let mut pop = deadlier.population();
+        for _ in 0..deadlier.years { pop = pop.advance_year(); }
+        assert_eq!(*swept.replicates[0].deaths.last().unwrap(), pop.deaths());
+        assert!(swept.replicates[0].deaths[0] > baseline.replicates[0].deaths[0]);
+    }
+
+    #[test]
+    fn quantiles_are_ordered() {
+        let result = Ensemble::new(40, 9).with_tfr_uncertainty(0.3).run(&scenario());
+        let rows = result.quantiles(Series::Births, &[0.05, 0.5, 0.95]);
+        for row in rows {
+            assert!(row[0] <= row[1] && row[1] <= row[2]);
+        }
+        assert_eq!(result.median(Series::AgeGroup(0)).len(), 30);
+        assert_eq!(result.fan_chart_csv(Series::Total, &[0.5]).lines().count(), 31);
+    }
+
+    #[test]
+    fn interpolated_quantiles() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(sorted_quantile(&values, 0.0), 1.0);
+        assert_eq!(sorted_quantile(&values, 0.5), 2.5);
+        assert_eq!(sorted_quantile(&values, 1.0), 4.0);
+    }
+}
@@ -0,0 +1,886 @@
+/*!
+Writing a run as a table with one row per year, for dataframe tools.
+
+Each row describes a completed year: the population at its end, and the births, deaths and
+migration during it. Which columns appear, and in what order, is up to a `Columns`. Ages are
+completed years, as for the indicators, in single years or in groups, with an open group at the
+end so that every year has the same columns. The year's `Indicators` can be columns too;
+those that are undefined, such as a sex ratio with no women, are left empty.
+
+Rows are written as they're recorded, so a long run is never held in memory. Attach an
+`Exporter` to a population through its observer:
+
+```
+use pop_sim::export::{Columns, Exporter, Format};
+use pop_sim::population::Population;
+
+let export = Exporter::new(Vec::new(), Format::Csv, Columns::default()).unwrap().observer();
+let mut pop = Population::new(vec![ ( 1_000, 1_000 ); 30 ]).with_observer(export.clone());
+for _ in 0..10 {
+    pop = pop.advance_year();
+}
+assert_eq!(export.finish().unwrap(), 10);
+```
+
+CSV and JSON Lines are always available. Parquet needs the `parquet` feature; it's written in row
+groups of `ROW_GROUP_SIZE` years, with whole counts as 64-bit integers and real counts as doubles.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use population::{completed_ages, Population};
+use population::clock::Clock;
+use population::cohort::Cohort;
+use population::count::Count;
+use population::indicators::{AgeBand, Indicators, SEX_RATIO_GROUP, SEX_RATIO_LAST};
+use population::observer::Observer;
+
+/// Years per Parquet row group.
+pub const ROW_GROUP_SIZE: usize = 4096;
+/// The start of the open age group, unless a column says otherwise.
+pub const DEFAULT_LAST_AGE: usize = 100;
+
+#[derive(Debug)]
+pub struct ExportError { problem: String }
+
+impl ExportError {
+    pub fn new<S: Into<String>>(problem: S) -> ExportError {
+        ExportError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for ExportError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+fn problem<E: fmt::Display>(e: &E) -> ExportError {
+    ExportError::new(format!("Couldn't write the export: {}", e))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+    Parquet,
+}
+
+impl Format {
+    /// The format a file's extension asks for: .csv, .jsonl or .ndjson, or .parquet.
+    pub fn of_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(Format::Csv),
+            "jsonl" | "ndjson" => Some(Format::JsonLines),
+            "parquet" => Some(Format::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// Counts by age, in groups of `width` years from age zero up to `last`, and an open group of
+/// everyone `last` or older.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ages {
+    pub width: usize,
+    pub last: usize,
+    /// Separate columns for males and females, rather than one for both.
+    pub by_sex: bool,
+}
+
+impl Ages {
+    fn groups(&self) -> usize {
+        self.last.div_ceil(self.width) + 1
+    }
+
+    fn group_of(&self, age: usize) -> usize {
+        if age >= self.last { self.groups() - 1 } else { age / self.width }
+    }
+
+    fn group_names(&self) -> Vec<String> {
+        (0..self.groups()).map(|group| {
+            let start = group * self.width;
+            let end = (start + self.width).min(self.last) - 1;
+            if group == self.groups() - 1 {
+                format!("age_{}_plus", self.last)
+            } else if start == end {
+                format!("age_{}", start)
+            } else {
+                format!("age_{}_{}", start, end)
+            }
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Year,
+    Total,
+    Males,
+    Females,
+    Births,
+    MaleBirths,
+    FemaleBirths,
+    Deaths,
+    MaleDeaths,
+    FemaleDeaths,
+    Immigrants,
+    Emigrants,
+    Ages(Ages),
+    CrudeBirthRate,
+    CrudeDeathRate,
+    CrudeGrowthRate,
+    MeanAge,
+    MedianAge,
+    YouthDependency,
+    OldAgeDependency,
+    TotalDependency,
+    SexRatio,
+    /// Sex ratios in five-year groups.
+    SexRatios,
+    MaleLifeExpectancy,
+    FemaleLifeExpectancy,
+    RealizedTfr,
+    /// The proportion of the population in a band of ages.
+    Share(AgeBand),
+}
+
+const NAMED: [(&str, Column); 25] = [
+    ("year", Column::Year),
+    ("total", Column::Total),
+    ("males", Column::Males),
+    ("females", Column::Females),
+    ("births", Column::Births),
+    ("male_births", Column::MaleBirths),
+    ("female_births", Column::FemaleBirths),
+    ("deaths", Column::Deaths),
+    ("male_deaths", Column::MaleDeaths),
+    ("female_deaths", Column::FemaleDeaths),
+    ("immigrants", Column::Immigrants),
+    ("emigrants", Column::Emigrants),
+    ("crude_birth_rate", Column::CrudeBirthRate),
+    ("crude_death_rate", Column::CrudeDeathRate),
+    ("crude_growth_rate", Column::CrudeGrowthRate),
+    ("mean_age", Column::MeanAge),
+    ("median_age", Column::MedianAge),
+    ("youth_dependency", Column::YouthDependency),
+    ("old_age_dependency", Column::OldAgeDependency),
+    ("total_dependency", Column::TotalDependency),
+    ("sex_ratio", Column::SexRatio),
+    ("sex_ratios", Column::SexRatios),
+    ("male_life_expectancy", Column::MaleLifeExpectancy),
+    ("female_life_expectancy", Column::FemaleLifeExpectancy),
+    ("realized_tfr", Column::RealizedTfr),
+];
+
+impl Column {
+    /// A column by name. Ages are `ages` or `sex_ages`, optionally followed by `:WIDTH` and
+    /// `:LAST`, as in `ages:5:85`. Shares are `share:` and a band, as in `share:15-49` or
+    /// `share:65+`.
+    pub fn parse(name: &str) -> Result<Column, ExportError> {
+        let name = name.trim().to_lowercase();
+        if let Some(&(_, column)) = NAMED.iter().find(|&&(named, _)| named == name) {
+            return Ok(column);
+        }
+        if let Some(share) = name.strip_prefix("share:") {
+            return share.parse().map(Column::Share).map_err(ExportError::new);
+        }
+        let mut parts = name.split(':');
+        let by_sex = match parts.next() {
+            Some("ages") => false,
+            Some("sex_ages") => true,
+            _ => return Err(ExportError::new(format!("There's no column called {}.", name))),
+        };
+        let mut number = |default: usize| match parts.next() {
+            None => Ok(default),
+            Some(part) => part.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| {
+                ExportError::new(format!("{} in {} isn't a positive whole number.", part, name))
+            }),
+        };
+        let width = number(1)?;
+        let last = number(DEFAULT_LAST_AGE)?;
+        Ok(Column::Ages(Ages { width, last, by_sex }))
+    }
+
+    /// Whether the column comes from the year's `Indicators`.
+    pub fn is_indicator(&self) -> bool {
+        !matches!(*self,
+            Column::Year | Column::Total | Column::Males | Column::Females | Column::Births |
+            Column::MaleBirths | Column::FemaleBirths | Column::Deaths | Column::MaleDeaths |
+            Column::FemaleDeaths | Column::Immigrants | Column::Emigrants | Column::Ages(_))
+    }
+
+    fn names(&self) -> Vec<String> {
+        match *self {
+            Column::Ages(ages) if ages.by_sex => ages.group_names().iter()
+                .flat_map(|name| vec![format!("males_{}", name), format!("females_{}", name)])
+                .collect(),
+            Column::Ages(ages) => ages.group_names(),
+            Column::SexRatios => (0..SEX_RATIO_LAST / SEX_RATIO_GROUP + 1).map(|group| {
+                let start = group * SEX_RATIO_GROUP;
+                if start >= SEX_RATIO_LAST { format!("sex_ratio_{}_plus", start) }
+                else { format!("sex_ratio_{}_{}", start, start + SEX_RATIO_GROUP - 1) }
+            }).collect(),
+            Column::Share(band) => vec![match band.end {
+                Some(end) => format!("share_{}_{}", band.start, end),
+                None => format!("share_{}_plus", band.start),
+            }],
+            column => {
+                let &(name, _) = NAMED.iter().find(|&&(_, named)| named == column).unwrap();
+                vec![String::from(name)]
+            },
+        }
+    }
+}
+
+/// A value in a row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<C> {
+    Year(i32),
+    Count(C),
+    /// An indicator, if it's defined.
+    Real(Option<f64>),
+}
+
+impl<C: Count> fmt::Display for Value<C> {
+    /// Undefined indicators are empty.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Year(year) => write!(f, "{}", year),
+            Value::Count(count) => write!(f, "{:?}", count),
+            Value::Real(Some(value)) => write!(f, "{:?}", value),
+            Value::Real(None) => Ok(()),
+        }
+    }
+}
+
+/// Everything a row can say about a year.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YearRecord<C> {
+    pub year: i32,
+    /// Males and females by completed age at the end of the year; see `completed_ages`.
+    pub distribution: Vec<(C, C)>,
+    pub births: (C, C),
+    pub deaths: (C, C),
+    pub immigrants: (C, C),
+    pub emigrants: (C, C),
+    /// The year's indicators, if any columns need them.
+    pub indicators: Option<Indicators>,
+}
+
+/// The columns of an export, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns(pub Vec<Column>);
+
+impl Columns {
+    /// Columns from a comma-separated list of names, as in `year,total,births,ages:5`.
+    pub fn parse(list: &str) -> Result<Columns, ExportError> {
+        let columns = list.split(',').map(Column::parse).collect::<Result<Vec<_>, _>>()?;
+        Ok(Columns(columns))
+    }
+
+    /// The names of the columns, with each age group named separately.
+    pub fn names(&self) -> Vec<String> {
+        self.0.iter().flat_map(Column::names).collect()
+    }
+
+    /// The bands to compute indicators with, or None if no column needs them.
+    pub fn indicator_bands(&self) -> Option<Vec<AgeBand>> {
+        if !self.0.iter().any(Column::is_indicator) { return None; }
+        Some(self.0.iter().filter_map(|column| match *column {
+            Column::Share(band) => Some(band),
+            _ => None,
+        }).collect())
+    }
+
+    pub fn values<C: Count>(&self, record: &YearRecord<C>) -> Vec<Value<C>> {
+        let sum = |(males, females): (C, C)| males.plus(females);
+        let males = C::total(record.distribution.iter().map(|&(males, _)| males));
+        let females = C::total(record.distribution.iter().map(|&(_, females)| females));
+        let indicators = record.indicators.as_ref();
+        let indicator = |value: fn(&Indicators) -> Option<f64>| {
+            Value::Real(indicators.and_then(value))
+        };
+        let mut values = Vec::new();
+        for column in self.0.iter() {
+            let count = match *column {
+                Column::Year => { values.push(Value::Year(record.year)); continue },
+                Column::Total => males.plus(females),
+                Column::Males => males,
+                Column::Females => females,
+                Column::Births => sum(record.births),
+                Column::MaleBirths => record.births.0,
+                Column::FemaleBirths => record.births.1,
+                Column::Deaths => sum(record.deaths),
+                Column::MaleDeaths => record.deaths.0,
+                Column::FemaleDeaths => record.deaths.1,
+                Column::Immigrants => sum(record.immigrants),
+                Column::Emigrants => sum(record.emigrants),
+                Column::Ages(ages) => {
+                    let mut groups = vec![(C::default(), C::default()); ages.groups()];
+                    for (age, &(males, females)) in record.distribution.iter().enumerate() {
+                        let group = &mut groups[ages.group_of(age)];
+                        *group = (group.0.plus(males), group.1.plus(females));
+                    }
+                    for group in groups {
+                        if ages.by_sex {
+                            values.push(Value::Count(group.0));
+                            values.push(Value::Count(group.1));
+                        } else {
+                            values.push(Value::Count(sum(group)));
+                        }
+                    }
+                    continue
+                },
+                Column::SexRatios => {
+                    let groups = column.names().len();
+                    values.extend((0..groups).map(|group| Value::Real(indicators.and_then(|i| {
+                        i.sex_ratios.get(group).cloned().and_then(|ratio| ratio)
+                    }))));
+                    continue
+                },
+                Column::Share(band) => {
+                    values.push(Value::Real(indicators.and_then(|i| {
+                        i.shares.iter().find(|&&(shared, _)| shared == band)
+                            .and_then(|&(_, share)| share)
+                    })));
+                    continue
+                },
+                column => {
+                    values.push(indicator(match column {
+                        Column::CrudeBirthRate => |i| i.crude_birth_rate,
+                        Column::CrudeDeathRate => |i| i.crude_death_rate,
+                        Column::CrudeGrowthRate => |i| i.crude_growth_rate,
+                        Column::MeanAge => |i| i.mean_age,
+                        Column::MedianAge => |i| i.median_age,
+                        Column::YouthDependency => |i| i.youth_dependency,
+                        Column::OldAgeDependency => |i| i.old_age_dependency,
+                        Column::TotalDependency => |i| i.total_dependency,
+                        Column::SexRatio => |i| i.sex_ratio,
+                        Column::MaleLifeExpectancy => |i| Some(i.male_life_expectancy),
+                        Column::FemaleLifeExpectancy => |i| Some(i.female_life_expectancy),
+                        Column::RealizedTfr => |i| i.realized_tfr,
+                        _ => unreachable!(),
+                    }));
+                    continue
+                },
+            };
+            values.push(Value::Count(count));
+        }
+        values
+    }
+}
+
+impl Default for Columns {
+    /// The year, totals by sex, births and deaths.
+    fn default() -> Columns {
+        Columns(vec![
+            Column::Year, Column::Total, Column::Males, Column::Females, Column::Births,
+            Column::Deaths,
+        ])
+    }
+}
+
+/// Somewhere rows go.
+trait Sink<C>: Send {
+    fn write(&mut self, row: &[Value<C>]) -> Result<(), ExportError>;
+    fn finish(&mut self) -> Result<(), ExportError>;
+}
+
+struct CsvSink<W: Write> { writer: W }
+
+impl<W: Write> CsvSink<W> {
+    fn new(mut writer: W, names: &[String]) -> Result<CsvSink<W>, ExportError> {
+        writeln!(writer, "{}", names.join(",")).map_err(|e| problem(&e))?;
+        Ok(CsvSink { writer })
+    }
+}
+
+impl<C: Count, W: Write + Send> Sink<C> for CsvSink<W> {
+    fn write(&mut self, row: &[Value<C>]) -> Result<(), ExportError> {
+        let fields: Vec<String> = row.iter().map(Value::to_string).collect();
+        writeln!(self.writer, "{}", fields.join(",")).map_err(|e| problem(&e))
+    }
+
+    fn finish(&mut self) -> Result<(), ExportError> {
+        self.writer.flush().map_err(|e| problem(&e))
+    }
+}
+
+struct JsonLinesSink<W: Write> { writer: W, names: Vec<String> }
+
+impl<C: Count, W: Write + Send> Sink<C> for JsonLinesSink<W> {
+    fn write(&mut self, row: &[Value<C>]) -> Result<(), ExportError> {
+        let fields: Vec<String> = self.names.iter().zip(row.iter())
+            .map(|(name, value)| match *value {
+                Value::Real(None) => format!("\"{}\":null", name),
+                value => format!("\"{}\":{}", name, value),
+            })
+            .collect();
+        writeln!(self.writer, "{{{}}}", fields.join(",")).map_err(|e| problem(&e))
+    }
+
+    fn finish(&mut self) -> Result<(), ExportError> {
+        self.writer.flush().map_err(|e| problem(&e))
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_sink {
+    use std::io::Write;
+    use std::sync::Arc;
+
+    use parquet::data_type::{DoubleType, Int32Type, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use super::{problem, ExportError, Sink, Value, ROW_GROUP_SIZE};
+    use population::count::Count;
+
+    /// What a column holds.
+    #[derive(Clone, Copy, PartialEq)]
+    pub enum Kind {
+        Year,
+        Count,
+        /// Indicators, which can be missing.
+        Real,
+    }
+
+    pub struct ParquetSink<C, W: Write + Send> {
+        writer: Option<SerializedFileWriter<W>>,
+        rows: Vec<Vec<Value<C>>>,
+    }
+
+    impl<C: Count, W: Write + Send> ParquetSink<C, W> {
+        pub fn new(writer: W, names: &[String], kinds: &[Kind])
+            -> Result<ParquetSink<C, W>, ExportError>
+        {
+            let count_type = if C::WHOLE { "REQUIRED INT64" } else { "REQUIRED DOUBLE" };
+            let fields: Vec<String> = names.iter().zip(kinds.iter())
+                .map(|(name, &kind)| format!("{} {};", match kind {
+                    Kind::Year => "REQUIRED INT32",
+                    Kind::Count => count_type,
+                    Kind::Real => "OPTIONAL DOUBLE",
+                }, name))
+                .collect();
+            let schema = format!("message population {{ {} }}", fields.join(" "));
+            let schema = parse_message_type(&schema).map_err(|e| problem(&e))?;
+            let properties = Arc::new(WriterProperties::builder().build());
+            let writer = SerializedFileWriter::new(writer, Arc::new(schema), properties)
+                .map_err(|e| problem(&e))?;
+            Ok(ParquetSink { writer: Some(writer), rows: Vec::new() })
+        }
+
+        fn write_row_group(&mut self) -> Result<(), ExportError> {
+            if self.rows.is_empty() { return Ok(()); }
+            let writer = self.writer.as_mut().expect("the Parquet file is already closed");
+            let mut group = writer.next_row_group().map_err(|e| problem(&e))?;
+            let mut index = 0;
+            while let Some(mut column) = group.next_column().map_err(|e| problem(&e))? {
+                let values = self.rows.iter().map(|row| row[index]);
+                let written = match self.rows[0][index] {
+                    Value::Year(_) => {
+                        let years: Vec<i32> = values.map(|value| match value {
+                            Value::Year(year) => year,
+                            _ => unreachable!(),
+                        }).collect();
+                        column.typed::<Int32Type>().write_batch(&years, None, None)
+                    },
+                    Value::Count(_) if C::WHOLE => {
+                        let counts = values.map(|value| match value {
+                            Value::Count(count) => count.to_trials()
+                                .filter(|&count| count <= i64::MAX as u64)
+                                .map(|count| count as i64)
+                                .ok_or_else(|| ExportError::new(format!(
+                                    "{:?} is too large for a Parquet integer.", count))),
+                            _ => unreachable!(),
+                        }).collect::<Result<Vec<i64>, _>>()?;
+                        column.typed::<Int64Type>().write_batch(&counts, None, None)
+                    },
+                    Value::Count(_) => {
+                        let counts: Vec<f64> = values.map(|value| match value {
+                            Value::Count(count) => count.to_f64(),
+                            _ => unreachable!(),
+                        }).collect();
+                        column.typed::<DoubleType>().write_batch(&counts, None, None)
+                    },
+                    Value::Real(_) => {
+                        let reals: Vec<Option<f64>> = values.map(|value| match value {
+                            Value::Real(real) => real,
+                            _ => unreachable!(),
+                        }).collect();
+                        let levels: Vec<i16> = reals.iter().map(|real| real.is_some() as i16)
+                            .collect();
+                        let defined: Vec<f64> = reals.iter().filter_map(|&real| real).collect();
+                        column.typed::<DoubleType>().write_batch(&defined, Some(&levels), None)
+                    },
+                };
+                written.map_err(|e| problem(&e))?;
+                column.close().map_err(|e| problem(&e))?;
+                index += 1;
+            }
+            group.close().map_err(|e| problem(&e))?;
+            self.rows.clear();
+            Ok(())
+        }
+    }
+
+    impl<C: Count, W: Write + Send> Sink<C> for ParquetSink<C, W> {
+        fn write(&mut self, row: &[Value<C>]) -> Result<(), ExportError> {
+            self.rows.push(row.to_vec());
+            if self.rows.len() >= ROW_GROUP_SIZE { self.write_row_group()?; }
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), ExportError> {
+            self.write_row_group()?;
+            if let Some(writer) = self.writer.take() {
+                writer.close().map_err(|e| problem(&e))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes rows in a format, as they're recorded.
+pub struct Exporter<C> {
+    columns: Columns,
+    sink: Box<dyn Sink<C>>,
+    rows: usize,
+}
+
+impl<C: Count> Exporter<C> {
+    /// Export to a file, in the format its extension asks for.
+    pub fn create<P: AsRef<Path>>(path: P, columns: Columns) -> Result<Exporter<C>, ExportError> {
+        let path = path.as_ref();
+        let format = Format::of_path(path).ok_or_else(|| ExportError::new(format!(
+            "{} should end in .csv, .jsonl or .parquet.", path.display())))?;
+        let file = File::create(path).map_err(|e| {
+            ExportError::new(format!("Couldn't create {}: {}", path.display(), e))
+        })?;
+        Exporter::new(BufWriter::new(file), format, columns)
+    }
+
+    pub fn new<W: Write + Send + 'static>(writer: W, format: Format, columns: Columns)
+        -> Result<Exporter<C>, ExportError>
+    {
+        let names = columns.names();
+        let mut duplicates = names.clone();
+        duplicates.sort();
+        duplicates.dedup();
+        if duplicates.len() != names.len() {
+            return Err(ExportError::new("The same column is asked for more than once."));
+        }
+        let sink: Box<dyn Sink<C>> = match format {
+            Format::Csv => Box::new(CsvSink::new(writer, &names)?),
+            Format::JsonLines => Box::new(JsonLinesSink { writer, names }),
+            Format::Parquet => parquet(writer, &columns)?,
+        };
+        Ok(Exporter { columns, sink, rows: 0 })
+    }
+
+    pub fn record(&mut self, record: &YearRecord<C>) -> Result<(), ExportError> {
+        self.sink.write(&self.columns.values(record))?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Finish writing, and return the number of rows.
+    pub fn finish(mut self) -> Result<usize, ExportError> {
+        self.sink.finish()?;
+        Ok(self.rows)
+    }
+
+    /// An observer that records each year a population completes.
+    pub fn observer(self) -> ExportObserver<C> {
+        let zero = (C::default(), C::default());
+        ExportObserver(Arc::new(Mutex::new(Recording {
+            bands: self.columns.indicator_bands(),
+            exporter: Some(self),
+            births: zero,
+            deaths: zero,
+            error: None,
+        })))
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn parquet<C: Count, W: Write + Send + 'static>(writer: W, columns: &Columns)
+    -> Result<Box<dyn Sink<C>>, ExportError>
+{
+    use self::parquet_sink::Kind;
+    let kinds: Vec<Kind> = columns.0.iter()
+        .flat_map(|column| {
+            let kind = if *column == Column::Year { Kind::Year }
+                else if column.is_indicator() { Kind::Real }
+                else { Kind::Count };
+            vec![kind; column.names().len()]
+        })
+        .collect();
+    Ok(Box::new(parquet_sink::ParquetSink::new(writer, &columns.names(), &kinds)?))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn parquet<C: Count, W: Write + Send + 'static>(_writer: W, _columns: &Columns)
+    -> Result<Box<dyn Sink<C>>, ExportError>
+{
+    Err(ExportError::new("Parquet export needs PopSim built with the parquet feature."))
+}
+
+impl<C> fmt::Debug for Exporter<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Exporter({:?}, {} rows)", self.columns, self.rows)
+    }
+}
+
+struct Recording<C> {
+    exporter: Option<Exporter<C>>,
+    /// The bands for indicators, if the columns need them.
+    bands: Option<Vec<AgeBand>>,
+    births: (C, C),
+    deaths: (C, C),
+    /// The first error, which stops the recording.
+    error: Option<ExportError>,
+}
+
+/// An exporter attached to a population. Keep a clone to `finish` it after the run.
+#[derive(Clone)]
+pub struct ExportObserver<C>(Arc<Mutex<Recording<C>>>);
+
+impl<C: Count> ExportObserver<C> {
+    /// Finish writing, and return the number of rows or the first error since the start.
+    pub fn finish(&self) -> Result<usize, ExportError> {
+        let mut recording = self.0.lock().unwrap();
+        if let Some(error) = recording.error.take() { return Err(error); }
+        match recording.exporter.take() {
+            Some(exporter) => exporter.finish(),
+            None => Err(ExportError::new("The export has already finished.")),
+        }
+    }
+}
+
+impl<C: Count> Observer<C> for ExportObserver<C> {
+    fn on_year_start(&mut self, _pop: &Population<C>) {
+        let mut recording = self.0.lock().unwrap();
+        recording.births = (C::default(), C::default());
+        recording.deaths = (C::default(), C::default());
+    }
+
+    fn on_births(&mut self, _clock: &Clock, males: C, females: C) {
+        let mut recording = self.0.lock().unwrap();
+        let births = recording.births;
+        recording.births = (births.0.plus(males), births.1.plus(females));
+    }
+
+    fn on_cohort_deaths(&mut self, _clock: &Clock, _cohort: &Cohort<C>, males: C, females: C) {
+        let mut recording = self.0.lock().unwrap();
+        let deaths = recording.deaths;
+        recording.deaths = (deaths.0.plus(males), deaths.1.plus(females));
+    }
+
+    fn on_year_end(&mut self, pop: &Population<C>) {
+        let mut recording = self.0.lock().unwrap();
+        let zero = (C::default(), C::default());
+        let record = YearRecord {
+            year: pop.current_year() - 1,
+            distribution: completed_ages(&pop.age_sex_distribution()),
+            births: recording.births,
+            deaths: recording.deaths,
+            immigrants: zero,
+            emigrants: zero,
+            indicators: recording.bands.as_ref().map(|bands| pop.indicators(bands)),
+        };
+        if recording.error.is_some() { return; }
+        let result = match recording.exporter.as_mut() {
+            Some(exporter) => exporter.record(&record),
+            None => Ok(()),
+        };
+        if let Err(error) = result { recording.error = Some(error); }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use serde_json;
+
+    use super::{Columns, Exporter, Format};
+    use population::{completed_ages, Population};
+
+    /// A writer whose output can be read after it's been handed over.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn export(format: Format, columns: &str, years: usize) -> (String, Population) {
+        let output = Shared::default();
+        let columns = Columns::parse(columns).unwrap();
+        let export = Exporter::new(output.clone(), format, columns).unwrap().observer();
+        let mut pop = Population::new(vec![ ( 1_000, 1_000 ); 60 ])
+            .with_terminal_age(80)
+            .with_observer(export.clone());
+        for _ in 0..years {
+            pop = pop.advance_year();
+        }
+        assert_eq!(export.finish().unwrap(), years);
+        (output.text(), pop)
+    }
+
+    #[test]
+    fn csv_rows_follow_the_run() {
+        let (csv, pop) =
+            export(Format::Csv, "year,total,males,male_births,female_births,deaths,ages:10:70", 25);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 26);
+        assert_eq!(lines[0], "year,total,males,male_births,female_births,deaths,age_0_9,\
+            age_10_19,age_20_29,age_30_39,age_40_49,age_50_59,age_60_69,age_70_plus");
+
+        let last: Vec<usize> = lines[25].split(',').map(|field| field.parse().unwrap()).collect();
+        assert_eq!(last[0], 24);
+        assert_eq!(last[1], pop.total_pop());
+        assert_eq!(last[2], pop.total_male());
+        assert_eq!(last[3] + last[4], pop.births());
+        assert_eq!(last[5], pop.deaths());
+        assert_eq!(last[6..].iter().sum::<usize>(), pop.total_pop());
+        let groups = pop.age_groups(10);
+        assert_eq!(last[7], groups[1]);
+    }
+
+    #[test]
+    fn json_lines_name_every_field() {
+        let (jsonl, pop) = export(Format::JsonLines, "year,births,sex_ages:20:60,emigrants", 5);
+        let rows: Vec<serde_json::Value> =
+            jsonl.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(rows.len(), 5);
+        let last = &rows[4];
+        assert_eq!(last["year"], 4);
+        assert_eq!(last["births"], pop.births());
+        assert_eq!(last["emigrants"], 0);
+        let distribution = completed_ages(&pop.age_sex_distribution());
+        let women: usize = distribution[40..60].iter().map(|&(_, f)| f).sum();
+        let old_men: usize = distribution[60..].iter().map(|&(m, _)| m).sum();
+        assert_eq!(last["females_age_40_59"], women);
+        assert_eq!(last["males_age_60_plus"], old_men);
+    }
+
+    #[test]
+    fn indicators_are_columns() {
+        let (csv, pop) = export(Format::Csv, "year,realized_tfr,sex_ratios,share:15-49", 3);
+        let lines: Vec<&str> = csv.lines().collect();
+        let names: Vec<&str> = lines[0].split(',').collect();
+        assert_eq!(names.len(), 21);
+        assert_eq!((names[2], names[19], names[20]),
+                   ("sex_ratio_0_4", "sex_ratio_85_plus", "share_15_49"));
+        let last: Vec<&str> = lines[3].split(',').collect();
+        let indicators = pop.indicators(&["15-49".parse().unwrap()]);
+        assert_eq!(last[1].parse::<f64>().unwrap(), indicators.realized_tfr.unwrap());
+        assert_eq!(last[20].parse::<f64>().unwrap(), indicators.shares[0].1.unwrap());
+        // No one is 85 or older.
+        assert_eq!(last[19], "");
+
+        let (jsonl, _) = export(Format::JsonLines, "year,sex_ratios,median_age", 1);
+        let row: serde_json::Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert!(row["sex_ratio_85_plus"].is_null());
+        assert!(row["median_age"].as_f64().unwrap() > 20.0);
+    }
+
+    #[test]
+    fn ages_and_shares_agree() {
+        let (csv, _) = export(Format::Csv, "year,total,ages:1:3,share:0-0,share:1-1,share:3+", 2);
+        for line in csv.lines().skip(1) {
+            let row: Vec<f64> = line.split(',').map(|field| field.parse().unwrap()).collect();
+            let total = row[1];
+            for &(ages, share) in [(2, 6), (3, 7), (5, 8)].iter() {
+                assert!((row[ages] / total - row[share]).abs() < 1e-12, "{}", line);
+            }
+            assert!(row[2] > 0.0);
+        }
+    }
+
+    #[test]
+    fn bad_columns_are_refused() {
+        assert!(Columns::parse("year,population").is_err());
+        assert!(Columns::parse("ages:0").is_err());
+        assert!(Columns::parse("share:65").is_err());
+        let twice = Columns::parse("year,year").unwrap();
+        assert!(Exporter::<usize>::new(Vec::new(), Format::Csv, twice).is_err());
+        assert_eq!(Format::of_path("run.NDJSON"), Some(Format::JsonLines));
+        assert_eq!(Format::of_path("run.txt"), None);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_keeps_whole_and_real_counts() {
+        use std::env::temp_dir;
+        use std::fs::File;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::RowAccessor;
+        use super::ROW_GROUP_SIZE;
+
+        let years = ROW_GROUP_SIZE + 10;
+        let path = temp_dir().join("popsim-export.parquet");
+        let export = Exporter::create(&path, Columns::parse("year,total,ages:50").unwrap())
+            .unwrap()
+            .observer();
+        let mut pop = Population::new(vec![ ( 100, 100 ); 30 ]).with_observer(export.clone());
+        for _ in 0..years {
+            pop = pop.advance_year();
+        }
+        export.finish().unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+        let rows: Vec<_> = reader.get_row_iter(None).unwrap().map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len(), years);
+        let last = &rows[years - 1];
+        assert_eq!(last.get_int(0).unwrap(), years as i32 - 1);
+        assert_eq!(last.get_long(1).unwrap() as usize, pop.total_pop());
+
+        let path = temp_dir().join("popsim-export-real.parquet");
+        let columns = Columns::parse("year,total,sex_ratios").unwrap();
+        let export = Exporter::create(&path, columns).unwrap().observer();
+        let mut pop = Population::<f64>::from_counts(vec![ ( 10.5, 10.5 ); 30 ])
+            .with_observer(export.clone());
+        for _ in 0..5 {
+            pop = pop.advance_year();
+        }
+        export.finish().unwrap();
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let last = reader.get_row_iter(None).unwrap().last().unwrap().unwrap();
+        // Summed by age rather than by cohort, so it can differ in the last place.
+        assert!((last.get_double(1).unwrap() - pop.total_pop()).abs() < 1e-9);
+        // Sex ratios are missing above the oldest cohort.
+        assert!(last.get_double(2).unwrap() > 0.0);
+        assert!(last.get_double(19).is_err());
+    }
+}
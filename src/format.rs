@@ -0,0 +1,27 @@
+//! Formatting shared by the dashboard and the report.
+
+/// A count in whole numbers, with commas between the thousands.
+pub(crate) fn whole(count: f64) -> String {
+    let digits = format!("{:.0}", count);
+    let mut grouped = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index) % 3 == 0 && digit.is_ascii_digit() {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::whole;
+
+    #[test]
+    fn thousands_are_grouped() {
+        assert_eq!(whole(1234567.0), "1,234,567");
+        assert_eq!(whole(999.0), "999");
+        assert_eq!(whole(-1234.4), "-1,234");
+    }
+}
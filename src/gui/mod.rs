@@ -0,0 +1,77 @@
+/*!
+A window that shows a simulation as it runs: an animated age pyramid and a chart of the total
+population, with controls to play, pause and step through years, and sliders for the fertility
+rate and sex ratio that change the running population.
+
+The window is a web view of a page embedded in the binary, so it needs no network access. The page
+sends `Command`s as JSON through `external.invoke`, and the `Session` answers with a script that
+draws the new `Frame`. While playing, a thread runs a year at a time and sends each frame to the
+page.
+
+The window needs the `gui` feature, which is on by default. The `Session` it drives is in the
+`session` module, which doesn't.
+*/
+
+#[cfg(feature = "gui")]
+pub use self::window::show;
+
+#[cfg(feature = "gui")]
+mod window {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_json;
+    use web_view::{self, Content, MyUnique, WebView};
+
+    use population::Population;
+    use population::count::Count;
+    use session::Session;
+
+    const PAGE: &str = include_str!("viewer.html");
+    /// How often a paused session checks whether it's been set playing.
+    const IDLE: Duration = Duration::from_millis(50);
+
+    type Shared<C> = Arc<Mutex<Session<C>>>;
+
+    /// Open the viewer on a population, and run it until the window is closed.
+    pub fn show<C: Count>(pop: Population<C>) {
+        let session = Arc::new(Mutex::new(Session::new(pop)));
+        let open = Arc::new(AtomicBool::new(true));
+        let (ticker, ticking) = (session.clone(), open.clone());
+
+        web_view::run(
+            "PopSim",
+            Content::Html(PAGE),
+            Some((1000, 720)),
+            true,
+            false,
+            move |webview: MyUnique<WebView<Shared<C>>>| {
+                thread::spawn(move || while ticking.load(Ordering::SeqCst) {
+                    let (script, wait) = {
+                        let mut session = ticker.lock().unwrap();
+                        let script = if session.tick() { Some(session.render()) } else { None };
+                        (script, if session.is_playing() { session.interval() } else { IDLE })
+                    };
+                    if let Some(script) = script {
+                        webview.dispatch(move |webview, _| { webview.eval(&script); });
+                    }
+                    thread::sleep(wait);
+                });
+            },
+            |webview, message, session: &mut Shared<C>| {
+                let mut session = session.lock().unwrap();
+                let script = match session.handle(message) {
+                    Ok(()) => session.render(),
+                    Err(e) => {
+                        format!("showError({})", serde_json::to_string(&e.to_string()).unwrap())
+                    },
+                };
+                webview.eval(&script);
+            },
+            session,
+        );
+        open.store(false, Ordering::SeqCst);
+    }
+}
@@ -23,6 +23,30 @@
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
+extern crate serde_yaml;
+extern crate serde_json;
+extern crate bincode;
+extern crate crossterm;
+extern crate resvg;
+#[cfg(feature = "parquet")]
+extern crate parquet;
+#[cfg(feature = "gui")]
+extern crate web_view;
 
 pub mod population;
-mod skew_normal;
+pub mod skew_normal;
+pub mod random;
+pub mod scenario;
+pub mod census;
+pub mod chart;
+pub mod ensemble;
+pub mod tui;
+pub mod export;
+pub mod gui;
+pub mod session;
+pub mod report;
+pub mod sweep;
+pub mod sensitivity;
+pub mod compare;
+mod format;
+mod optimize;
@@ -1,27 +1,88 @@
 extern crate pop_sim;
+extern crate serde_json;
 
-use std::iter::repeat;
-use std::env::current_dir;
-use pop_sim::population::Population;
-//use serde_yaml;
+use std::env;
+use std::iter::{Peekable, Skip};
+use std::process;
 
-fn main() {
+use cli::Failure;
 
-    let mut path = current_dir().unwrap();
-    path.push("inpop.yml");
+mod cli;
 
-    let tens = repeat(100000).take(1);
+const USAGE: &str = "\
+Usage: PopSim [COMMAND] [OPTIONS]
 
-    let pop_: Vec<(usize, usize)> = tens.map(|x| (x, x)).collect();
+Commands:
+  run          Run a population, with checkpoints, exports and charts (the default)
+  report       Run a scenario and write an HTML page about it
+  indicators   Run a population and write its demographic indicators for each year
+  lifetable    Print the life table for a set of Heligman-Pollard parameters
+  sweep        Run a scenario across settings of its parameters, in parallel
+  sensitivity  Rank the parameters a scenario's outcomes depend on most
+  compare      Compare two runs or checkpoints year by year
+  fit          Fit Heligman-Pollard or fertility parameters to rates by age
+  validate     Check scenario files
+  convert      Rewrite a checkpoint in another format
+  help         Show this, or PopSim help COMMAND for a command's options
 
-    let mut pop = Population::new(pop_);
+PopSim with no command, or with options first, is PopSim run. Exits with 0 on success, 1 if a
+command fails, and 2 if its arguments are wrong.";
 
+type Arguments = Peekable<Skip<env::Args>>;
+type Command = fn(Arguments) -> Result<(), Failure>;
 
-    for _x in 0..1000 {
-        pop = pop.advance_year();
-        println!("{:?}", pop.total_pop());
-    }
-    println!("Males: {:?}", pop.total_male());
-    println!("Females: {:?}", pop.total_female());
+fn command(name: &str) -> Option<(Command, &'static str)> {
+    Some(match name {
+        "run" => (cli::run::main, cli::run::USAGE),
+        "report" => (cli::report::main, cli::report::USAGE),
+        "indicators" => (cli::indicators::main, cli::indicators::USAGE),
+        "lifetable" => (cli::lifetable::main, cli::lifetable::USAGE),
+        "sweep" => (cli::sweep::main, cli::sweep::USAGE),
+        "sensitivity" => (cli::sensitivity::main, cli::sensitivity::USAGE),
+        "compare" => (cli::compare::main, cli::compare::USAGE),
+        "fit" => (cli::fit::main, cli::fit::USAGE),
+        "validate" => (cli::validate::main, cli::validate::USAGE),
+        "convert" => (cli::convert::main, cli::convert::USAGE),
+        _ => return None,
+    })
+}
+
+fn unknown_command(name: &str) -> ! {
+    eprintln!("There's no command called {}.\n\n{}", name, USAGE);
+    process::exit(2);
+}
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    let first = args.peek().cloned();
+    let (run, usage) = match first.as_deref() {
+        Some("help") | Some("-h") | Some("--help") => {
+            match args.nth(1) {
+                Some(name) => match command(&name) {
+                    Some((_, usage)) => println!("{}", usage),
+                    None => unknown_command(&name),
+                },
+                None => println!("{}", USAGE),
+            }
+            return;
+        },
+        Some(name) if !name.starts_with('-') => match command(name) {
+            Some(found) => { args.next(); found },
+            None => unknown_command(name),
+        },
+        _ => (cli::run::main as Command, cli::run::USAGE),
+    };
 
+    match run(args) {
+        Ok(()) => {},
+        Err(Failure::Help) => println!("{}", usage),
+        Err(Failure::Usage(problem)) => {
+            eprintln!("{}\n\n{}", problem, usage);
+            process::exit(2);
+        },
+        Err(Failure::Failed(problem)) => {
+            eprintln!("{}", problem);
+            process::exit(1);
+        },
+    }
 }
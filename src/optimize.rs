@@ -0,0 +1,100 @@
+//! Small, dependency-free numerical routines shared by the fitting code.
+
+/// Minimize `f` with the Nelder-Mead downhill simplex method.
+///
+/// `start` is the initial guess and `step` the initial size of the simplex along each axis. The
+/// search stops once the spread of function values across the simplex drops below `tolerance`,
+/// or after `max_iterations`. Returns the best point found and its value.
+pub fn nelder_mead<F>(
+    f: F,
+    start: &[f64],
+    step: &[f64],
+    tolerance: f64,
+    max_iterations: usize,
+) -> (Vec<f64>, f64)
+    where F: Fn(&[f64]) -> f64
+{
+    let n = start.len();
+    let mut simplex: Vec<Vec<f64>> = (0..n + 1).map(|i| {
+        let mut point = start.to_vec();
+        if i > 0 { point[i - 1] += step[i - 1]; }
+        point
+    }).collect();
+    let mut values: Vec<f64> = simplex.iter().map(|p| nan_to_inf(f(p))).collect();
+
+    for _ in 0..max_iterations {
+        let mut order: Vec<usize> = (0..n + 1).collect();
+        order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap());
+        simplex = order.iter().map(|&i| simplex[i].clone()).collect();
+        values = order.iter().map(|&i| values[i]).collect();
+
+        if (values[n] - values[0]).abs() <= tolerance { break; }
+
+        let centroid: Vec<f64> = (0..n).map(|d| {
+            simplex[..n].iter().map(|p| p[d]).sum::<f64>() / n as f64
+        }).collect();
+        let towards = |scale: f64| -> Vec<f64> {
+            (0..n).map(|d| centroid[d] + scale * (simplex[n][d] - centroid[d])).collect()
+        };
+
+        let reflected = towards(-1.0);
+        let reflected_value = nan_to_inf(f(&reflected));
+
+        if reflected_value < values[0] {
+            let expanded = towards(-2.0);
+            let expanded_value = nan_to_inf(f(&expanded));
+            if expanded_value < reflected_value {
+                simplex[n] = expanded;
+                values[n] = expanded_value;
+            } else {
+                simplex[n] = reflected;
+                values[n] = reflected_value;
+            }
+        } else if reflected_value < values[n - 1] {
+            simplex[n] = reflected;
+            values[n] = reflected_value;
+        } else {
+            let contracted = towards(0.5);
+            let contracted_value = nan_to_inf(f(&contracted));
+            if contracted_value < values[n] {
+                simplex[n] = contracted;
+                values[n] = contracted_value;
+            } else {
+                let best = simplex[0].clone();
+                for i in 1..n + 1 {
+                    simplex[i] = (0..n).map(|d| best[d] + 0.5 * (simplex[i][d] - best[d])).collect();
+                    values[i] = nan_to_inf(f(&simplex[i]));
+                }
+            }
+        }
+    }
+
+    let best = (0..n + 1)
+        .min_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap())
+        .unwrap();
+    (simplex[best].clone(), values[best])
+}
+
+/// Find a root of `f` in `[lower, upper]` by bisection. The endpoints must bracket a sign change.
+pub fn bisect<F>(f: F, mut lower: f64, mut upper: f64, tolerance: f64) -> f64
+    where F: Fn(f64) -> f64
+{
+    let mut f_lower = f(lower);
+    for _ in 0..200 {
+        let middle = 0.5 * (lower + upper);
+        if (upper - lower).abs() <= tolerance { return middle; }
+        let f_middle = f(middle);
+        if f_middle == 0.0 { return middle; }
+        if f_middle.signum() == f_lower.signum() {
+            lower = middle;
+            f_lower = f_middle;
+        } else {
+            upper = middle;
+        }
+    }
+    0.5 * (lower + upper)
+}
+
+fn nan_to_inf(x: f64) -> f64 {
+    if x.is_nan() { f64::INFINITY } else { x }
+}
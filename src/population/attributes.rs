@@ -9,13 +9,15 @@ pub struct AttributeData {
 }
 
 
+#[allow(dead_code)]
 impl AttributeData {
-    fn advance_year(&self, consequences: &HashMap<&str, Consequence>) -> (AttributeData, Consequence) {
+    fn advance_year(&self, _consequences: &HashMap<&str, Consequence>) -> (AttributeData, Consequence) {
         unimplemented!()
     }
 }
 
 
+#[allow(dead_code)]
 struct Consequence {
     years_remaining: u64,
 }
@@ -0,0 +1,444 @@
+/*!
+Saving a simulation part way through and resuming it later.
+
+A checkpoint holds a population's entire state: its cohorts, including the fractions of deaths
+each is carrying, the birth remainders, the clock, the schedule and the random number generator.
+Resuming from one continues the run exactly as if it had never stopped. The rate tables aren't
+saved; they're rebuilt from the schedule, with the same values.
+
+Checkpoints can be YAML, JSON or a compact binary encoding, chosen by file extension (`.yml` or
+`.yaml`, `.json`, anything else binary), though the YAML library can't write counts in `u128`.
+Every checkpoint records the format version it was written with. Text checkpoints without one are
+taken to be a bare `Population` as serialized before checkpoints existed, when each cohort carried
+its own copy of the rates; those are migrated on loading.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use bincode;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_yaml;
+
+use super::Population;
+use super::attributes::AttributeData;
+use super::clock::Clock;
+use super::cohort::Cohort;
+use super::cohort::fertility::Fertility;
+use super::cohort::heligman_pollard::HPMortalityModel;
+use super::count::Count;
+use super::observer::Observers;
+use super::schedule::{Schedule, Tables};
+
+/// The version checkpoints are written with.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Starts every binary checkpoint, ahead of its version.
+const MAGIC: &[u8; 8] = b"PopSimCk";
+
+#[derive(Debug)]
+pub struct CheckpointError { problem: String }
+
+impl CheckpointError {
+    pub fn new<S: Into<String>>(problem: S) -> CheckpointError {
+        CheckpointError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for CheckpointError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Yaml,
+    Json,
+    Binary,
+}
+
+impl Format {
+    pub fn of_path<P: AsRef<Path>>(path: P) -> Format {
+        match path.as_ref().extension().and_then(|extension| extension.to_str()) {
+            Some("yml") | Some("yaml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Binary,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Checkpoint<C = usize> {
+    pub version: u32,
+    pub population: Population<C>,
+}
+
+
+/// Just a text checkpoint's version, to tell which it holds before reading the rest.
+#[derive(Deserialize)]
+struct Version {
+    #[serde(default)]
+    version: Option<u32>,
+}
+
+/// A population as serialized before checkpoints had a version.
+#[derive(Deserialize)]
+struct LegacyPopulation {
+    cohorts: Vec<LegacyCohort>,
+    male_remainder: f64,
+    female_remainder: f64,
+    current_year: i32,
+}
+
+#[derive(Deserialize)]
+struct LegacyCohort {
+    members: LegacyMembers,
+    birth_year: i32,
+    attributes: Vec<AttributeData>,
+    fertility: Fertility,
+    male_mortality: HPMortalityModel,
+    female_mortality: HPMortalityModel,
+}
+
+#[derive(Deserialize)]
+struct LegacyMembers {
+    males: usize,
+    females: usize,
+    male_dying: f32,
+    female_dying: f32,
+}
+
+impl<C: Count + Serialize + DeserializeOwned> Checkpoint<C> {
+    pub fn new(population: Population<C>) -> Checkpoint<C> {
+        Checkpoint { version: FORMAT_VERSION, population }
+    }
+
+    pub fn to_bytes(&self, format: Format) -> Result<Vec<u8>, CheckpointError> {
+        encode(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8], format: Format) -> Result<Checkpoint<C>, CheckpointError> {
+        let problem = |e: &dyn fmt::Display| {
+            CheckpointError::new(format!("Couldn't read checkpoint: {}", e))
+        };
+        fn text<T: DeserializeOwned>(bytes: &[u8], format: Format) -> Result<T, String> {
+            match format {
+                Format::Yaml => serde_yaml::from_slice(bytes).map_err(|e| e.to_string()),
+                _ => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            }
+        }
+        let checkpoint: Checkpoint<C> = match format {
+            Format::Yaml | Format::Json => {
+                let version: Version = text(bytes, format).map_err(|e| problem(&e))?;
+                match version.version {
+                    Some(version) => {
+                        check_version(version)?;
+                        text(bytes, format).map_err(|e| problem(&e))?
+                    },
+                    None => {
+                        let legacy: LegacyPopulation =
+                            text(bytes, format).map_err(|e| problem(&e))?;
+                        return Ok(Checkpoint::new(legacy.migrate()?));
+                    },
+                }
+            },
+            Format::Binary => {
+                if !bytes.starts_with(MAGIC) || bytes.len() < MAGIC.len() + 4 {
+                    return Err(CheckpointError::new("This isn't a binary checkpoint."));
+                }
+                // The version comes first, so a newer layout can be refused before decoding it.
+                let mut version = [0; 4];
+                version.copy_from_slice(&bytes[MAGIC.len()..MAGIC.len() + 4]);
+                let version = u32::from_le_bytes(version);
+                check_version(version)?;
+                let mut payload = bytes[MAGIC.len()..].to_vec();
+                // Version 1 came before histories, and version 2 before the fertility exposure,
+                // each in turn the population's last field. A missing history is a single zero
+                // byte, and no exposure is eight.
+                if version < 2 { payload.push(0); }
+                if version < 3 { payload.extend_from_slice(&0f64.to_le_bytes()); }
+                bincode::deserialize(&payload).map_err(|e| problem(&e))?
+            }
+        };
+        checkpoint.migrate()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CheckpointError> {
+        write_file(path.as_ref(), self)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint<C>, CheckpointError> {
+        let path = path.as_ref();
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| {
+                CheckpointError::new(format!("Couldn't open {}: {}", path.display(), e))
+            })?;
+        Checkpoint::from_bytes(&bytes, Format::of_path(path))
+    }
+
+    /// Bring a checkpoint written by an older version up to date. Each format change adds a step
+    /// here, or to decoding where the layout changed. Version 2 added histories and version 3 the
+    /// fertility exposure, which text formats fill in by default.
+    fn migrate(self) -> Result<Checkpoint<C>, CheckpointError> {
+        check_version(self.version)?;
+        Ok(Checkpoint { version: FORMAT_VERSION, ..self })
+    }
+}
+
+impl LegacyPopulation {
+    /// Those populations always had the default TFR and sex ratio, whole counts and annual steps,
+    /// and every cohort carried the same rates, which become the schedule.
+    fn migrate<C: Count>(self) -> Result<Population<C>, CheckpointError> {
+        let schedule = match self.cohorts.first() {
+            Some(cohort) => Schedule::new(
+                cohort.fertility.clone(),
+                cohort.male_mortality.clone(),
+                cohort.female_mortality.clone()),
+            None => Schedule::default(),
+        };
+        let whole = |count: usize| C::from_real(count as f64).map(|(count, _)| count)
+            .ok_or_else(|| CheckpointError::new("A cohort is too large for this count type."));
+
+        let mut cohorts = Vec::with_capacity(self.cohorts.len());
+        for legacy in self.cohorts {
+            let mut cohort = Cohort::new
+                ( whole(legacy.members.males)?
+                , whole(legacy.members.females)?
+                , legacy.birth_year );
+            cohort.members.male_dying = legacy.members.male_dying;
+            cohort.members.female_dying = legacy.members.female_dying;
+            cohort.attributes = legacy.attributes;
+            cohorts.push(cohort);
+        }
+
+        Ok(Population { cohorts: cohorts.into_iter().collect()
+            , schedule
+            , tables: Tables::default()
+            , male_remainder: self.male_remainder
+            , female_remainder: self.female_remainder
+            , clock: Clock::new(self.current_year, 1)
+            , total_fertility_rate: 2.028
+            , sex_ratio: 0.5
+            , rng: None
+            , births: C::default()
+            , deaths: C::default()
+            , history: None
+            , fertility_exposure: 0.0
+            , observers: Observers::default() })
+    }
+}
+
+impl<C: Count + Serialize + DeserializeOwned> Population<C> {
+    /// Write this population's state to `path`; see `Checkpoint`.
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<(), CheckpointError> {
+        write_file(path.as_ref(), &CheckpointRef { version: FORMAT_VERSION, population: self })
+    }
+
+    /// Resume a population saved with `save_checkpoint`.
+    pub fn resume<P: AsRef<Path>>(path: P) -> Result<Population<C>, CheckpointError> {
+        Checkpoint::load(path).map(|checkpoint| checkpoint.population)
+    }
+}
+
+fn check_version(version: u32) -> Result<(), CheckpointError> {
+    if version > FORMAT_VERSION {
+        Err(CheckpointError::new(format!(
+            "This checkpoint is format version {}, newer than this PopSim understands ({}).",
+            version, FORMAT_VERSION)))
+    } else {
+        Ok(())
+    }
+}
+
+/// A checkpoint that borrows its population, so saving doesn't need to take or copy it.
+#[derive(Serialize)]
+struct CheckpointRef<'a, C: 'a> {
+    version: u32,
+    population: &'a Population<C>,
+}
+
+fn encode<T: Serialize>(checkpoint: &T, format: Format) -> Result<Vec<u8>, CheckpointError> {
+    let problem = |e: &dyn fmt::Display| {
+        CheckpointError::new(format!("Couldn't write checkpoint: {}", e))
+    };
+    match format {
+        Format::Yaml => serde_yaml::to_string(checkpoint)
+            .map(String::into_bytes)
+            .map_err(|e| problem(&e)),
+        Format::Json => serde_json::to_vec_pretty(checkpoint).map_err(|e| problem(&e)),
+        Format::Binary => {
+            let mut bytes = MAGIC.to_vec();
+            bincode::serialize_into(&mut bytes, checkpoint).map_err(|e| problem(&e))?;
+            Ok(bytes)
+        }
+    }
+}
+
+fn write_file<T: Serialize>(path: &Path, checkpoint: &T) -> Result<(), CheckpointError> {
+    let bytes = encode(checkpoint, Format::of_path(path))?;
+    File::create(path)
+        .and_then(|mut file| file.write_all(&bytes))
+        .map_err(|e| CheckpointError::new(format!("Couldn't write {}: {}", path.display(), e)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use serde_json;
+
+    use super::{Checkpoint, Format, FORMAT_VERSION};
+    use population::Population;
+    use population::schedule::Schedule;
+
+    fn state(pop: &Population) -> String {
+        serde_json::to_string(pop).unwrap()
+    }
+
+    fn resumes_exactly(make: fn() -> Population) {
+        let mut straight = make();
+        for _ in 0..45 { straight = straight.advance_year(); }
+        straight = straight.advance_step();
+
+        for &format in [Format::Yaml, Format::Json, Format::Binary].iter() {
+            let bytes = Checkpoint::new(straight_copy(&straight)).to_bytes(format).unwrap();
+            let mut resumed = Checkpoint::<usize>::from_bytes(&bytes, format).unwrap().population;
+            let mut expected = straight_copy(&straight);
+            assert_eq!(state(&resumed), state(&expected), "{:?}", format);
+            for _ in 0..40 {
+                resumed = resumed.advance_year();
+                expected = expected.advance_year();
+            }
+            assert_eq!(state(&resumed), state(&expected), "{:?}", format);
+        }
+    }
+
+    fn straight_copy(pop: &Population) -> Population {
+        serde_json::from_str(&state(pop)).unwrap()
+    }
+
+    #[test]
+    fn deterministic_runs_resume_exactly() {
+        resumes_exactly(|| Population::new(vec![ ( 3_333, 3_217 ); 40 ])
+            .with_steps_per_year(4)
+            .with_terminal_age(95));
+    }
+
+    #[test]
+    fn stochastic_runs_resume_exactly() {
+        resumes_exactly(|| Population::new(vec![ ( 700, 650 ); 40 ]).stochastic(77));
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let pop = Population::new(vec![ ( 1_000, 1_000 ); 10 ]).advance_year();
+        for name in [ "popsim-checkpoint.yml", "popsim-checkpoint.json", "popsim-checkpoint.bin" ]
+            .iter()
+        {
+            let path = temp_dir().join(name);
+            pop.save_checkpoint(&path).unwrap();
+            assert_eq!(state(&Population::resume(&path).unwrap()), state(&pop));
+        }
+    }
+
+    #[test]
+    fn migrates_unversioned_populations() {
+        let schedule = serde_json::to_value(Schedule::default()).unwrap();
+        let cohorts: Vec<serde_json::Value> = (0..30).map(|index| json_cohort(index, &schedule))
+            .collect();
+        let legacy = serde_json::json!({
+            "cohorts": cohorts,
+            "male_remainder": 0.0,
+            "female_remainder": 0.0,
+            "current_year": 0,
+        });
+        let mut migrated = Checkpoint::<usize>::from_bytes(
+            legacy.to_string().as_bytes(), Format::Json).unwrap().population;
+        let mut fresh = Population::new(vec![ ( 10_000, 10_000 ); 30 ]);
+        for _ in 0..50 {
+            migrated = migrated.advance_year();
+            fresh = fresh.advance_year();
+        }
+        assert_eq!(state(&migrated), state(&fresh));
+    }
+
+    fn json_cohort(index: i32, schedule: &serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "members":
+                { "males": 10_000, "females": 10_000, "male_dying": 0.0, "female_dying": 0.0 },
+            "birth_year": -(index + 1),
+            "attributes": [],
+            "fertility": schedule["fertility"],
+            "male_mortality": schedule["male_mortality"],
+            "female_mortality": schedule["female_mortality"],
+        })
+    }
+
+    #[test]
+    fn migrates_older_binaries() {
+        let pop = Population::new(vec![ ( 1_000, 900 ); 20 ]).stochastic(3).advance_year();
+        // Without the fertility exposure, and then without the history as well.
+        for &(version, missing) in [(2u32, 8), (1, 9)].iter() {
+            let mut bytes =
+                Checkpoint::new(straight_copy(&pop)).to_bytes(Format::Binary).unwrap();
+            let length = bytes.len() - missing;
+            bytes.truncate(length);
+            bytes[8..12].copy_from_slice(&version.to_le_bytes());
+            let migrated = Checkpoint::<usize>::from_bytes(&bytes, Format::Binary).unwrap();
+            assert_eq!(migrated.version, FORMAT_VERSION);
+            // The exposure starts again with the next year.
+            assert_eq!(state(&migrated.population.advance_year()),
+                       state(&straight_copy(&pop).advance_year()));
+        }
+    }
+
+    #[test]
+    fn keeps_histories() {
+        let pop = Population::new(vec![ ( 1_000, 900 ); 20 ]).with_history().advance_year();
+        let bytes = Checkpoint::new(straight_copy(&pop)).to_bytes(Format::Binary).unwrap();
+        let resumed = Checkpoint::<usize>::from_bytes(&bytes, Format::Binary).unwrap().population;
+        assert_eq!(resumed.history(), pop.history());
+        assert!(resumed.history().is_some());
+    }
+
+    #[test]
+    fn text_checkpoints_of_any_count() {
+        let pop = Population::<u128>::from_counts(vec![ ( 1 << 80, 1 << 80 ); 5 ]).advance_year();
+        for name in [ "popsim-wide-checkpoint.json", "popsim-wide-checkpoint.bin" ].iter() {
+            let path = temp_dir().join(name);
+            pop.save_checkpoint(&path).unwrap();
+            assert_eq!(Population::<u128>::resume(&path).unwrap().total_pop(), pop.total_pop());
+        }
+
+        // The problem itself, rather than that nothing matched.
+        let broken = "{ \"version\": 3, \"population\": { \"cohorts\": 7 } }";
+        let error = Checkpoint::<usize>::from_bytes(broken.as_bytes(), Format::Json).unwrap_err();
+        assert!(error.to_string().contains("invalid type"), "{}", error);
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let pop = Population::new(vec![ ( 10, 10 ) ]);
+        let mut checkpoint = Checkpoint::new(pop);
+        checkpoint.version = FORMAT_VERSION + 1;
+        for &format in [Format::Yaml, Format::Json, Format::Binary].iter() {
+            let bytes = checkpoint.to_bytes(format).unwrap();
+            assert!(Checkpoint::<usize>::from_bytes(&bytes, format).is_err());
+        }
+    }
+}
@@ -0,0 +1,71 @@
+/**
+Simulation time, as a year and a step within that year.
+
+Rates in the models are annual. When a year is divided into several steps, a mortality
+probability `q` becomes `1 - (1 - q)^(1/n)` per step, so that surviving all `n` steps is exactly
+as likely as surviving the year, and a fertility rate is spread evenly across the steps. With one
+step per year both conversions are the identity, and the simulation is the annual one.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    pub year: i32,
+    pub step: u32,
+    pub steps_per_year: u32,
+}
+
+impl Clock {
+    pub fn new(year: i32, steps_per_year: u32) -> Clock {
+        Clock { year, step: 0, steps_per_year: steps_per_year.max(1) }
+    }
+
+    /// Steps elapsed since the given birth, or `None` if it's still in the future.
+    pub fn steps_since(&self, birth_year: i32, birth_step: u32) -> Option<i64> {
+        let n = self.steps_per_year as i64;
+        let steps = (self.year - birth_year) as i64 * n + self.step as i64 - birth_step as i64;
+        if steps < 0 { None } else { Some(steps) }
+    }
+
+    /// Age in completed years, which is what the annual rate tables are indexed by.
+    pub fn age_of(&self, birth_year: i32, birth_step: u32) -> Option<i32> {
+        self.steps_since(birth_year, birth_step)
+            .map(|steps| (steps / self.steps_per_year as i64) as i32)
+    }
+
+    /// Age in years, at the resolution of a step.
+    pub fn exact_age_of(&self, birth_year: i32, birth_step: u32) -> Option<f64> {
+        self.steps_since(birth_year, birth_step)
+            .map(|steps| steps as f64 / self.steps_per_year as f64)
+    }
+
+    /// The current time as a fractional year.
+    pub fn time(&self) -> f64 {
+        self.year as f64 + self.step as f64 / self.steps_per_year as f64
+    }
+
+    pub fn is_start_of_year(&self) -> bool {
+        self.step == 0
+    }
+
+    /// Convert an annual probability of death to the probability for one step.
+    pub fn step_probability(&self, annual: f64) -> f64 {
+        if self.steps_per_year == 1 { annual }
+        else {
+            let survival = (1.0 - annual).powf(1.0 / self.steps_per_year as f64);
+            1.0 - survival
+        }
+    }
+
+    /// Convert an annual rate to the rate for one step.
+    pub fn step_rate(&self, annual: f64) -> f64 {
+        if self.steps_per_year == 1 { annual }
+        else { annual / self.steps_per_year as f64 }
+    }
+
+    pub fn advance(&mut self) {
+        self.step += 1;
+        if self.step >= self.steps_per_year {
+            self.step = 0;
+            self.year += 1;
+        }
+    }
+}
@@ -1,26 +1,91 @@
+use ::optimize::nelder_mead;
 use ::skew_normal::Curve;
 
 /// Fertility is measured with a truncated skew-normal curve, typically starting at age 15, peaking
 /// at age 23-27, and slowly declining towards the cutoff at menopause. 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Fertility {
     curve: Vec<f32>,
     min_age: usize,
     max_age: usize,
 }
 
+/// The skew of the standard fertility curve.
+pub const DEFAULT_SKEW: f64 = -3.0;
+
 impl Fertility {
     pub fn new(min_age: usize, max_age: usize) -> Fertility {
-        let curve = normalize(Curve::new(-3.0).limits_range(-2.0, 3.0, max_age - min_age));
+        Fertility::skewed(min_age, max_age, DEFAULT_SKEW)
+    }
+
+    /// A curve with another skew. Less negative skews push births to older ages.
+    pub fn skewed(min_age: usize, max_age: usize, skew: f64) -> Fertility {
+        let curve = normalize(Curve::new(skew).limits_range(-2.0, 3.0, max_age - min_age));
         Fertility { curve, min_age, max_age }
     }
 
-    pub fn birth_rate(&self, age: usize, total_fertility_rate: f32) -> f32 {
+    pub fn birth_rate(&self, age: usize, total_fertility_rate: f64) -> f64 {
         if age < self.min_age || age > self.max_age { 0.0 }
-        else { self.curve.get(age - self.min_age).unwrap_or(&0.0) * total_fertility_rate }
+        else {
+            let rate = self.curve.get(age - self.min_age).map_or(0.0, |&rate| rate as f64);
+            rate * total_fertility_rate
+        }
+    }
+
+    /**
+    The ages, skew and TFR that best fit observed birth rates by single year of age, as
+    `(age, rate)` pairs, or `None` if there are no births.
+
+    The TFR is the sum of the rates. Fitting searches the ages the curve starts and ends at, and
+    for each pair the skew, for the least squared difference from the rates. It starts up to ten
+    years before the youngest mother. The curve's upper tail is negligible for the last third or
+    so of its ages, so it can end well after the oldest. Returns the fit, its TFR and skew, and
+    that difference.
+    */
+    pub fn fit(rates: &[(usize, f64)]) -> Option<(Fertility, f32, f64, f64)> {
+        let total: f64 = rates.iter().map(|&(_, rate)| rate).sum();
+        let mothers = rates.iter().filter(|&&(_, rate)| rate > 0.0).map(|&(age, _)| age);
+        let (youngest, oldest) = (mothers.clone().min()?, mothers.max()?);
+        if total.is_nan() || total <= 0.0 { return None; }
+
+        let mut observed = vec![0.0; rates.iter().map(|&(age, _)| age + 1).max()?];
+        for &(age, rate) in rates.iter() { observed[age] += rate; }
+        let difference = |fertility: &Fertility| -> f64 {
+            (fertility.min_age.min(youngest)..fertility.max_age.max(oldest) + 1)
+                .map(|age| {
+                    let observed = observed.get(age).cloned().unwrap_or(0.0);
+                    (fertility.birth_rate(age, total) - observed).powi(2)
+                })
+                .sum()
+        };
+
+        let mut best: Option<(Fertility, f64, f64)> = None;
+        for min_age in youngest.saturating_sub(FIT_REACH)..youngest + 1 {
+            let furthest = min_age + 2 * (oldest + 1 - min_age) + FIT_REACH;
+            for max_age in oldest.max(min_age + 1)..furthest {
+                let (skew, least) = nelder_mead(
+                    |skew| difference(&Fertility::skewed(min_age, max_age, skew[0])),
+                    &[DEFAULT_SKEW], &[1.0], 1e-15, 500);
+                if best.as_ref().is_none_or(|&(_, _, fewest)| least < fewest) {
+                    best = Some((Fertility::skewed(min_age, max_age, skew[0]), skew[0], least));
+                }
+            }
+        }
+        best.map(|(fertility, skew, difference)| (fertility, total as f32, skew, difference))
+    }
+
+    pub fn min_age(&self) -> usize {
+        self.min_age
+    }
+
+    pub fn max_age(&self) -> usize {
+        self.max_age
     }
 }
 
+/// How far `Fertility::fit` looks beyond the ages with births, at the least.
+const FIT_REACH: usize = 10;
+
 /// We are normalizing against the mean, not a vector space.
 /// We want to multiply any index by the TFR, and get the instantaneous birth rate.
 fn normalize(vector: Vec<f32>) -> Vec<f32> {
@@ -30,3 +95,22 @@ fn normalize(vector: Vec<f32>) -> Vec<f32> {
     vector.iter().map(|x| x/sum).collect()
 
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::Fertility;
+
+    #[test]
+    fn fits_its_own_curve() {
+        let fertility = Fertility::skewed(17, 46, -1.5);
+        let rates: Vec<(usize, f64)> = (0..60)
+            .map(|age| (age, fertility.birth_rate(age, 1.7)))
+            .collect();
+        let (fitted, tfr, skew, difference) = Fertility::fit(&rates).unwrap();
+        assert_eq!((fitted.min_age(), fitted.max_age()), (17, 46));
+        assert!((skew + 1.5).abs() < 1e-3, "{}", skew);
+        assert!((tfr - 1.7).abs() < 1e-4 && difference < 1e-9, "{} {}", tfr, difference);
+        assert!(Fertility::fit(&[(20, 0.0), (30, 0.0)]).is_none());
+    }
+}
@@ -1,6 +1,8 @@
 use std::error::Error;
 use std::fmt;
 
+use ::optimize::nelder_mead;
+
 /// The Heligman-Pollard method of mortality rate estimation.
 
 #[derive(Debug)]
@@ -13,7 +15,7 @@ impl fmt::Display for HPError {
 }
 
 impl Error for HPError {
-    fn cause(&self) -> Option<&Error> {
+    fn cause(&self) -> Option<&dyn Error> {
         None
     }
     fn description(&self) -> &str {
@@ -26,7 +28,7 @@ impl Error for HPError {
 /// I've given the variables appropriate names for their intended effect on the function, but I've
 /// also annotated them with the traditional (bad) variable names. Why mathematicians do this, I
 /// don't know.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct HPMortalityModel {
     ///A
     infant_mortality: f32,
@@ -47,8 +49,37 @@ pub struct HPMortalityModel {
 }
 
 
+/// The parameters' names, A to H, in the order `HPMortalityModel::new` takes them.
+pub const PARAMETERS: [&str; 8] = [
+    "infant_mortality",
+    "first_year_mortality",
+    "infant_mortality_dropoff",
+    "accident_severity",
+    "accident_spread",
+    "accident_midpoint",
+    "adult_mortality",
+    "adult_mortality_increase",
+];
+
+/// The most times `HPMortalityModel::fit` restarts its search.
+const FIT_RESTARTS: usize = 10;
+
+/// Whether a parameter, by its index in `PARAMETERS`, is a proportion rather than just positive.
+fn is_proportion(index: usize) -> bool {
+    !matches!(index, 4 | 5 | 7)
+}
+
+fn scale(index: usize, value: f64) -> f64 {
+    let value = value.max(1e-12);
+    if is_proportion(index) { (value / (1.0 - value).max(1e-12)).ln() } else { value.ln() }
+}
+
+fn unscale(index: usize, value: f64) -> f64 {
+    if is_proportion(index) { 1.0 / (1.0 + (-value).exp()) } else { value.exp() }
+}
+
 fn clamp (x: f32, name: &str) -> Result<f32, HPError> {
-    if 0.0 > x || x > 1.0 {
+    if !(0.0..=1.0).contains(&x) {
         Err(HPError { problem: format!("Value of {} out of range 0...1.", name) })
     } else {
         Ok(x)
@@ -65,6 +96,7 @@ fn positive (x: f32, name: &str) -> Result<f32, HPError> {
 
 
 impl HPMortalityModel {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         infant_mortality: f32,
         first_year_mortality: f32,
@@ -103,6 +135,86 @@ impl HPMortalityModel {
         HPMortalityModel::new(0.0004, 0.0192, 0.1048, 0.0004, 3.0, 19.0, 0.00002, 1.1).unwrap()
     }
 
+    /// A model from the parameters' values, A to H, in the order of `PARAMETERS`.
+    pub fn from_parameters(p: [f32; 8]) -> Result<HPMortalityModel, HPError> {
+        HPMortalityModel::new(p[0], p[1], p[2], p[3], p[4], p[5], p[6], p[7])
+    }
+
+    /// This model with one parameter, named as in `PARAMETERS`, changed.
+    pub fn with_parameter(&self, name: &str, value: f32) -> Result<HPMortalityModel, HPError> {
+        let index = PARAMETERS.iter().position(|&parameter| parameter == name).ok_or_else(|| {
+            HPError { problem: format!("There's no Heligman-Pollard parameter {}.", name) }
+        })?;
+        let mut parameters = self.parameters();
+        parameters[index] = value;
+        HPMortalityModel::from_parameters(parameters)
+    }
+
+    /**
+    Fit a model to observed probabilities of dying, as `(age, q)` pairs, starting from `start`.
+
+    This minimizes Heligman and Pollard's own measure, the sum of `(1 - fitted / observed)²` over
+    the ages, so that the young ages with their small probabilities count as much as the old.
+    Ages with no deaths observed carry no information on that scale, and are left out. The
+    parameters in 0...1 are searched on a logistic scale and the rest on a log scale, which keeps
+    every step of the search a valid model. Returns the model and its measure.
+    */
+    pub fn fit(observed: &[(i32, f64)], start: &HPMortalityModel)
+        -> Result<(HPMortalityModel, f64), HPError>
+    {
+        let observed: Vec<(i32, f64)> = observed.iter().cloned()
+            .filter(|&(_, q)| q > 0.0)
+            .collect();
+        if let Some(&(age, q)) = observed.iter().find(|&&(age, q)| age < 0 || q >= 1.0) {
+            return Err(HPError { problem: format!(
+                "A probability of dying of {} at age {} can't be fitted.", q, age) });
+        }
+        if observed.len() < PARAMETERS.len() {
+            return Err(HPError { problem: format!(
+                "Fitting needs deaths at {} ages or more, not {}.",
+                PARAMETERS.len(), observed.len()) });
+        }
+
+        let model = |scaled: &[f64]| {
+            let mut parameters = [0.0f32; 8];
+            for (index, value) in scaled.iter().enumerate() {
+                parameters[index] = unscale(index, *value) as f32;
+            }
+            HPMortalityModel::from_parameters(parameters)
+        };
+        let measure = |scaled: &[f64]| match model(scaled) {
+            Ok(model) => observed.iter()
+                .map(|&(age, q)| (1.0 - model.at_age(age) as f64 / q).powi(2))
+                .sum(),
+            Err(_) => f64::INFINITY,
+        };
+
+        // Restarting from the best point found lets the simplex recover from collapsing early.
+        let mut best: Vec<f64> = start.parameters().iter().enumerate()
+            .map(|(index, &value)| scale(index, value as f64))
+            .collect();
+        let mut value = measure(&best);
+        for _ in 0..FIT_RESTARTS {
+            let (point, point_value) = nelder_mead(measure, &best, &[0.5; 8], 1e-12, 4000);
+            let improved = point_value < value - 1e-12;
+            if point_value < value { best = point; value = point_value; }
+            if !improved { break; }
+        }
+        Ok((model(&best)?, value))
+    }
+
+    /// The parameters' values, A to H, in the order of `PARAMETERS`.
+    pub fn parameters(&self) -> [f32; 8] {
+        [ self.infant_mortality
+        , self.first_year_mortality
+        , self.infant_mortality_dropoff
+        , self.accident_severity
+        , self.accident_spread
+        , self.accident_midpoint
+        , self.adult_mortality
+        , self.adult_mortality_increase ]
+    }
+
     pub fn at_age(&self, age_i: i32) -> f32 {
         let age = age_i as f32;
         let early_exponent = (age + self.first_year_mortality).powf(self.infant_mortality_dropoff);
@@ -115,9 +227,39 @@ impl HPMortalityModel {
 
         let combined_formula = early_life + accident_spike + old_age; // q/(1-q)
 
-        let mortality = combined_formula / (combined_formula + 1.0);
+        combined_formula / (combined_formula + 1.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::HPMortalityModel;
 
-        mortality
+    #[test]
+    fn parameters_by_name() {
+        let male = HPMortalityModel::baseline_male();
+        let older = male.with_parameter("adult_mortality_increase", 1.12).unwrap();
+        assert_eq!(older.parameters()[7], 1.12);
+        assert_eq!(older.parameters()[..7], male.parameters()[..7]);
+        assert!(male.with_parameter("infant_mortality", 2.0).is_err());
+        assert!(male.with_parameter("lifespan", 80.0).is_err());
+    }
 
+    #[test]
+    fn fits_its_own_curve() {
+        let male = HPMortalityModel::baseline_male();
+        let observed: Vec<(i32, f64)> = (0..100)
+            .map(|age| (age, male.at_age(age) as f64))
+            .collect();
+        let (fitted, measure) =
+            HPMortalityModel::fit(&observed, &HPMortalityModel::baseline_female()).unwrap();
+        assert!(measure < 0.05, "{}", measure);
+        for &(age, q) in observed.iter() {
+            let error = (fitted.at_age(age) as f64 / q - 1.0).abs();
+            assert!(error < 0.1, "age {}: {} against {}", age, fitted.at_age(age), q);
+        }
+        assert!(HPMortalityModel::fit(&observed[..5], &male).is_err());
+        assert!(HPMortalityModel::fit(&[(1, 1.5); 10], &male).is_err());
     }
 }
@@ -1,14 +1,16 @@
+use population::count::Count;
+
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Members {
-    pub males: usize,
-    pub females: usize,
+pub struct Members<C = usize> {
+    pub males: C,
+    pub females: C,
     pub male_dying: f32,
     pub female_dying: f32,
 }
 
 
-impl Members {
-    pub fn total(&self) -> usize {
-        self.males + self.females
+impl<C: Count> Members<C> {
+    pub fn total(&self) -> C {
+        self.males.plus(self.females)
     }
 }
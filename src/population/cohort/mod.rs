@@ -1,26 +1,28 @@
 mod members;
-mod heligman_pollard;
-mod fertility;
+pub mod heligman_pollard;
+pub mod fertility;
 
 use std::fmt;
-use self::fertility::Fertility;
-use self::heligman_pollard::HPMortalityModel;
 use self::members::Members;
 use super::attributes::AttributeData;
+use super::clock::Clock;
+use super::count::Count;
+use super::schedule::Tables;
+use ::random::Random;
 
 /**
-A Cohort is a tight demographic grouping. Here, it's all people born within a given year.
-Cohorts independently track their fertility curve, their gender-specific mortality rate, and their
-separate gender populations.
+A Cohort is a tight demographic grouping. Here, it's all people born within a given year, or
+within one step of it when the year is divided into several. Cohorts track their separate gender
+populations; the fertility and mortality rates they're subject to come from their population's
+`Schedule`. Members are counted in `C`, whole individuals by default.
 */
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Cohort/*<'a>*/ {
-    pub members: Members,
+pub struct Cohort/*<'a>*/<C = usize> {
+    pub members: Members<C>,
     pub birth_year: i32,
+    #[serde(default)]
+    pub birth_step: u32,
     pub attributes: Vec<AttributeData>,
-    pub fertility: Fertility,
-    pub male_mortality: HPMortalityModel,
-    pub female_mortality: HPMortalityModel,
 }
 
 
@@ -33,76 +35,116 @@ pub trait Dimension: fmt::Debug {
 }
 
 
-impl Cohort {
+impl<C: Count> Cohort<C> {
 
-    pub fn new ( males: usize, females: usize, birth_year: i32 ) -> Cohort {
-        let fertility = Fertility::new(15, 50);
+    pub fn new ( males: C, females: C, birth_year: i32 ) -> Cohort<C> {
         let members = Members { males, females, male_dying: 0.0, female_dying: 0.0 };
-        let male_mortality = HPMortalityModel::baseline_male();
-        let female_mortality = HPMortalityModel::baseline_female();
         let attributes = Vec::new();
-        Cohort { members, birth_year, attributes, fertility, male_mortality, female_mortality }
+        Cohort { members, birth_year, birth_step: 0, attributes }
+    }
+
+    /// A cohort born at a particular step of its birth year.
+    pub fn born_at (males: C, females: C, clock: &Clock) -> Cohort<C> {
+        let mut cohort = Cohort::new(males, females, clock.year);
+        cohort.birth_step = clock.step;
+        cohort
+    }
+
+    /// Age in completed years, or None before the cohort is born.
+    pub fn age (&self, clock: &Clock) -> Option<i32> {
+        clock.age_of(self.birth_year, self.birth_step)
+    }
+
+    /// Take in another cohort's members, as when cohorts are merged into an open age group. The
+    /// merged cohort keeps its own birth date and attributes.
+    pub fn absorb (&mut self, other: Cohort<C>) {
+        let members = &mut self.members;
+        members.males = members.males.plus(other.members.males);
+        members.females = members.females.plus(other.members.females);
+        members.male_dying += other.members.male_dying;
+        members.female_dying += other.members.female_dying;
     }
 
     /// Births are currently much simpler than deaths at the cohort level. This might change as
     /// male fertility is accounted for, as well as event effects.
-    pub fn births (&self, year: i32, tfr: f32) -> f64 {
-        if (year as i32) < self.birth_year { 0.0 }
-        else {
-            let rate = self.fertility.birth_rate((year - self.birth_year) as usize, tfr);
-            rate as f64 * self.members.females as f64
+    pub fn births (&self, clock: &Clock, tables: &Tables, tfr: f32) -> f64 {
+        match self.age(clock) {
+            None => 0.0,
+            Some(age) => tables.birth_rate(age, tfr) * self.members.females.to_f64(),
+        }
+    }
+
+    /// Draw this step's births at random: binomial while the rate is a plausible per-woman
+    /// probability, Poisson above that.
+    pub fn random_births (&self, clock: &Clock, tables: &Tables, tfr: f32, rng: &mut Random) -> u64 {
+        match self.age(clock) {
+            None => 0,
+            Some(age) => {
+                let rate = tables.birth_rate(age, tfr);
+                let females = self.members.females.expect_trials();
+                if rate <= 1.0 { rng.binomial(females, rate) }
+                else { rng.poisson(rate * females as f64) }
+            }
         }
     }
 
     /// Kill off population members, carrying remainders over to the next year (simulating someone
     /// still living, but in declining health.) While remainder births are saved per population,
-    /// remainder deaths are saved per cohort per gender. If population is zero, this will return
-    /// None, signaling the removal of the generation.
+    /// remainder deaths are saved per cohort per gender. If population reaches zero, this will
+    /// return false, signaling the removal of the generation.
     pub fn perform_deaths (
-        mut self,
-        year: i32,
-        extraordinary_male_deaths: usize,
-        extraordinary_female_deaths: usize
-    ) -> Option<Cohort> {
-
-        if (year as i32) < self.birth_year { return Some(self); }
-
-
-        let male_mort = self.male_mortality.at_age(year - self.birth_year);
-        let male_unnatural_survivors =
-            self.members.males.saturating_sub(extraordinary_male_deaths) as f32;
-        let male_natural_deaths = (male_unnatural_survivors * male_mort) + self.members.male_dying;
-        let male_deaths = male_natural_deaths + extraordinary_male_deaths as f32;
-
-
-        let female_mort = self.female_mortality.at_age(year - self.birth_year);
-        let female_unnatural_survivors =
-            self.members.females.saturating_sub(extraordinary_female_deaths) as f32;
-        let female_natural_deaths =
-            (female_unnatural_survivors * female_mort) + self.members.female_dying;
-        let female_deaths = female_natural_deaths + extraordinary_female_deaths as f32;
+        &mut self,
+        clock: &Clock,
+        tables: &Tables,
+        extraordinary_male_deaths: C,
+        extraordinary_female_deaths: C
+    ) -> bool {
+
+        let age = match self.age(clock) {
+            Some(age) => age,
+            None => return true,
+        };
 
 
-        let males = self.members.males.saturating_sub(male_deaths.trunc() as usize);
-        let females = self.members.females.saturating_sub(female_deaths.trunc() as usize);
+        let (males, male_dying) = self.members.males
+            .survive(extraordinary_male_deaths, tables.male_q(age), self.members.male_dying);
+        let (females, female_dying) = self.members.females
+            .survive(extraordinary_female_deaths, tables.female_q(age), self.members.female_dying);
 
+        self.members = Members { males, male_dying, females, female_dying };
 
-        if males + females == 0 {
-            println!("Everyone's dead in gen {:?}", self.birth_year);
-            return None
-        }
 
+        !self.members.total().is_empty()
+    }
 
-        let survivors = Members {
-            males,
-            male_dying: male_deaths.fract(),
-            females,
-            female_dying: female_deaths.fract(),
+    /// The stochastic counterpart of `perform_deaths`: each survivor of the extraordinary deaths
+    /// dies independently with the step's mortality rate. No remainders are carried.
+    pub fn perform_random_deaths (
+        &mut self,
+        clock: &Clock,
+        tables: &Tables,
+        extraordinary_male_deaths: C,
+        extraordinary_female_deaths: C,
+        rng: &mut Random,
+    ) -> bool {
+
+        let age = match self.age(clock) {
+            Some(age) => age,
+            None => return true,
         };
 
+        let male_survivors = self.members.males.saturating_sub(extraordinary_male_deaths);
+        let male_mort = tables.male_q(age);
+        let male_deaths = rng.binomial(male_survivors.expect_trials(), male_mort);
+        let males = male_survivors.saturating_sub(C::from_trials(male_deaths));
+
+        let female_survivors = self.members.females.saturating_sub(extraordinary_female_deaths);
+        let female_mort = tables.female_q(age);
+        let female_deaths = rng.binomial(female_survivors.expect_trials(), female_mort);
+        let females = female_survivors.saturating_sub(C::from_trials(female_deaths));
 
-        self.members = survivors;
+        self.members = Members { males, male_dying: 0.0, females, female_dying: 0.0 };
 
-        Some(self)
+        !self.members.total().is_empty()
     }
 }
@@ -0,0 +1,236 @@
+use std::any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Sub;
+
+/// A real count that falls below this in a step dies out, so a cohort of real counts ends at
+/// some age, as a whole one does once its last member dies, rather than dwindling forever.
+pub const REAL_EXTINCTION: f64 = 1e-9;
+
+/**
+The type a population counts its members in.
+
+Whole counts (`usize`) are the default. Flows that come out fractional, such as births or deaths
+in a small cohort, are truncated and the fractions carried forward in the remainders, so that
+nothing is lost over time. Real counts (`f64`) keep the fractions in the counts themselves, which
+suits scaled-down models such as densities per square kilometre; they carry nothing. A real
+count that falls below `REAL_EXTINCTION`, a billionth of a member, dies out.
+
+For populations that could outgrow a `usize`, such as long runs of exponential growth, count in
+`u128`. Whatever the type, additions are checked: a count that no longer fits panics rather than
+wrapping or saturating. Rates are carried in `f64` from the step's probability of death onwards,
+and deaths are reckoned in `f64` whatever the type.
+*/
+pub trait Count:
+    Copy + Default + PartialOrd + fmt::Debug + Send + Sync + 'static + Sub<Output = Self>
+{
+    /// Whether counts are whole numbers.
+    const WHOLE: bool;
+
+    fn to_f64(self) -> f64;
+
+    /// The part of a real-valued amount this type can hold, and the fraction left over, or None
+    /// if the amount is too large for the type.
+    fn from_real(value: f64) -> Option<(Self, f64)>;
+
+    /// A count of whole individuals, such as a random draw.
+    fn from_trials(trials: u64) -> Self;
+
+    /// The number of individuals to make random draws for, or None if there are too many.
+    fn to_trials(self) -> Option<u64>;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    fn saturating_sub(self, other: Self) -> Self;
+
+    fn is_empty(self) -> bool {
+        self.partial_cmp(&Self::default()) != Some(Ordering::Greater)
+    }
+
+    /// Apply one step of mortality: `extraordinary` deaths first, then a proportion `rate` of
+    /// those left, plus the fraction of a death `dying` carried from the previous step. Returns the
+    /// survivors and the fraction of a death to carry on.
+    fn survive(self, extraordinary: Self, rate: f64, dying: f32) -> (Self, f32);
+
+    /// Add two counts, panicking if the result doesn't fit.
+    fn plus(self, other: Self) -> Self {
+        self.checked_add(other).unwrap_or_else(|| overflow::<Self>())
+    }
+
+    fn checked_total<I: IntoIterator<Item = Self>>(counts: I) -> Option<Self> {
+        counts.into_iter().try_fold(Self::default(), Self::checked_add)
+    }
+
+    /// The sum of some counts, panicking if it doesn't fit.
+    fn total<I: IntoIterator<Item = Self>>(counts: I) -> Self {
+        Self::checked_total(counts).unwrap_or_else(|| overflow::<Self>())
+    }
+
+    /// Like `from_real`, panicking if the amount doesn't fit.
+    fn expect_real(value: f64) -> (Self, f64) {
+        Self::from_real(value).unwrap_or_else(|| overflow::<Self>())
+    }
+
+    /// Like `to_trials`, panicking if there are too many individuals.
+    fn expect_trials(self) -> u64 {
+        self.to_trials().unwrap_or_else(|| {
+            panic!("{:?} members is too many to draw for in stochastic mode", self)
+        })
+    }
+}
+
+fn overflow<C>() -> ! {
+    panic!("population count overflowed {}; count in a wider type such as u128",
+        any::type_name::<C>())
+}
+
+/// Deaths among a whole count, and the fraction of a death carried forward.
+fn survive_f64(count: f64, extraordinary: f64, rate: f64, dying: f32) -> (f64, f32) {
+    let unnatural_survivors = (count - extraordinary).max(0.0);
+    let deaths = (unnatural_survivors * rate) + dying as f64 + extraordinary;
+    (deaths.trunc(), deaths.fract() as f32)
+}
+
+impl Count for usize {
+    const WHOLE: bool = true;
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_real(value: f64) -> Option<(usize, f64)> {
+        if value.is_nan() || value >= usize::MAX as f64 { return None; }
+        Some((value.trunc() as usize, value.fract()))
+    }
+
+    fn from_trials(trials: u64) -> usize {
+        trials as usize
+    }
+
+    fn to_trials(self) -> Option<u64> {
+        Some(self as u64)
+    }
+
+    fn checked_add(self, other: usize) -> Option<usize> {
+        usize::checked_add(self, other)
+    }
+
+    fn saturating_sub(self, other: usize) -> usize {
+        usize::saturating_sub(self, other)
+    }
+
+    fn survive(self, extraordinary: usize, rate: f64, dying: f32) -> (usize, f32) {
+        let (deaths, dying) = survive_f64(self as f64, extraordinary as f64, rate, dying);
+        (self.saturating_sub(deaths as usize), dying)
+    }
+}
+
+impl Count for u128 {
+    const WHOLE: bool = true;
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_real(value: f64) -> Option<(u128, f64)> {
+        if value.is_nan() || value >= u128::MAX as f64 { return None; }
+        Some((value.trunc() as u128, value.fract()))
+    }
+
+    fn from_trials(trials: u64) -> u128 {
+        u128::from(trials)
+    }
+
+    fn to_trials(self) -> Option<u64> {
+        if self > u128::from(u64::MAX) { None } else { Some(self as u64) }
+    }
+
+    fn checked_add(self, other: u128) -> Option<u128> {
+        u128::checked_add(self, other)
+    }
+
+    fn saturating_sub(self, other: u128) -> u128 {
+        u128::saturating_sub(self, other)
+    }
+
+    fn survive(self, extraordinary: u128, rate: f64, dying: f32) -> (u128, f32) {
+        let (deaths, dying) = survive_f64(self as f64, extraordinary as f64, rate, dying);
+        (self.saturating_sub(deaths as u128), dying)
+    }
+}
+
+impl Count for f64 {
+    const WHOLE: bool = false;
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_real(value: f64) -> Option<(f64, f64)> {
+        if value.is_finite() { Some((value, 0.0)) } else { None }
+    }
+
+    fn from_trials(trials: u64) -> f64 {
+        trials as f64
+    }
+
+    fn to_trials(self) -> Option<u64> {
+        if self.round() < u64::MAX as f64 { Some(self.round() as u64) } else { None }
+    }
+
+    fn checked_add(self, other: f64) -> Option<f64> {
+        let sum = self + other;
+        if sum.is_finite() { Some(sum) } else { None }
+    }
+
+    fn saturating_sub(self, other: f64) -> f64 {
+        (self - other).max(0.0)
+    }
+
+    fn survive(self, extraordinary: f64, rate: f64, _dying: f32) -> (f64, f32) {
+        let survivors = self.saturating_sub(extraordinary) * (1.0 - rate);
+        (if survivors < REAL_EXTINCTION { 0.0 } else { survivors }, 0.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Count;
+
+    #[test]
+    fn additions_are_checked() {
+        assert_eq!(usize::checked_total(vec![ usize::MAX, 1 ]), None);
+        assert_eq!(u128::checked_total(vec![ u128::from(u64::MAX); 4 ]),
+            Some(4 * u128::from(u64::MAX)));
+        assert_eq!(f64::checked_total(vec![ 1e308, 1e308 ]), None);
+        assert_eq!(usize::from_real(1e30), None);
+        assert_eq!(u128::from_real(1e30).map(|(count, _)| count), Some(1e30 as u128));
+        assert_eq!((u128::from(u64::MAX) + 1).to_trials(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflowed usize")]
+    fn overflow_panics() {
+        usize::MAX.plus(1);
+    }
+
+    #[test]
+    fn large_counts_die_at_their_rate() {
+        let huge: u128 = 1 << 100;
+        let (survivors, _) = huge.survive(0, 0.25, 0.0);
+        assert!((survivors as f64 / huge as f64 - 0.75).abs() < 1e-12);
+        // A rate an f32 can't hold exactly still applies in full.
+        let (survivors, _) = huge.survive(0, 0.1, 0.0);
+        assert!((survivors as f64 / huge as f64 - 0.9).abs() < 1e-12);
+        let big = 3_000_000_000usize;
+        let (survivors, _) = big.survive(0, 0.001, 0.0);
+        assert_eq!(survivors, 2_997_000_000);
+    }
+
+    #[test]
+    fn real_counts_die_out() {
+        assert_eq!(1e-8f64.survive(0.0, 0.5, 0.0), (5e-9, 0.0));
+        assert_eq!(1e-9f64.survive(0.0, 0.5, 0.0), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,316 @@
+/*!
+A record of a population's progress, year by year, that can be queried after the run.
+
+The age/sex distribution is recorded at the start of every year, as `Population::age_sex_
+distribution` gives it, along with each year's births and deaths by sex and deaths by age.
+Storing every distribution in full would grow with the number of years times the number of ages,
+so most years are stored as a change from the year before: the previous distribution aged by a
+year, less that year's deaths and plus its births, is usually exactly the new one. Only the ages
+where it isn't (open age groups, steps shorter than a year, rounding in real counts) are stored
+in full. A full distribution is kept every `keyframe_interval` years so that a query never replays
+more than that many years.
+
+The engine has no migration yet, so migration is recorded as zero; it's there so histories keep
+the same shape once it does.
+
+A history doesn't know the schedule it was run with, so its life tables are the ones its deaths
+imply: the probability of dying at each age is that year's deaths at the age over those who were
+there at its start, with the year's births at age zero. Ages no one reached end the table, and an
+open age group counts as a single year of age. For a population spread across every age, these
+are the schedule's own tables.
+*/
+
+use std::ops::RangeInclusive;
+
+use super::count::Count;
+use super::indicators::{indicators_of, AgeBand, Indicators};
+use super::life_table::LifeTable;
+
+/// Years between full distributions, by default.
+pub const KEYFRAME_INTERVAL: usize = 50;
+
+/// What happened in one year, and how the next year's distribution differs from the expected one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Frame<C> {
+    /// Male and female births.
+    pub births: (C, C),
+    /// Male and female deaths at each age, from age zero.
+    pub deaths_by_age: Vec<(C, C)>,
+    /// Male and female immigrants.
+    pub immigrants: (C, C),
+    /// Male and female emigrants.
+    pub emigrants: (C, C),
+    /// Ages where the next distribution isn't the expected one, with the actual counts.
+    corrections: Vec<(usize, C, C)>,
+    /// The number of ages in the next distribution.
+    ages: usize,
+}
+
+impl<C: Count> Frame<C> {
+    fn new() -> Frame<C> {
+        let zero = (C::default(), C::default());
+        Frame {
+            births: zero,
+            deaths_by_age: Vec::new(),
+            immigrants: zero,
+            emigrants: zero,
+            corrections: Vec::new(),
+            ages: 0,
+        }
+    }
+
+    pub fn deaths(&self) -> (C, C) {
+        ( C::total(self.deaths_by_age.iter().map(|deaths| deaths.0))
+        , C::total(self.deaths_by_age.iter().map(|deaths| deaths.1)) )
+    }
+
+    /// The distribution a year after `previous`, if the year's flows account for every change.
+    fn expected(&self, previous: &[(C, C)]) -> Vec<(C, C)> {
+        let zero = (C::default(), C::default());
+        let deaths = |age: usize| self.deaths_by_age.get(age).cloned().unwrap_or(zero);
+        let mut expected = vec![zero; self.ages];
+        for (age, slot) in expected.iter_mut().enumerate().skip(1) {
+            let (males, females) = previous.get(age - 1).cloned().unwrap_or(zero);
+            let (males, females) = if age == 1 {
+                (males.plus(self.births.0), females.plus(self.births.1))
+            } else {
+                (males, females)
+            };
+            let (male_deaths, female_deaths) = deaths(age - 1);
+            *slot = (males.saturating_sub(male_deaths), females.saturating_sub(female_deaths));
+        }
+        expected
+    }
+
+    fn apply(&self, previous: &[(C, C)]) -> Vec<(C, C)> {
+        let mut next = self.expected(previous);
+        for &(age, males, females) in self.corrections.iter() {
+            next[age] = (males, females);
+        }
+        next
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct History<C> {
+    start_year: i32,
+    keyframe_interval: usize,
+    keyframes: Vec<Vec<(C, C)>>,
+    frames: Vec<Frame<C>>,
+    /// The year in progress.
+    current: Frame<C>,
+    /// The latest distribution, to compare the next one with.
+    latest: Vec<(C, C)>,
+}
+
+impl<C: Count> History<C> {
+    /// Start a history in `year`, with the distribution at its start.
+    pub fn new(year: i32, distribution: Vec<(C, C)>, keyframe_interval: usize) -> History<C> {
+        History {
+            start_year: year,
+            keyframe_interval: keyframe_interval.max(1),
+            keyframes: vec![distribution.clone()],
+            frames: Vec::new(),
+            current: Frame::new(),
+            latest: distribution,
+        }
+    }
+
+    pub(crate) fn count_births(&mut self, males: C, females: C) {
+        let births = self.current.births;
+        self.current.births = (births.0.plus(males), births.1.plus(females));
+    }
+
+    pub(crate) fn count_deaths(&mut self, age: usize, males: C, females: C) {
+        let deaths = &mut self.current.deaths_by_age;
+        if deaths.len() <= age { deaths.resize(age + 1, (C::default(), C::default())); }
+        deaths[age] = (deaths[age].0.plus(males), deaths[age].1.plus(females));
+    }
+
+    /// Close the year in progress, given the distribution at the start of the next.
+    pub(crate) fn end_year(&mut self, distribution: Vec<(C, C)>) {
+        let mut frame = ::std::mem::replace(&mut self.current, Frame::new());
+        frame.ages = distribution.len();
+        let expected = frame.expected(&self.latest);
+        frame.corrections = distribution.iter().zip(expected.iter()).enumerate()
+            .filter(|&(_, (actual, expected))| actual != expected)
+            .map(|(age, (actual, _))| (age, actual.0, actual.1))
+            .collect();
+        self.frames.push(frame);
+        if self.frames.len().is_multiple_of(self.keyframe_interval) {
+            self.keyframes.push(distribution.clone());
+        }
+        self.latest = distribution;
+    }
+
+    /// The years with a recorded distribution, from the first to the start of the current one.
+    pub fn years(&self) -> RangeInclusive<i32> {
+        self.start_year..=self.start_year + self.frames.len() as i32
+    }
+
+    /// The flows of a completed year.
+    pub fn frame(&self, year: i32) -> Option<&Frame<C>> {
+        if year < self.start_year { return None; }
+        self.frames.get((year - self.start_year) as usize)
+    }
+
+    /// Males and females at each age at the start of `year`.
+    pub fn pyramid(&self, year: i32) -> Option<Vec<(C, C)>> {
+        if !self.years().contains(&year) { return None; }
+        let offset = (year - self.start_year) as usize;
+        let keyframe = offset / self.keyframe_interval;
+        let mut distribution = self.keyframes[keyframe].clone();
+        for frame in &self.frames[keyframe * self.keyframe_interval..offset] {
+            distribution = frame.apply(&distribution);
+        }
+        Some(distribution)
+    }
+
+    pub fn total(&self, year: i32) -> Option<C> {
+        self.pyramid(year).map(|pyramid| C::total(pyramid.into_iter().map(|(m, f)| m.plus(f))))
+    }
+
+    pub fn births(&self, year: i32) -> Option<C> {
+        self.frame(year).map(|frame| frame.births.0.plus(frame.births.1))
+    }
+
+    pub fn deaths(&self, year: i32) -> Option<C> {
+        self.frame(year).map(|frame| {
+            let (males, females) = frame.deaths();
+            males.plus(females)
+        })
+    }
+
+    pub fn net_migration(&self, year: i32) -> Option<f64> {
+        self.frame(year).map(|frame| {
+            frame.immigrants.0.plus(frame.immigrants.1).to_f64()
+                - frame.emigrants.0.plus(frame.emigrants.1).to_f64()
+        })
+    }
+
+    /// The male and female life tables a completed year's deaths imply; see the module.
+    pub fn life_tables(&self, year: i32) -> Option<(LifeTable, LifeTable)> {
+        let frame = self.frame(year)?;
+        let pyramid = self.pyramid(year)?;
+        let table = |sex: fn(&(C, C)) -> C| {
+            let q = |age: i32| {
+                let age = age as usize;
+                let mut exposed = pyramid.get(age).map_or(0.0, |counts| sex(counts).to_f64());
+                if age == 0 { exposed += sex(&frame.births).to_f64(); }
+                let deaths = frame.deaths_by_age.get(age)
+                    .map_or(0.0, |deaths| sex(deaths).to_f64());
+                if exposed > 0.0 { (deaths / exposed).min(1.0) as f32 } else { 1.0 }
+            };
+            LifeTable::new(q, None, None)
+        };
+        Some((table(|counts| counts.0), table(|counts| counts.1)))
+    }
+
+    /// Indicators for a completed year, with the proportions in `bands`; see `Indicators`. Life
+    /// expectancies are from `life_tables`, and without the exposure to the fertility schedule,
+    /// there's no realized TFR.
+    pub fn indicators(&self, year: i32, bands: &[AgeBand]) -> Option<Indicators> {
+        let frame = self.frame(year)?;
+        let distribution = self.pyramid(year + 1)?;
+        let (male, female) = self.life_tables(year)?;
+        let (male_deaths, female_deaths) = frame.deaths();
+        Some(indicators_of(&distribution,
+                           frame.births.0.plus(frame.births.1).to_f64(),
+                           male_deaths.plus(female_deaths).to_f64(),
+                           (male.life_expectancy(), female.life_expectancy()),
+                           0.0, bands))
+    }
+
+    /// Male and female deaths at each age, summed over the completed years in `years`.
+    pub fn deaths_by_age(&self, years: RangeInclusive<i32>) -> Vec<(C, C)> {
+        let mut totals: Vec<(C, C)> = Vec::new();
+        for frame in years.filter_map(|year| self.frame(year)) {
+            if totals.len() < frame.deaths_by_age.len() {
+                totals.resize(frame.deaths_by_age.len(), (C::default(), C::default()));
+            }
+            for (total, deaths) in totals.iter_mut().zip(frame.deaths_by_age.iter()) {
+                *total = (total.0.plus(deaths.0), total.1.plus(deaths.1));
+            }
+        }
+        totals
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use population::Population;
+    use population::count::Count;
+
+    fn check_pyramids<C: Count>(pop: Population<C>, years: usize) -> Population<C> {
+        let mut pop = pop;
+        let mut pyramids = vec![pop.age_sex_distribution()];
+        let mut flows = Vec::new();
+        for _ in 0..years {
+            pop = pop.advance_year();
+            pyramids.push(pop.age_sex_distribution());
+            flows.push((pop.births(), pop.deaths()));
+        }
+
+        let history = pop.history().unwrap();
+        let start = *history.years().start();
+        assert_eq!(history.years().count(), years + 1);
+        for (offset, pyramid) in pyramids.iter().enumerate() {
+            assert_eq!(history.pyramid(start + offset as i32).as_ref(), Some(pyramid));
+        }
+        for (offset, &(births, deaths)) in flows.iter().enumerate() {
+            let year = start + offset as i32;
+            assert_eq!(history.births(year), Some(births));
+            // Real deaths are summed in a different order, so they can differ in the last place.
+            let recorded = history.deaths(year).unwrap().to_f64();
+            assert!((recorded - deaths.to_f64()).abs() <= 1e-9 * recorded.max(1.0));
+        }
+        assert_eq!(history.pyramid(start - 1), None);
+        assert_eq!(history.births(start + years as i32), None);
+        pop
+    }
+
+    #[test]
+    fn replays_whole_counts_from_flows() {
+        let pop = Population::new(vec![ ( 10_000, 10_000 ); 30 ]).with_history();
+        let pop = check_pyramids(pop, 230);
+        let history = pop.history().unwrap();
+        assert!(history.frames.iter().all(|frame| frame.corrections.is_empty()));
+        assert_eq!(history.keyframes.len(), 5);
+
+        let by_age = history.deaths_by_age(100..=199);
+        let total = by_age.iter().fold(0, |total, &(m, f)| total + m + f);
+        assert_eq!(total, (100..200).map(|year| history.deaths(year).unwrap()).sum::<usize>());
+        assert!(by_age[80].1 > by_age[20].1);
+    }
+
+    #[test]
+    fn indicators_follow_the_recorded_years() {
+        let mut pop = Population::<f64>::from_counts(vec![ ( 1_000.0, 1_000.0 ); 120 ])
+            .with_history();
+        for _ in 0..3 { pop = pop.advance_year(); }
+        let history = pop.history().unwrap();
+        let recorded = history.indicators(2, &[]).unwrap();
+        let live = pop.indicators(&[]);
+        assert_eq!((recorded.population, recorded.median_age), (live.population, live.median_age));
+        // Real deaths are summed in a different order.
+        let cdr = recorded.crude_death_rate.unwrap() - live.crude_death_rate.unwrap();
+        assert!(cdr.abs() < 1e-9, "{}", cdr);
+        assert_eq!(recorded.realized_tfr, None);
+        // Every age is there, so the deaths imply the schedule's own mortality.
+        let difference = recorded.female_life_expectancy - live.female_life_expectancy;
+        assert!(difference.abs() < 1e-3, "{}", difference);
+        assert_eq!(history.net_migration(2), Some(0.0));
+        assert!(history.indicators(3, &[]).is_none());
+    }
+
+    #[test]
+    fn replays_everything_else_with_corrections() {
+        check_pyramids(Population::new(vec![ ( 500, 500 ); 30 ]).stochastic(5).with_history(), 120);
+        let real = Population::<f64>::from_counts(vec![ ( 1000.5, 999.25 ); 50 ])
+            .with_steps_per_year(4)
+            .with_terminal_age(70)
+            .with_history_keyframes(7);
+        check_pyramids(real, 100);
+    }
+}
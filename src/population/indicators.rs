@@ -0,0 +1,260 @@
+/*!
+The standard demographic indicators of a population, for the year it has just completed.
+
+Rates are for the year's births and deaths, per thousand of the population midway through it,
+taken as the mean of its size at the start (the end, less the births, plus the deaths) and the
+end. Everything else describes the population at the end of the year.
+
+Ages are completed years, as in `InverseProjection` and everything else that reports by age; see
+`completed_ages`. An open age group is counted at its start. Those aged `a` are taken to be
+`a + 0.5` on average, and are spread evenly across the year of age for the median.
+
+Life expectancies are at birth, for the period: the years a newborn would live at this year's
+mortality, from the population's schedule; see `LifeTable`. The realized TFR is the TFR implied
+by the year's births, given how many women there were at each age and the fertility pattern they
+were exposed to; it differs from the TFR the population was set to by chance in stochastic mode,
+and by the remainders carried from year to year otherwise.
+
+Any indicator that would divide by zero, such as a sex ratio with no women, is `None`.
+*/
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{completed_ages, Population};
+use super::count::Count;
+use super::life_table::LifeTable;
+
+/// Youth are younger than this, and the working ages run up to it.
+pub const WORKING_AGE: usize = 15;
+/// The old are this age or older.
+pub const OLD_AGE: usize = 65;
+/// Age-specific sex ratios are in groups of this many years...
+pub const SEX_RATIO_GROUP: usize = 5;
+/// ...up to an open group from this age.
+pub const SEX_RATIO_LAST: usize = 85;
+
+/// A range of ages, from `start` up to and including `end`, or with no end.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgeBand {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl AgeBand {
+    pub fn new(start: usize, end: Option<usize>) -> AgeBand {
+        AgeBand { start, end }
+    }
+
+    pub fn contains(&self, age: usize) -> bool {
+        age >= self.start && self.end.is_none_or(|end| age <= end)
+    }
+}
+
+impl FromStr for AgeBand {
+    type Err = String;
+
+    /// Bands are written `15-49` or `65+`.
+    fn from_str(band: &str) -> Result<AgeBand, String> {
+        let band = band.trim();
+        let age = |text: &str| text.trim().parse::<usize>()
+            .map_err(|_| format!("{} isn't an age band such as 15-49 or 65+.", band));
+        if let Some(start) = band.strip_suffix('+') {
+            return Ok(AgeBand::new(age(start)?, None));
+        }
+        let mut ages = band.splitn(2, '-');
+        let start = age(ages.next().unwrap_or(""))?;
+        let end = age(ages.next().unwrap_or(""))?;
+        if end < start {
+            return Err(format!("The age band {} ends before it starts.", band));
+        }
+        Ok(AgeBand::new(start, Some(end)))
+    }
+}
+
+impl fmt::Display for AgeBand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}-{}", self.start, end),
+            None => write!(f, "{}+", self.start),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Indicators {
+    /// The population at the end of the year.
+    pub population: f64,
+    /// Births, deaths and growth per thousand.
+    pub crude_birth_rate: Option<f64>,
+    pub crude_death_rate: Option<f64>,
+    pub crude_growth_rate: Option<f64>,
+    pub mean_age: Option<f64>,
+    pub median_age: Option<f64>,
+    /// Under 15s, 65 and overs, and both, per hundred aged 15 to 64.
+    pub youth_dependency: Option<f64>,
+    pub old_age_dependency: Option<f64>,
+    pub total_dependency: Option<f64>,
+    /// Males per hundred females.
+    pub sex_ratio: Option<f64>,
+    /// Males per hundred females in five-year groups, from 0-4 to 85+.
+    pub sex_ratios: Vec<Option<f64>>,
+    pub male_life_expectancy: f64,
+    pub female_life_expectancy: f64,
+    pub realized_tfr: Option<f64>,
+    /// The proportion of the population in each band asked for.
+    pub shares: Vec<(AgeBand, Option<f64>)>,
+}
+
+impl<C: Count> Population<C> {
+    /// Indicators for the year just completed, with the proportions in `bands`; see `Indicators`.
+    pub fn indicators(&self, bands: &[AgeBand]) -> Indicators {
+        let life_expectancy = (LifeTable::male(&self.schedule).life_expectancy(),
+                               LifeTable::female(&self.schedule).life_expectancy());
+        indicators_of(&self.age_sex_distribution(), self.births.to_f64(), self.deaths.to_f64(),
+                      life_expectancy, self.fertility_exposure, bands)
+    }
+}
+
+/// Indicators for a year that ended with `distribution`, ages as in `age_sex_distribution`, given
+/// its births and deaths, male and female life expectancies, and births per unit of TFR.
+pub(crate) fn indicators_of<C: Count>(distribution: &[(C, C)], births: f64, deaths: f64,
+                                      life_expectancy: (f64, f64), fertility_exposure: f64,
+                                      bands: &[AgeBand]) -> Indicators
+{
+    let ages: Vec<(f64, f64)> = completed_ages(distribution).iter()
+        .map(|&(males, females)| (males.to_f64(), females.to_f64()))
+        .collect();
+    let between = |band: AgeBand| -> f64 {
+        ages.iter().enumerate()
+            .filter(|&(age, _)| band.contains(age))
+            .fold(0.0, |total, (_, &(males, females))| total + males + females)
+    };
+    let population = ages.iter().fold(0.0, |total, &(males, females)| total + males + females);
+
+    let start = population - births + deaths;
+    let middle = (start + population) / 2.0;
+    let per_thousand = |count: f64| ratio(1000.0 * count, middle);
+
+    let young = between(AgeBand::new(0, Some(WORKING_AGE - 1)));
+    let working = between(AgeBand::new(WORKING_AGE, Some(OLD_AGE - 1)));
+    let old = between(AgeBand::new(OLD_AGE, None));
+
+    let sex_ratios = (0..SEX_RATIO_LAST / SEX_RATIO_GROUP + 1).map(|group| {
+        let start = group * SEX_RATIO_GROUP;
+        let end = start + SEX_RATIO_GROUP - 1;
+        let band = AgeBand::new(start, if start >= SEX_RATIO_LAST { None } else { Some(end) });
+        let (males, females) = ages.iter().enumerate()
+            .filter(|&(age, _)| band.contains(age))
+            .fold((0.0, 0.0), |(m, f), (_, &(males, females))| (m + males, f + females));
+        ratio(100.0 * males, females)
+    }).collect();
+    let males: f64 = ages.iter().map(|&(males, _)| males).sum();
+
+    Indicators {
+        population,
+        crude_birth_rate: per_thousand(births),
+        crude_death_rate: per_thousand(deaths),
+        crude_growth_rate: per_thousand(population - start),
+        mean_age: ratio(ages.iter().enumerate()
+            .map(|(age, &(males, females))| (age as f64 + 0.5) * (males + females))
+            .sum(), population),
+        median_age: median(&ages),
+        youth_dependency: ratio(100.0 * young, working),
+        old_age_dependency: ratio(100.0 * old, working),
+        total_dependency: ratio(100.0 * (young + old), working),
+        sex_ratio: ratio(100.0 * males, population - males),
+        sex_ratios,
+        male_life_expectancy: life_expectancy.0,
+        female_life_expectancy: life_expectancy.1,
+        realized_tfr: ratio(births, fertility_exposure),
+        shares: bands.iter().map(|&band| (band, ratio(between(band), population))).collect(),
+    }
+}
+
+fn ratio(numerator: f64, denominator: f64) -> Option<f64> {
+    if denominator > 0.0 { Some(numerator / denominator) } else { None }
+}
+
+fn median(ages: &[(f64, f64)]) -> Option<f64> {
+    let counts: Vec<f64> = ages.iter().map(|&(males, females)| males + females).collect();
+    let half = counts.iter().sum::<f64>() / 2.0;
+    if half.is_nan() || half <= 0.0 { return None; }
+    let mut below = 0.0;
+    for (age, &count) in counts.iter().enumerate() {
+        if count > 0.0 && below + count >= half {
+            return Some(age as f64 + (half - below) / count);
+        }
+        below += count;
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::AgeBand;
+    use population::Population;
+
+    #[test]
+    fn bands_parse_and_print() {
+        let band: AgeBand = "15-49".parse().unwrap();
+        assert_eq!(band, AgeBand::new(15, Some(49)));
+        assert!(band.contains(15) && band.contains(49) && !band.contains(50));
+        assert_eq!(" 65+".parse::<AgeBand>().unwrap().to_string(), "65+");
+        for bad in ["", "15", "49-15", "a-b", "+"].iter() {
+            assert!(bad.parse::<AgeBand>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn indicators_describe_the_year() {
+        // A cohort of 1000 of each sex at every completed age from 0 to 79.
+        let mut pop = Population::<f64>::from_counts(vec![ ( 1_000.0, 1_000.0 ); 80 ]);
+        let before = pop.indicators(&[]);
+        assert_eq!(before.population, 160_000.0);
+        assert_eq!(before.mean_age, Some(40.0));
+        assert_eq!(before.median_age, Some(40.0));
+        assert_eq!(before.youth_dependency, Some(30.0));
+        assert_eq!(before.old_age_dependency, Some(30.0));
+        assert_eq!(before.sex_ratio, Some(100.0));
+        assert_eq!(before.sex_ratios.len(), 18);
+        assert_eq!(before.crude_birth_rate, Some(0.0));
+        assert_eq!(before.realized_tfr, None);
+        assert_eq!(pop.indicators(&["90+".parse().unwrap()]).shares[0].1, Some(0.0));
+
+        pop = pop.with_fertility_rate(2.5).advance_year();
+        let bands = ["15-49".parse().unwrap(), "80+".parse().unwrap()];
+        let after = pop.indicators(&bands);
+        let middle = (2.0 * pop.total_pop() - pop.births() + pop.deaths()) / 2.0;
+        let cbr = after.crude_birth_rate.unwrap();
+        assert!((cbr - 1000.0 * pop.births() / middle).abs() < 1e-9);
+        let growth = after.crude_growth_rate.unwrap();
+        assert!((growth - (cbr - after.crude_death_rate.unwrap())).abs() < 1e-9);
+        assert!((after.realized_tfr.unwrap() - 2.5).abs() < 1e-5);
+        assert!(after.female_life_expectancy > after.male_life_expectancy);
+        assert!(after.male_life_expectancy > 50.0 && after.female_life_expectancy < 100.0);
+        // Everyone is a year older, and the newborns are age zero.
+        let (teens, old) = (after.shares[0].1.unwrap(), after.shares[1].1.unwrap());
+        assert!(teens > 0.4 && teens < 0.45, "{}", teens);
+        assert!(old > 0.0 && old < 0.02, "{}", old);
+        // No one is 85 yet.
+        assert!(after.sex_ratios[0].is_some() && after.sex_ratios[17].is_none());
+    }
+
+    #[test]
+    fn realized_tfr_follows_the_draws() {
+        let mut pop = Population::new(vec![ ( 20_000, 20_000 ); 50 ])
+            .with_fertility_rate(1.8)
+            .stochastic(11);
+        for _ in 0..3 {
+            pop = pop.advance_year();
+            let tfr = pop.indicators(&[]).realized_tfr.unwrap();
+            assert!((tfr - 1.8).abs() < 0.05 && tfr != 1.8, "{}", tfr);
+        }
+
+        let empty = Population::new(vec![]).advance_year().indicators(&[]);
+        assert_eq!((empty.mean_age, empty.median_age, empty.sex_ratio), (None, None, None));
+        assert_eq!(empty.crude_birth_rate, None);
+    }
+}
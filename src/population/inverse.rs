@@ -0,0 +1,340 @@
+/*!
+Inverse projection: reconstructing past populations from counts of births and deaths alone.
+
+This follows Lee's method. Mortality each year has a fixed age pattern, the standard `q*(a)` of an
+`HPMortalityModel`, and a level `θ` that scales its hazards: `q(a) = 1 - (1 - q*(a))^θ`, so `θ`
+of one is the model itself, below one is lighter mortality and above one heavier. For each year,
+`θ` is whatever makes the age structure's expected deaths equal the recorded deaths. The
+survivors then make the next year's age structure.
+
+Age structures follow `Population::new`: index `i` holds those who have completed `i` years at
+the start of the year, and the last index is an open group of everyone that age or older. They
+have the same timing as the cohort engine, so during the year index `i` faces the rates for age
+`i + 1`, and the year's births face the rates for age zero before joining index zero.
+
+Reconstruction can run forwards from an initial structure, or backwards from a terminal one.
+Backwards, the open group's members could have come from either of the two oldest ages, so they
+are split in the proportion those ages have in the later year.
+
+Each year also gets the period life expectancy implied by its mortality, and the TFR implied by
+its births, given the women of each age (`female_share` of the structure) and the standard
+fertility pattern of a `Fertility`.
+*/
+
+use std::error::Error;
+use std::fmt;
+
+use super::cohort::fertility::Fertility;
+use super::cohort::heligman_pollard::HPMortalityModel;
+use ::optimize::bisect;
+
+/// Ages summed for life expectancy.
+const LIFE_TABLE_AGES: usize = 150;
+const TOLERANCE: f64 = 1e-12;
+
+#[derive(Debug)]
+pub struct InverseError { problem: String }
+
+impl InverseError {
+    pub fn new<S: Into<String>>(problem: S) -> InverseError {
+        InverseError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for InverseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for InverseError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InverseProjection {
+    pub mortality: HPMortalityModel,
+    pub fertility: Fertility,
+    /// The proportion of each age that is female.
+    pub female_share: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReconstructedYear {
+    pub year: i32,
+    /// The age structure at the start of the year.
+    pub population: Vec<f64>,
+    pub births: f64,
+    pub deaths: f64,
+    /// The year's mortality as a multiple of the model's hazards.
+    pub mortality_level: f64,
+    pub life_expectancy: f64,
+    pub total_fertility_rate: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reconstruction {
+    pub years: Vec<ReconstructedYear>,
+    /// The age structure at the end of the last year.
+    pub end: Vec<f64>,
+}
+
+impl InverseProjection {
+    pub fn new(mortality: HPMortalityModel, fertility: Fertility) -> InverseProjection {
+        InverseProjection { mortality, fertility, female_share: 0.5 }
+    }
+
+    pub fn with_female_share(mut self, female_share: f64) -> InverseProjection {
+        self.female_share = female_share;
+        self
+    }
+
+    /// The model's death probabilities scaled to a level, from age zero to `ages`.
+    fn mortality_at(&self, level: f64, ages: usize) -> Vec<f64> {
+        (0..ages + 1).map(|age| {
+            let standard = self.mortality.at_age(age as i32) as f64;
+            1.0 - (1.0 - standard).powf(level)
+        }).collect()
+    }
+
+    /// Reconstruct the years from `first_year`, given the age structure at its start and each
+    /// year's births and deaths.
+    pub fn forward(&self, first_year: i32, initial: &[f64], births: &[f64], deaths: &[f64])
+        -> Result<Reconstruction, InverseError>
+    {
+        check_inputs(initial, births, deaths)?;
+        let ages = initial.len();
+        let mut population = initial.to_vec();
+        let mut years = Vec::with_capacity(births.len());
+
+        for (index, (&born, &died)) in births.iter().zip(deaths.iter()).enumerate() {
+            let expected = |level: f64| {
+                let q = self.mortality_at(level, ages);
+                born * q[0] + population.iter().zip(&q[1..]).map(|(n, q)| n * q).sum::<f64>()
+            };
+            let possible = born + population.iter().sum::<f64>();
+            if died > possible {
+                return Err(InverseError::new(format!(
+                    "{} deaths in {} is more than the {} people there could have been.",
+                    died, first_year + index as i32, possible)));
+            }
+            let level = solve_level(expected, died)?;
+
+            let q = self.mortality_at(level, ages);
+            let mut next = vec![0.0; ages];
+            next[0] = born * (1.0 - q[0]);
+            for age in 1..ages {
+                next[age] = population[age - 1] * (1.0 - q[age]);
+            }
+            next[ages - 1] += population[ages - 1] * (1.0 - q[ages]);
+
+            years.push(self.year(first_year + index as i32, population, born, died, level));
+            population = next;
+        }
+        Ok(Reconstruction { years, end: population })
+    }
+
+    /// Reconstruct the years before `end_year`, given the age structure at its start and each
+    /// earlier year's births and deaths, oldest first.
+    pub fn backward(&self, end_year: i32, terminal: &[f64], births: &[f64], deaths: &[f64])
+        -> Result<Reconstruction, InverseError>
+    {
+        check_inputs(terminal, births, deaths)?;
+        if terminal.len() < 2 {
+            return Err(InverseError::new("Projecting backwards needs at least two ages."));
+        }
+        let ages = terminal.len();
+        let first_year = end_year - births.len() as i32;
+        let mut later = terminal.to_vec();
+        let mut years = Vec::with_capacity(births.len());
+
+        for index in (0..births.len()).rev() {
+            let (born, died) = (births[index], deaths[index]);
+            let earlier = |level: f64| self.survived_from(&later, level);
+            let expected = |level: f64| {
+                let q = self.mortality_at(level, ages);
+                born * q[0] + earlier(level).iter().zip(&q[1..]).map(|(n, q)| n * q).sum::<f64>()
+            };
+            let level = solve_level(expected, died)?;
+            let population = earlier(level);
+            years.push(self.year(first_year + index as i32, population.clone(), born, died, level));
+            later = population;
+        }
+        years.reverse();
+        Ok(Reconstruction { years, end: terminal.to_vec() })
+    }
+
+    /// The age structure a year earlier that survives at `level` into `later`.
+    fn survived_from(&self, later: &[f64], level: f64) -> Vec<f64> {
+        let ages = later.len();
+        let q = self.mortality_at(level, ages);
+        let mut earlier = vec![0.0; ages];
+        for age in 0..ages - 2 {
+            earlier[age] = later[age + 1] / (1.0 - q[age + 1]);
+        }
+        let (next_oldest, open) = (later[ages - 2], later[ages - 1]);
+        if next_oldest > 0.0 {
+            let ratio = open / next_oldest;
+            let from_next_oldest = open / ((1.0 - q[ages - 1]) + ratio * (1.0 - q[ages]));
+            earlier[ages - 2] = from_next_oldest;
+            earlier[ages - 1] = ratio * from_next_oldest;
+        } else {
+            earlier[ages - 1] = open / (1.0 - q[ages]);
+        }
+        earlier
+    }
+
+    fn year(&self, year: i32, population: Vec<f64>, births: f64, deaths: f64, level: f64)
+        -> ReconstructedYear
+    {
+        let q = self.mortality_at(level, LIFE_TABLE_AGES);
+        let mut alive = 1.0;
+        let mut life_expectancy = 0.5;
+        for q in q.iter().take(LIFE_TABLE_AGES) {
+            alive *= 1.0 - q;
+            life_expectancy += alive;
+        }
+
+        let exposure: f64 = population.iter().enumerate()
+            .map(|(index, n)| {
+                n * self.female_share * self.fertility.birth_rate(index + 1, 1.0)
+            })
+            .sum();
+        let total_fertility_rate = if exposure > 0.0 { births / exposure } else { 0.0 };
+
+        ReconstructedYear {
+            year,
+            population,
+            births,
+            deaths,
+            mortality_level: level,
+            life_expectancy,
+            total_fertility_rate,
+        }
+    }
+}
+
+impl Default for InverseProjection {
+    fn default() -> InverseProjection {
+        InverseProjection::new(HPMortalityModel::baseline_female(), Fertility::new(15, 50))
+    }
+}
+
+fn check_inputs(structure: &[f64], births: &[f64], deaths: &[f64]) -> Result<(), InverseError> {
+    if structure.is_empty() {
+        Err(InverseError::new("The age structure is empty."))
+    } else if births.len() != deaths.len() {
+        Err(InverseError::new(format!(
+            "There are {} years of births but {} of deaths.", births.len(), deaths.len())))
+    } else if structure.iter().chain(births).chain(deaths).any(|n| n.is_nan() || *n < 0.0) {
+        Err(InverseError::new("Counts must not be negative."))
+    } else {
+        Ok(())
+    }
+}
+
+/// The mortality level at which `expected` deaths match `deaths`. Expected deaths grow with the
+/// level, from none at level zero.
+fn solve_level<F: Fn(f64) -> f64>(expected: F, deaths: f64) -> Result<f64, InverseError> {
+    if deaths == 0.0 { return Ok(0.0); }
+    let mut upper = 1.0;
+    while expected(upper) < deaths {
+        upper *= 2.0;
+        if upper > 1e6 {
+            return Err(InverseError::new(format!("No mortality level gives {} deaths.", deaths)));
+        }
+    }
+    Ok(bisect(|level| expected(level) - deaths, 0.0, upper, TOLERANCE))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::InverseProjection;
+    use population::Population;
+    use population::cohort::heligman_pollard::HPMortalityModel;
+    use population::schedule::Schedule;
+
+    /// A run of the cohort engine with the same mortality for both sexes, as age structures
+    /// (from completed age zero, up to 129) and yearly births and deaths.
+    fn simulated(years: usize, tfr: f32) -> (Vec<Vec<f64>>, Vec<f64>, Vec<f64>) {
+        let schedule = Schedule {
+            male_mortality: HPMortalityModel::baseline_female(),
+            ..Schedule::default()
+        };
+        let mut pop = Population::<f64>::from_counts(vec![ ( 5_000.0, 5_000.0 ); 40 ])
+            .with_schedule(schedule)
+            .with_fertility_rate(tfr);
+        let structure = |pop: &Population<f64>| {
+            let mut ages = vec![0.0; 130];
+            for (age, &(m, f)) in pop.age_sex_distribution().iter().enumerate().skip(1) {
+                ages[age - 1] += m + f;
+            }
+            ages
+        };
+        let mut structures = vec![structure(&pop)];
+        let (mut births, mut deaths) = (Vec::new(), Vec::new());
+        for _ in 0..years {
+            pop = pop.advance_year();
+            structures.push(structure(&pop));
+            births.push(pop.births());
+            deaths.push(pop.deaths());
+        }
+        (structures, births, deaths)
+    }
+
+    #[test]
+    fn forward_recovers_a_simulated_run() {
+        let (structures, births, deaths) = simulated(60, 2.4);
+        let result = InverseProjection::default()
+            .forward(1700, &structures[0], &births, &deaths)
+            .unwrap();
+        assert_eq!(result.years.len(), 60);
+        assert_eq!(result.years[59].year, 1759);
+        for (year, structure) in result.years.iter().zip(structures.iter()) {
+            assert!((year.mortality_level - 1.0).abs() < 1e-5, "{}", year.mortality_level);
+            let tfr = year.total_fertility_rate;
+            assert!((tfr - 2.4).abs() < 1e-4, "{}", tfr);
+            for (a, b) in year.population.iter().zip(structure.iter()) {
+                assert!((a - b).abs() < 1e-3 * b.max(1.0));
+            }
+        }
+        let e0 = result.years[0].life_expectancy;
+        assert!(e0 > 60.0 && e0 < 95.0, "{}", e0);
+    }
+
+    #[test]
+    fn backward_recovers_a_simulated_run() {
+        let (structures, births, deaths) = simulated(30, 2.0);
+        let result = InverseProjection::default()
+            .backward(1830, &structures[30], &births, &deaths)
+            .unwrap();
+        assert_eq!(result.years[0].year, 1800);
+        for (year, structure) in result.years.iter().zip(structures.iter()) {
+            assert!((year.mortality_level - 1.0).abs() < 1e-4, "{}", year.mortality_level);
+            let total: f64 = structure.iter().sum();
+            let reconstructed: f64 = year.population.iter().sum();
+            assert!((total - reconstructed).abs() / total < 1e-4, "{} vs {}", total, reconstructed);
+        }
+    }
+
+    #[test]
+    fn heavier_mortality_raises_the_level() {
+        let (structures, births, deaths) = simulated(10, 2.0);
+        let heavier: Vec<f64> = deaths.iter().map(|d| d * 1.5).collect();
+        let result = InverseProjection::default()
+            .forward(0, &structures[0], &births, &heavier)
+            .unwrap();
+        for year in result.years.iter() {
+            assert!(year.mortality_level > 1.2);
+            assert!(year.life_expectancy < 80.0);
+        }
+        let impossible = vec![1e9; 10];
+        assert!(InverseProjection::default().forward(0, &structures[0], &births, &impossible)
+            .is_err());
+        assert!(InverseProjection::default().forward(0, &structures[0], &births, &deaths[1..])
+            .is_err());
+    }
+}
@@ -0,0 +1,420 @@
+/*!
+The Leslie projection matrix implied by a population's schedule, and its eigen-analysis.
+
+The state vector holds the members aged `1..=ages` at the start of a year, with the same age
+convention and timing as `Population::advance_year`: births come from the rates at each mother's
+current age, the newborns then face the mortality of age zero, and every other age faces its own
+mortality before moving up a year. Anyone who would age past `ages` leaves the matrix.
+
+A female-only matrix has one row per age. A two-sex matrix stacks the male ages after the female
+ones; men don't contribute births, so the males add rows but don't change `λ`.
+
+If the schedule has a terminal age within `ages`, the matrix stops there instead, and its last age
+is the open group: its members stay in it with the group's survival rate.
+
+The analysis needs a population that renews itself. Without births that anyone survives to give,
+as with a TFR of zero, there's no stable age distribution, and `analysis` says so rather than
+passing off the start of the iteration as one. `λ` comes from the fertile ages alone: the ages
+after them can't have children, so they only follow the births at a lag. An open group that
+survives better than the births grow is the exception; it would outgrow the rest of the
+population, so at a low enough TFR there's no stable age distribution either.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+
+use super::Population;
+use super::clock::Clock;
+use super::count::Count;
+use super::schedule::{Schedule, Tables};
+
+/// Power iteration stops once successive estimates agree this closely.
+const TOLERANCE: f64 = 1e-13;
+const MAX_ITERATIONS: usize = 1_000_000;
+
+#[derive(Debug)]
+pub struct LeslieError { problem: String }
+
+impl LeslieError {
+    pub fn new<S: Into<String>>(problem: S) -> LeslieError {
+        LeslieError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for LeslieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for LeslieError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeslieMatrix {
+    pub ages: usize,
+    pub two_sex: bool,
+    #[serde(default)]
+    pub open: bool,
+    rows: Vec<Vec<f64>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EigenAnalysis {
+    /// The dominant eigenvalue: the long-run factor the population grows by each year.
+    pub lambda: f64,
+    /// The right eigenvector, as proportions summing to one.
+    pub stable_age_distribution: Vec<f64>,
+    /// The left eigenvector, scaled so the youngest females have a reproductive value of one.
+    pub reproductive_values: Vec<f64>,
+    /// `∂λ/∂a_ij` for every entry, whether or not the entry is zero.
+    pub sensitivities: Vec<Vec<f64>>,
+    /// `(a_ij / λ) ∂λ/∂a_ij`, the proportional sensitivities. These sum to one.
+    pub elasticities: Vec<Vec<f64>>,
+}
+
+impl LeslieMatrix {
+    pub fn female(schedule: &Schedule, total_fertility_rate: f32, sex_ratio: f64, ages: usize)
+        -> LeslieMatrix
+    {
+        LeslieMatrix::build(schedule, total_fertility_rate, sex_ratio, ages, false)
+    }
+
+    pub fn two_sex(schedule: &Schedule, total_fertility_rate: f32, sex_ratio: f64, ages: usize)
+        -> LeslieMatrix
+    {
+        LeslieMatrix::build(schedule, total_fertility_rate, sex_ratio, ages, true)
+    }
+
+    /// The matrix for a population's schedule, TFR and sex ratio.
+    pub fn of_population<C: Count>(pop: &Population<C>, ages: usize, two_sex: bool)
+        -> LeslieMatrix
+    {
+        LeslieMatrix::build
+            ( pop.schedule()
+            , pop.total_fertility_rate()
+            , pop.sex_ratio()
+            , ages
+            , two_sex )
+    }
+
+    fn build(
+        schedule: &Schedule,
+        total_fertility_rate: f32,
+        sex_ratio: f64,
+        ages: usize,
+        two_sex: bool,
+    ) -> LeslieMatrix {
+        let tables = Tables::new(schedule, &Clock::new(0, 1));
+        let (ages, open) = match schedule.terminal_age {
+            Some(terminal_age) if terminal_age >= 1 && terminal_age as usize <= ages =>
+                (terminal_age as usize, true),
+            _ => (ages, false),
+        };
+        let size = if two_sex { 2 * ages } else { ages };
+        let mut rows = vec![vec![0.0; size]; size];
+
+        let female_births = (1.0 - sex_ratio) * (1.0 - tables.female_q(0));
+        let male_births = sex_ratio * (1.0 - tables.male_q(0));
+        for index in 0..ages {
+            let age = index as i32 + 1;
+            let fertility = tables.birth_rate(age, total_fertility_rate);
+            rows[0][index] = female_births * fertility;
+            if two_sex { rows[ages][index] = male_births * fertility; }
+
+            if index + 1 < ages {
+                rows[index + 1][index] = 1.0 - tables.female_q(age);
+                if two_sex {
+                    rows[ages + index + 1][ages + index] = 1.0 - tables.male_q(age);
+                }
+            }
+        }
+        if open {
+            let last = ages - 1;
+            rows[last][last] = 1.0 - tables.female_q(ages as i32);
+            if two_sex { rows[ages + last][ages + last] = 1.0 - tables.male_q(ages as i32); }
+        }
+
+        LeslieMatrix { ages, two_sex, open, rows }
+    }
+
+    pub fn size(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn rows(&self) -> &[Vec<f64>] {
+        &self.rows
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.rows[row][column]
+    }
+
+    /// A population's members as a state vector for this matrix.
+    pub fn state_of<C: Count>(&self, pop: &Population<C>) -> Vec<f64> {
+        let mut state = vec![0.0; self.size()];
+        for (age, &(males, females)) in pop.age_sex_distribution().iter().enumerate() {
+            if age == 0 || (age > self.ages && !self.open) { continue; }
+            let index = age.min(self.ages) - 1;
+            state[index] += females.to_f64();
+            if self.two_sex { state[self.ages + index] += males.to_f64(); }
+        }
+        state
+    }
+
+    /// Advance a state vector one year.
+    pub fn project(&self, state: &[f64]) -> Vec<f64> {
+        self.rows.iter()
+            .map(|row| row.iter().zip(state).map(|(a, x)| a * x).sum())
+            .collect()
+    }
+
+    fn nonzero(&self) -> Vec<(usize, usize, f64)> {
+        let mut entries = Vec::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            for (j, &a) in row.iter().enumerate() {
+                if a != 0.0 { entries.push((i, j, a)); }
+            }
+        }
+        entries
+    }
+
+    /// The female ages from birth through the last fertile age that anyone born survives to.
+    /// Every age in it leads to every other, so it has a single dominant eigenvalue; the ages
+    /// past it, the open group and the men only feed on it.
+    fn reproductive_ages(&self) -> usize {
+        let mut block = 0;
+        for index in 0..self.ages {
+            if self.rows[0][index] > 0.0 { block = index + 1; }
+            if index + 1 == self.ages || self.rows[index + 1][index] == 0.0 { break; }
+        }
+        block
+    }
+
+    /// The dominant eigenvalue and eigenvectors of the reproductive ages by power iteration,
+    /// extended to the rest of the matrix, and the sensitivities and elasticities that follow
+    /// from them. Fails if no one born survives to give birth, so that the newborns have no
+    /// reproductive value; if the iteration doesn't settle, as it won't if every birth comes at
+    /// the same age; or if some age past the fertile ones, like the open group, survives at `λ`
+    /// or better, so that its members come to outnumber everyone else.
+    pub fn analysis(&self) -> Result<EigenAnalysis, LeslieError> {
+        let block = if self.rows.is_empty() { 0 } else { self.reproductive_ages() };
+        if block == 0 {
+            return Err(LeslieError::new(
+                "No one born survives to have children, so there's no stable age distribution."));
+        }
+        let entries: Vec<_> = self.nonzero().into_iter()
+            .filter(|&(i, j, _)| i < block && j < block)
+            .collect();
+
+        let (lambda, mut right, settled) = power_iteration(block, |x, out| {
+            for &(i, j, a) in entries.iter() { out[i] += a * x[j]; }
+        });
+        let (_, mut left, left_settled) = power_iteration(block, |x, out| {
+            for &(i, j, a) in entries.iter() { out[j] += a * x[i]; }
+        });
+        if !settled || !left_settled {
+            return Err(LeslieError::new(format!(
+                "The power iteration didn't settle in {} steps; the matrix may be periodic.",
+                MAX_ITERATIONS)));
+        }
+
+        // The other ages only take members from younger ages or from births, and only give
+        // them to older ages, so each follows from the ones before it in the right vector, and
+        // from the ones after it in the left.
+        let size = self.size();
+        right.resize(size, 0.0);
+        left.resize(size, 0.0);
+        for i in block..size {
+            let staying = self.rows[i][i];
+            if staying >= lambda {
+                return Err(LeslieError::new(format!(
+                    "Those in age class {} survive at {}, at least λ = {}, so they come to \
+                     outnumber everyone else and there's no stable age distribution.",
+                    i % self.ages + 1, staying, lambda)));
+            }
+            let arriving: f64 = (0..i).map(|j| self.rows[i][j] * right[j]).sum();
+            right[i] = arriving / (lambda - staying);
+        }
+        for j in (block..size).rev() {
+            let giving: f64 = (0..size).filter(|&i| i != j && (i < block || i > j))
+                .map(|i| left[i] * self.rows[i][j])
+                .sum();
+            left[j] = giving / (lambda - self.rows[j][j]);
+        }
+        let first = left[0];
+
+        let total: f64 = right.iter().sum();
+        for w in right.iter_mut() { *w /= total; }
+        for v in left.iter_mut() { *v /= first; }
+
+        let scale: f64 = left.iter().zip(right.iter()).map(|(v, w)| v * w).sum();
+        let sensitivities: Vec<Vec<f64>> = left.iter().map(|v| {
+            right.iter().map(|w| v * w / scale).collect()
+        }).collect();
+        let elasticities = sensitivities.iter().zip(self.rows.iter()).map(|(s_row, a_row)| {
+            s_row.iter().zip(a_row).map(|(s, a)| a / lambda * s).collect()
+        }).collect();
+
+        Ok(EigenAnalysis {
+            lambda,
+            stable_age_distribution: right,
+            reproductive_values: left,
+            sensitivities,
+            elasticities,
+        })
+    }
+
+    /// The matrix as CSV, one row per line, with a header naming each age class.
+    pub fn to_csv(&self) -> String {
+        let labels: Vec<String> = (0..self.size()).map(|index| {
+            let sex = if index < self.ages { "f" } else { "m" };
+            format!("{}{}", sex, index % self.ages + 1)
+        }).collect();
+
+        let mut csv = String::new();
+        writeln!(csv, ",{}", labels.join(",")).unwrap();
+        for (label, row) in labels.iter().zip(self.rows.iter()) {
+            let values: Vec<String> = row.iter().map(|a| a.to_string()).collect();
+            writeln!(csv, "{},{}", label, values.join(",")).unwrap();
+        }
+        csv
+    }
+}
+
+/// Repeatedly apply `multiply` (which adds `A·x` into its output) to a positive start vector,
+/// normalizing each time. Returns the growth factor, the vector it settles on, and whether it
+/// did settle. A vector that dies out leaves a growth factor of zero.
+fn power_iteration<F>(size: usize, multiply: F) -> (f64, Vec<f64>, bool)
+    where F: Fn(&[f64], &mut [f64])
+{
+    let mut x = vec![1.0 / size as f64; size];
+    let mut lambda = 0.0;
+    for _ in 0..MAX_ITERATIONS {
+        let mut next = vec![0.0; size];
+        multiply(&x, &mut next);
+        let norm: f64 = next.iter().sum();
+        if norm.is_nan() || norm <= 0.0 { return (0.0, x, true); }
+        for value in next.iter_mut() { *value /= norm; }
+
+        let change: f64 = next.iter().zip(x.iter()).map(|(a, b)| (a - b).abs()).sum();
+        x = next;
+        let converged = change < TOLERANCE && (norm - lambda).abs() < TOLERANCE;
+        lambda = norm;
+        if converged { return (lambda, x, true); }
+    }
+    (lambda, x, false)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::LeslieMatrix;
+    use population::Population;
+    use population::schedule::Schedule;
+
+    #[test]
+    fn lambda_matches_lotka() {
+        let schedule = Schedule::default();
+        for &tfr in [1.4, 2.1, 3.5].iter() {
+            let pop = Population::new(vec![]).with_fertility_rate(tfr);
+            let r = pop.reproduction().intrinsic_growth_rate;
+            let female = LeslieMatrix::female(&schedule, tfr, 0.5, 100).analysis().unwrap();
+            let two_sex = LeslieMatrix::two_sex(&schedule, tfr, 0.5, 100).analysis().unwrap();
+            assert!((female.lambda.ln() - r).abs() < 1e-9, "{} vs {}", female.lambda.ln(), r);
+            assert!((two_sex.lambda - female.lambda).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn no_births_has_no_stable_distribution() {
+        let schedule = Schedule::default();
+        assert!(LeslieMatrix::female(&schedule, 0.0, 0.5, 100).analysis().is_err());
+        let open = schedule.with_terminal_age(85);
+        assert!(LeslieMatrix::two_sex(&open, 0.0, 0.5, 100).analysis().is_err());
+        assert!(LeslieMatrix::two_sex(&open, 2.1, 0.5, 100).analysis().is_ok());
+    }
+
+    #[test]
+    fn open_group_follows_the_fertile_ages() {
+        let schedule = Schedule::default().with_terminal_age(51);
+        let pop = Population::new(vec![]).with_fertility_rate(2.1);
+        let r = pop.reproduction().intrinsic_growth_rate;
+        let matrix = LeslieMatrix::two_sex(&schedule, 2.1, 0.5, 100);
+        let analysis = matrix.analysis().unwrap();
+        assert!((analysis.lambda.ln() - r).abs() < 1e-9, "{} vs {}", analysis.lambda.ln(), r);
+        let projected = matrix.project(&analysis.stable_age_distribution);
+        for (p, w) in projected.iter().zip(analysis.stable_age_distribution.iter()) {
+            assert!((p - analysis.lambda * w).abs() < 1e-12);
+        }
+
+        for &tfr in [0.5, 0.3].iter() {
+            assert!(LeslieMatrix::female(&schedule, tfr, 0.5, 100).analysis().is_err());
+        }
+        assert!(LeslieMatrix::female(&Schedule::default(), 0.0, 0.5, 0).analysis().is_err());
+    }
+
+    #[test]
+    fn projection_matches_cohort_engine() {
+        let mut pop = Population::new(vec![ ( 1_000_000, 1_000_000 ); 60 ]);
+        let matrix = LeslieMatrix::of_population(&pop, 120, true);
+        let mut state = matrix.state_of(&pop);
+        for _ in 0..50 {
+            pop = pop.advance_year();
+            state = matrix.project(&state);
+        }
+        let projected: f64 = state.iter().sum();
+        let simulated = pop.total_pop() as f64;
+        assert!((projected - simulated).abs() / simulated < 1e-4, "{} vs {}", projected, simulated);
+    }
+
+    #[test]
+    fn long_run_growth_matches_lambda() {
+        let mut pop = Population::new(vec![ ( 1_000_000, 1_000_000 ) ]).with_fertility_rate(2.6);
+        let lambda = LeslieMatrix::of_population(&pop, 100, false).analysis().unwrap().lambda;
+        for _ in 0..400 { pop = pop.advance_year(); }
+        let before = pop.total_pop() as f64;
+        pop = pop.advance_year();
+        assert!((pop.total_pop() as f64 / before - lambda).abs() < 1e-3);
+    }
+
+    #[test]
+    fn eigenvectors_and_elasticities() {
+        let matrix = LeslieMatrix::female(&Schedule::default(), 2.5, 0.5, 80);
+        let analysis = matrix.analysis().unwrap();
+
+        let projected = matrix.project(&analysis.stable_age_distribution);
+        for (p, w) in projected.iter().zip(analysis.stable_age_distribution.iter()) {
+            assert!((p - analysis.lambda * w).abs() < 1e-10);
+        }
+        assert!((analysis.stable_age_distribution.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert_eq!(analysis.reproductive_values[0], 1.0);
+        assert!(analysis.reproductive_values[70] < 1e-9);
+
+        let total: f64 = analysis.elasticities.iter().flat_map(|row| row.iter()).sum();
+        assert!((total - 1.0).abs() < 1e-8);
+        assert_eq!(matrix.to_csv().lines().count(), 81);
+    }
+
+    #[test]
+    fn open_group_matches_cohort_engine() {
+        let initial = vec![ ( 1e6, 1e6 ); 80 ];
+        let mut pop = Population::<f64>::from_counts(initial).with_terminal_age(70);
+        let matrix = LeslieMatrix::of_population(&pop, 120, true);
+        assert_eq!((matrix.ages, matrix.open), (70, true));
+        let mut state = matrix.state_of(&pop);
+        assert!((state.iter().sum::<f64>() - pop.total_pop()).abs() < 1e-6);
+        for _ in 0..50 {
+            pop = pop.advance_year();
+            state = matrix.project(&state);
+        }
+        let projected: f64 = state.iter().sum();
+        let simulated = pop.total_pop() - pop.age_sex_distribution()[0].0
+            - pop.age_sex_distribution()[0].1;
+        assert!((projected - simulated).abs() / simulated < 1e-6, "{} vs {}", projected, simulated);
+    }
+}
@@ -0,0 +1,153 @@
+/*!
+Period life tables: what a schedule's mortality means for a group of newborns followed to the end
+of their lives.
+
+Each row follows the usual columns, for a radix of `RADIX` births: the probability of dying
+before the next birthday `q`, the survivors to each age `l`, the deaths `d`, the person-years
+lived in the year of age `L` (with those who die living half of it), the person-years remaining
+`T`, and the life expectancy `e`. A schedule with a terminal age ends in an open row for the group,
+whose person-years are the group's stationary survivors. Without one, the table ends once the
+survivors are negligible, or at `LIFE_TABLE_LIMIT`.
+*/
+
+use std::fmt::Write;
+
+use super::schedule::Schedule;
+
+/// Newborns at the top of the table.
+pub const RADIX: f64 = 100_000.0;
+/// Tables stop here, if they haven't run out of survivors before.
+pub const LIFE_TABLE_LIMIT: i32 = 250;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LifeTableRow {
+    pub age: i32,
+    pub q: f64,
+    pub l: f64,
+    pub d: f64,
+    #[serde(rename = "L")]
+    pub person_years: f64,
+    #[serde(rename = "T")]
+    pub remaining_years: f64,
+    pub e: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LifeTable {
+    pub rows: Vec<LifeTableRow>,
+    /// Whether the last row is an open age group.
+    pub open: bool,
+}
+
+impl LifeTable {
+    /// A table from the death probability at each age, closed with `open_mortality` from
+    /// `terminal_age` if there's one.
+    pub fn new<F>(mortality: F, terminal_age: Option<i32>, open_mortality: Option<f64>)
+        -> LifeTable
+        where F: Fn(i32) -> f32
+    {
+        let last = terminal_age.unwrap_or(LIFE_TABLE_LIMIT);
+        let mut rows = Vec::new();
+        let mut alive = RADIX;
+        for age in 0..last {
+            let q = mortality(age) as f64;
+            let d = alive * q;
+            rows.push(LifeTableRow {
+                age, q, l: alive, d, person_years: alive - d / 2.0, remaining_years: 0.0, e: 0.0,
+            });
+            alive -= d;
+            if alive < RADIX * 1e-15 { break; }
+        }
+        let open = match (terminal_age, open_mortality) {
+            (Some(age), Some(q)) if alive >= RADIX * 1e-15 => {
+                // The group's survivors, summed from the start of each year, less the half year
+                // that puts them in the middle of it.
+                let person_years = alive * (1.0 / q - 0.5);
+                rows.push(LifeTableRow {
+                    age, q: 1.0, l: alive, d: alive, person_years, remaining_years: 0.0, e: 0.0,
+                });
+                true
+            },
+            _ => false,
+        };
+        let mut remaining = 0.0;
+        for row in rows.iter_mut().rev() {
+            remaining += row.person_years;
+            row.remaining_years = remaining;
+            row.e = if row.l > 0.0 { remaining / row.l } else { 0.0 };
+        }
+        LifeTable { rows, open }
+    }
+
+    pub fn male(schedule: &Schedule) -> LifeTable {
+        let mortality = |age| schedule.male_mortality_at(age);
+        LifeTable::new(mortality, schedule.terminal_age, schedule.open_mortality(mortality))
+    }
+
+    pub fn female(schedule: &Schedule) -> LifeTable {
+        let mortality = |age| schedule.female_mortality_at(age);
+        LifeTable::new(mortality, schedule.terminal_age, schedule.open_mortality(mortality))
+    }
+
+    /// Life expectancy at birth.
+    pub fn life_expectancy(&self) -> f64 {
+        self.rows.first().map_or(0.0, |row| row.e)
+    }
+
+    /// The row for an age, or the open row if the age is in it.
+    pub fn at(&self, age: i32) -> Option<&LifeTableRow> {
+        match self.rows.last() {
+            Some(last) if self.open && age >= last.age => Some(last),
+            _ => self.rows.iter().find(|row| row.age == age),
+        }
+    }
+
+    /// The table as CSV, with a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("age,q,l,d,L,T,e\n");
+        for (index, row) in self.rows.iter().enumerate() {
+            let open = self.open && index == self.rows.len() - 1;
+            writeln!(csv, "{}{},{},{},{},{},{},{}", row.age, if open { "+" } else { "" },
+                     row.q, row.l, row.d, row.person_years, row.remaining_years, row.e).unwrap();
+        }
+        csv
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{LifeTable, RADIX};
+    use population::schedule::Schedule;
+
+    #[test]
+    fn columns_are_consistent() {
+        let table = LifeTable::male(&Schedule::default());
+        assert!(!table.open);
+        let first = table.rows[0];
+        assert_eq!((first.age, first.l), (0, RADIX));
+        for pair in table.rows.windows(2) {
+            assert!((pair[0].l - pair[0].d - pair[1].l).abs() < 1e-6);
+            assert!(pair[0].e > pair[1].e - 1.0);
+        }
+        let e0 = table.life_expectancy();
+        assert!(e0 > 50.0 && e0 < 90.0, "{}", e0);
+        assert!((first.remaining_years / RADIX - e0).abs() < 1e-9);
+        assert_eq!(table.to_csv().lines().count(), table.rows.len() + 1);
+    }
+
+    #[test]
+    fn open_groups_close_the_table() {
+        let closed = Schedule::default();
+        let open = Schedule::default().with_terminal_age(85);
+        let (closed, open) = (LifeTable::female(&closed), LifeTable::female(&open));
+        assert!(open.open);
+        assert_eq!(open.rows.len(), 86);
+        assert_eq!(open.at(100), open.rows.last());
+        assert_eq!(open.at(40).unwrap().l, closed.at(40).unwrap().l);
+        assert!(open.to_csv().lines().last().unwrap().starts_with("85+,"));
+        // The open group stands in for the same survivors, so the expectancy barely changes.
+        let (e0, open_e0) = (closed.life_expectancy(), open.life_expectancy());
+        assert!((e0 - open_e0).abs() < 1.0, "{} {}", e0, open_e0);
+    }
+}
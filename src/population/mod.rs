@@ -1,75 +1,620 @@
 pub mod cohort;
 pub mod attributes;
+pub mod reproduction;
+pub mod clock;
+pub mod schedule;
+pub mod leslie;
+pub mod count;
+pub mod checkpoint;
+pub mod history;
+pub mod observer;
+pub mod inverse;
+pub mod indicators;
+pub mod life_table;
 
+use std::collections::VecDeque;
+use std::mem;
+
+use self::clock::Clock;
 use self::cohort::Cohort;
+use self::count::Count;
+use self::history::{History, KEYFRAME_INTERVAL};
+use self::observer::{Observer, Observers};
+use self::reproduction::Reproduction;
+use self::schedule::{Schedule, Tables};
+use ::random::Random;
 
 
 /**
 A population consists of a large number of cohorts, within a geographical area. The remainders are
 a way to account for very slow population growth, without resorting to random numbers.
+
+Cohorts are kept youngest first in a ring buffer, so a new generation is added without shifting
+the others, and every cohort is updated in place. All cohorts share the population's `Schedule`,
+which is evaluated into per-age tables once rather than for every cohort in every year.
+
+The sex ratio is the proportion of births that are male.
+
+A population given a random number generator runs in stochastic mode instead: births and deaths
+are drawn from binomial (or Poisson) distributions, and no remainders are kept. The generator
+state is part of the population, so the same seed always produces the same run.
+
+Ages are counted as `current_year - birth_year`, the age each cohort's rates are looked up at
+during the coming year. Births and deaths are totals for the year so far, or for the whole of the
+most recent year once it is complete.
+
+A year can be split into several steps (see `Clock`), each with its own newborn cohort. Annual
+rates are converted to per-step rates, so with constant rates the results agree with annual
+stepping.
+
+Members are counted in whole individuals by default. A population can instead count in real
+numbers (`Population<f64>`), which needs no truncation and so keeps no remainders; see `Count`.
+
+A population can also keep a `History` of every year it runs, to be queried afterwards, and
+report each step to `Observer`s as it runs.
 */
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Population {
-    cohorts: Vec<Cohort>,
+pub struct Population<C = usize> {
+    cohorts: VecDeque<Cohort<C>>,
+    schedule: Schedule,
+    #[serde(skip)]
+    tables: Tables,
     male_remainder: f64,
     female_remainder: f64,
-    current_year: i32,
+    clock: Clock,
+    total_fertility_rate: f32,
+    sex_ratio: f64,
+    rng: Option<Random>,
+    births: C,
+    deaths: C,
+    #[serde(default = "no_history")]
+    history: Option<History<C>>,
+    /// The year's births per unit of TFR so far, for the realized TFR.
+    #[serde(default)]
+    fertility_exposure: f64,
+    #[serde(skip, default = "Observers::default")]
+    observers: Observers<C>,
 }
 
+fn no_history<C>() -> Option<History<C>> { None }
+
 impl Population {
     pub fn new(initial_gens: Vec<(usize, usize)>) -> Population {
-        let cohorts = initial_gens.iter().enumerate().map(|(year, (male, female))| {
-            Cohort::new(*male, *female, -(year as i32 + 1))
+        Population::from_counts(initial_gens)
+    }
+}
+
+impl<C: Count> Population<C> {
+    /// Like `Population::new`, for any kind of count.
+    pub fn from_counts(initial_gens: Vec<(C, C)>) -> Population<C> {
+        let cohorts = initial_gens.iter().enumerate().map(|(year, &(male, female))| {
+            Cohort::new(male, female, -(year as i32 + 1))
         });
         Population { cohorts: cohorts.collect()
+            , schedule: Schedule::default()
+            , tables: Tables::default()
             , male_remainder: 0.0
             , female_remainder: 0.0
-            , current_year: 0 }
+            , clock: Clock::new(0, 1)
+            , total_fertility_rate: 2.028
+            , sex_ratio: 0.5
+            , rng: None
+            , births: C::default()
+            , deaths: C::default()
+            , history: None
+            , fertility_exposure: 0.0
+            , observers: Observers::default() }
+    }
+
+    /// Switch to stochastic mode, seeding the random number generator.
+    pub fn stochastic(mut self, seed: u64) -> Population<C> {
+        self.rng = Some(Random::new(seed));
+        self
+    }
+
+    /// Divide each year into `steps` equal steps. Takes effect from the start of the next year.
+    pub fn with_steps_per_year(mut self, steps: u32) -> Population<C> {
+        let year = if self.clock.is_start_of_year() { self.clock.year } else { self.clock.year + 1 };
+        self.clock = Clock::new(year, steps);
+        self
+    }
+
+    /// Move the calendar so the population starts in `year`, keeping every cohort's age.
+    pub fn with_start_year(mut self, year: i32) -> Population<C> {
+        let shift = year - self.clock.year;
+        for cohort in self.cohorts.iter_mut() {
+            cohort.birth_year += shift;
+        }
+        self.clock.year = year;
+        self
+    }
+
+    /// Start recording a history from the current year; see `History`.
+    pub fn with_history(self) -> Population<C> {
+        self.with_history_keyframes(KEYFRAME_INTERVAL)
+    }
+
+    /// Start recording a history that keeps a full distribution every `interval` years.
+    pub fn with_history_keyframes(mut self, interval: usize) -> Population<C> {
+        let clock = self.clock;
+        let year = if clock.is_start_of_year() { clock.year } else { clock.year + 1 };
+        self.history = Some(History::new(year, self.age_sex_distribution(), interval));
+        self
+    }
+
+    pub fn history(&self) -> Option<&History<C>> {
+        self.history.as_ref()
+    }
+
+    /// Attach an observer, to be told about every step from now on.
+    pub fn with_observer<O: Observer<C> + 'static>(mut self, observer: O) -> Population<C> {
+        self.observers.push(observer);
+        self
+    }
+
+    pub fn is_stochastic(&self) -> bool {
+        self.rng.is_some()
+    }
+
+    pub fn with_fertility_rate(mut self, total_fertility_rate: f32) -> Population<C> {
+        self.total_fertility_rate = total_fertility_rate;
+        self
+    }
+
+    pub fn with_sex_ratio(mut self, sex_ratio: f64) -> Population<C> {
+        self.sex_ratio = sex_ratio;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Population<C> {
+        self.schedule = schedule;
+        self.tables = Tables::default();
+        self
+    }
+
+    /// Treat everyone `age` and older as a single open age group; see `Schedule`.
+    pub fn with_terminal_age(self, age: i32) -> Population<C> {
+        let schedule = self.schedule.clone().with_terminal_age(age);
+        self.with_schedule(schedule)
+    }
+
+    /// Let no one live past `age`.
+    pub fn with_max_age(self, age: i32) -> Population<C> {
+        let schedule = self.schedule.clone().with_max_age(age);
+        self.with_schedule(schedule)
+    }
+
+    /// Set the TFR to exactly the replacement level for the current mortality regime.
+    pub fn at_replacement(self) -> Population<C> {
+        let replacement = self.reproduction().replacement_tfr;
+        self.with_fertility_rate(replacement as f32)
+    }
+
+    /// Reproduction measures for the population's schedule.
+    pub fn reproduction(&self) -> Reproduction {
+        Reproduction::new
+            ( &self.schedule.fertility
+            , &self.schedule.female_mortality
+            , self.total_fertility_rate
+            , self.sex_ratio )
     }
 
-    pub fn advance_year(mut self) -> Population {
-        let babies: Vec<f64> = self.cohorts.iter().map(|cohort| {
-            cohort.births(self.current_year, 2.028)
-        }).collect();
+    /// Run every remaining step of the current year.
+    pub fn advance_year(self) -> Population<C> {
+        let mut pop = self.advance_step();
+        while !pop.clock.is_start_of_year() {
+            pop = pop.advance_step();
+        }
+        pop
+    }
 
+    pub fn advance_step(mut self) -> Population<C> {
+        if !self.tables.fits(&self.clock) {
+            self.tables = Tables::new(&self.schedule, &self.clock);
+        }
+        let mut observers = mem::take(&mut self.observers);
+        if self.clock.is_start_of_year() {
+            self.births = C::default();
+            self.deaths = C::default();
+            self.fertility_exposure = 0.0;
+            observers.year_start(&self);
+        }
+        if self.rng.is_some() { self.step_stochastic(&mut observers) }
+        else { self.step_deterministic(&mut observers) }
+        self.clock.advance();
+        if self.clock.is_start_of_year() {
+            if self.history.is_some() {
+                let distribution = self.age_sex_distribution();
+                if let Some(history) = self.history.as_mut() { history.end_year(distribution); }
+            }
+            observers.year_end(&self);
+        }
+        self.observers = observers;
+        self
+    }
 
-        let total_babies: f64 = babies.iter().sum::<f64>();
-        let sex_ratio = 0.5;
+    fn step_deterministic(&mut self, observers: &mut Observers<C>) {
+        let clock = self.clock;
+        let tfr = self.total_fertility_rate;
+        let tables = &self.tables;
+        let total_babies: f64 = self.cohorts.iter()
+            .map(|cohort| cohort.births(&clock, tables, tfr))
+            .sum();
+        self.fertility_exposure += exposure(&self.cohorts, &clock, tables);
+
+        let sex_ratio = self.sex_ratio;
         let males = (total_babies * sex_ratio) + self.male_remainder;
         let females = (total_babies * (1.0 - sex_ratio)) + self.female_remainder;
 
-        let new_gen = Cohort::new
-            ( males.trunc() as usize
-            , females.trunc() as usize
-            , self.current_year );
-        self.male_remainder = males.fract();
-        self.female_remainder = females.fract();
+        let (males, male_remainder) = C::expect_real(males);
+        let (females, female_remainder) = C::expect_real(females);
+        let new_gen = Cohort::born_at(males, females, &clock);
+        self.male_remainder = male_remainder;
+        self.female_remainder = female_remainder;
+
 
+        self.births = self.births.plus(new_gen.members.total());
+        if let Some(history) = self.history.as_mut() { history.count_births(males, females); }
+        observers.births(&clock, males, females);
+        self.cohorts.push_front(new_gen);
 
-        self.cohorts.insert(0, new_gen);
+        let before = self.total_pop();
+        let terminal_age = self.schedule.terminal_age;
+        for cohort in self.cohorts.iter_mut() {
+            let alive = (cohort.members.males, cohort.members.females);
+            let survived = cohort.perform_deaths(&clock, tables, C::default(), C::default());
+            report_deaths(self.history.as_mut(), observers, cohort, alive, survived, &clock,
+                terminal_age);
+        }
+        self.cohorts.retain(|cohort| !cohort.members.total().is_empty());
+        self.merge_open_group();
+        self.deaths = self.deaths.plus(before - self.total_pop());
+    }
 
-        let year = self.current_year;
-        self.cohorts = self.cohorts
-            .into_iter()
-            .filter_map(|cohort| cohort.perform_deaths(year, 0, 0))
-            .collect();
+    fn step_stochastic(&mut self, observers: &mut Observers<C>) {
+        let clock = self.clock;
+        let tfr = self.total_fertility_rate;
+        let sex_ratio = self.sex_ratio;
+        let tables = &self.tables;
+        let mut rng = self.rng.take().expect("stochastic mode without a generator");
 
-        self.current_year += 1;
+        let total_babies = self.cohorts.iter()
+            .map(|cohort| cohort.random_births(&clock, tables, tfr, &mut rng))
+            .fold(0u64, |total, births| {
+                total.checked_add(births).expect("births overflowed stochastic mode's u64 draws")
+            });
+        self.fertility_exposure += exposure(&self.cohorts, &clock, tables);
+        let males = rng.binomial(total_babies, sex_ratio);
+        let females = total_babies - males;
 
+        let (males, females) = (C::from_trials(males), C::from_trials(females));
+        self.births = self.births.plus(C::from_trials(total_babies));
+        if let Some(history) = self.history.as_mut() { history.count_births(males, females); }
+        observers.births(&clock, males, females);
+        let new_gen = Cohort::born_at(males, females, &clock);
+        self.cohorts.push_front(new_gen);
 
-        self
+        let before = self.total_pop();
+        let terminal_age = self.schedule.terminal_age;
+        for cohort in self.cohorts.iter_mut() {
+            let alive = (cohort.members.males, cohort.members.females);
+            let survived = cohort
+                .perform_random_deaths(&clock, tables, C::default(), C::default(), &mut rng);
+            report_deaths(self.history.as_mut(), observers, cohort, alive, survived, &clock,
+                terminal_age);
+        }
+        self.cohorts.retain(|cohort| !cohort.members.total().is_empty());
+        self.merge_open_group();
+        self.deaths = self.deaths.plus(before - self.total_pop());
+
+        self.rng = Some(rng);
+    }
+
+    /// Fold every cohort in the open age group into the oldest, which stands for the group.
+    fn merge_open_group(&mut self) {
+        let terminal_age = match self.schedule.terminal_age {
+            Some(terminal_age) => terminal_age,
+            None => return,
+        };
+        let clock = self.clock;
+        let is_open = |cohort: &Cohort<C>| {
+            cohort.age(&clock).is_some_and(|age| age >= terminal_age)
+        };
+        let mut group = match self.cohorts.pop_back() {
+            Some(oldest) => oldest,
+            None => return,
+        };
+        while self.cohorts.back().is_some_and(&is_open) {
+            let cohort = self.cohorts.pop_back().unwrap();
+            group.absorb(cohort);
+        }
+        self.cohorts.push_back(group);
     }
 
-    pub fn total_pop(&self) -> usize {
-        self.cohorts.iter().map(|cohort| { cohort.members.total() }).sum()
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
     }
 
-    pub fn total_male(&self) -> usize {
-        self.cohorts.iter().map(|cohort| { cohort.members.males }).sum()
+    pub fn total_fertility_rate(&self) -> f32 {
+        self.total_fertility_rate
     }
 
-    pub fn total_female(&self) -> usize {
-        self.cohorts.iter().map(|cohort| { cohort.members.females }).sum()
+    pub fn sex_ratio(&self) -> f64 {
+        self.sex_ratio
     }
 
+    pub fn current_year(&self) -> i32 {
+        self.clock.year
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn steps_per_year(&self) -> u32 {
+        self.clock.steps_per_year
+    }
+
+    pub fn births(&self) -> C {
+        self.births
+    }
+
+    pub fn deaths(&self) -> C {
+        self.deaths
+    }
+
+    pub fn cohorts(&self) -> &VecDeque<Cohort<C>> {
+        &self.cohorts
+    }
+
+    /// Males and females at each age, starting from age zero. These are the ages whose rates each
+    /// cohort faces in the coming year; see `completed_ages` for the ages everything reports. An
+    /// open age group is counted at the terminal age.
+    pub fn age_sex_distribution(&self) -> Vec<(C, C)> {
+        let mut distribution = Vec::new();
+        let terminal_age = self.schedule.terminal_age.unwrap_or(i32::MAX);
+        for cohort in self.cohorts.iter() {
+            let age = match cohort.age(&self.clock) {
+                Some(age) => age.min(terminal_age) as usize,
+                None => continue,
+            };
+            if distribution.len() <= age {
+                distribution.resize(age + 1, (C::default(), C::default()));
+            }
+            let (males, females) = distribution[age];
+            distribution[age] =
+                (males.plus(cohort.members.males), females.plus(cohort.members.females));
+        }
+        distribution
+    }
+
+    /// Total members in consecutive age groups of `width` years, starting from completed age zero.
+    pub fn age_groups(&self, width: usize) -> Vec<C> {
+        let mut groups = Vec::new();
+        let ages = completed_ages(&self.age_sex_distribution());
+        for (age, (males, females)) in ages.into_iter().enumerate() {
+            let group = age / width;
+            if groups.len() <= group { groups.resize(group + 1, C::default()); }
+            groups[group] = groups[group].plus(males).plus(females);
+        }
+        groups
+    }
+
+    /// Panics if the total doesn't fit in `C`; see `checked_total_pop`.
+    pub fn total_pop(&self) -> C {
+        C::total(self.cohorts.iter().map(|cohort| { cohort.members.total() }))
+    }
+
+    /// The total population, or None if it doesn't fit in `C`.
+    pub fn checked_total_pop(&self) -> Option<C> {
+        let totals = self.cohorts.iter()
+            .map(|cohort| cohort.members.males.checked_add(cohort.members.females));
+        totals.collect::<Option<Vec<C>>>().and_then(C::checked_total)
+    }
+
+    pub fn total_male(&self) -> C {
+        C::total(self.cohorts.iter().map(|cohort| { cohort.members.males }))
+    }
+
+    pub fn total_female(&self) -> C {
+        C::total(self.cohorts.iter().map(|cohort| { cohort.members.females }))
+    }
+
+}
+
+/// A distribution from `age_sex_distribution` by completed age at the end of the year, the ages a
+/// scenario's `initial` starts from: each age is one less, with the first two counted together.
+pub fn completed_ages<C: Count>(distribution: &[(C, C)]) -> Vec<(C, C)> {
+    let mut ages = distribution.iter().skip(1).cloned().collect::<Vec<_>>();
+    if let Some(&(males, females)) = distribution.first() {
+        if ages.is_empty() { ages.push((C::default(), C::default())); }
+        ages[0] = (ages[0].0.plus(males), ages[0].1.plus(females));
+    }
+    ages
+}
+
+/// The births a step would have at a TFR of one.
+fn exposure<C: Count>(cohorts: &VecDeque<Cohort<C>>, clock: &Clock, tables: &Tables) -> f64 {
+    cohorts.iter().map(|cohort| cohort.births(clock, tables, 1.0)).sum()
+}
+
+/// Pass a cohort's deaths in this step to the history, at the cohort's age or the terminal age,
+/// and to the observers.
+fn report_deaths<C: Count>(
+    history: Option<&mut History<C>>,
+    observers: &mut Observers<C>,
+    cohort: &Cohort<C>,
+    alive: (C, C),
+    survived: bool,
+    clock: &Clock,
+    terminal_age: Option<i32>,
+) {
+    let age = match cohort.age(clock) {
+        Some(age) => terminal_age.map_or(age, |terminal_age| age.min(terminal_age)),
+        None => return,
+    };
+    let males = alive.0 - cohort.members.males;
+    let females = alive.1 - cohort.members.females;
+    if let Some(history) = history { history.count_deaths(age as usize, males, females); }
+    observers.cohort_deaths(clock, cohort, males, females);
+    if !survived { observers.cohort_extinct(clock, cohort); }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Population;
+
+    fn run(pop: Population, years: usize) -> Vec<usize> {
+        let mut pop = pop;
+        let mut totals = Vec::new();
+        for _ in 0..years {
+            pop = pop.advance_year();
+            totals.push(pop.total_pop());
+        }
+        totals
+    }
+
+    fn sexes_every_50_years(pop: Population, years: usize) -> Vec<(usize, usize)> {
+        let mut pop = pop;
+        let mut sexes = Vec::new();
+        for year in 1..years + 1 {
+            pop = pop.advance_year();
+            if year % 50 == 0 { sexes.push((pop.total_male(), pop.total_female())); }
+        }
+        sexes
+    }
+
+    // Recorded from the original engine, which kept a `Vec` of cohorts that each carried and
+    // evaluated their own fertility and mortality models. Reckoning rates in `f64` rather than
+    // `f32` has since moved one count by one member.
+    #[test]
+    fn matches_reference_engine() {
+        let initial = vec![ ( 10_000, 10_000 ); 30 ];
+        assert_eq!(sexes_every_50_years(Population::new(initial.clone()), 300),
+            vec![ (645609, 751099), (647048, 816728), (644434, 813412)
+                , (641828, 810114), (639229, 806840), (636640, 803575) ]);
+        assert_eq!(sexes_every_50_years(Population::new(initial).with_steps_per_year(4), 100),
+            vec![ (640381, 745455), (640677, 807832) ]);
+        let small = Population::new(vec![ ( 300, 300 ); 30 ]).stochastic(2024);
+        assert_eq!(sexes_every_50_years(small, 200),
+            vec![ (19260, 22417), (19192, 24547), (19642, 24945), (19791, 25329) ]);
+    }
+
+    #[test]
+    fn stochastic_runs_repeat_from_seed() {
+        let first = run(Population::new(vec![ ( 500, 500 ); 30 ]).stochastic(1234), 150);
+        let second = run(Population::new(vec![ ( 500, 500 ); 30 ]).stochastic(1234), 150);
+        let other = run(Population::new(vec![ ( 500, 500 ); 30 ]).stochastic(4321), 150);
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn stochastic_tracks_deterministic() {
+        let deterministic = run(Population::new(vec![ ( 50_000, 50_000 ); 30 ]), 50);
+        let stochastic = run(Population::new(vec![ ( 50_000, 50_000 ); 30 ]).stochastic(99), 50);
+        let (d, s) = (*deterministic.last().unwrap() as f64, *stochastic.last().unwrap() as f64);
+        assert!((d - s).abs() / d < 0.01, "{} vs {}", d, s);
+    }
+
+    #[test]
+    fn monthly_steps_agree_with_annual() {
+        let initial = vec![ ( 100_000, 100_000 ); 60 ];
+        let annual = run(Population::new(initial.clone()).with_fertility_rate(0.0), 30);
+        let monthly = run(Population::new(initial.clone())
+            .with_fertility_rate(0.0)
+            .with_steps_per_year(12), 30);
+        for (a, m) in annual.iter().zip(monthly.iter()) {
+            assert!((*a as f64 - *m as f64).abs() / (*a as f64) < 1e-4, "{} vs {}", a, m);
+        }
+
+        // Until the first cohorts born mid-year reach childbearing age, every mother faces the
+        // same rates for the whole year either way.
+        let mut annual = Population::new(initial.clone());
+        let mut quarterly = Population::new(initial).with_steps_per_year(4);
+        for _ in 0..15 {
+            annual = annual.advance_year();
+            quarterly = quarterly.advance_year();
+            let births = (annual.births() as f64, quarterly.births() as f64);
+            assert!((births.0 - births.1).abs() / births.0 < 1e-3, "{:?}", births);
+        }
+        assert_eq!(annual.current_year(), quarterly.current_year());
+    }
+
+    #[test]
+    fn real_counts_keep_fractions() {
+        let mut real = Population::<f64>::from_counts(vec![ ( 0.75, 0.75 ); 30 ]);
+        for _ in 0..20 { real = real.advance_year(); }
+        assert!(real.total_pop() > 0.0 && real.total_pop().fract() != 0.0);
+        assert!(real.births() > 0.0 && real.births().fract() != 0.0);
+
+        let initial = vec![ ( 1_000_000, 1_000_000 ); 30 ];
+        let scaled = initial.iter().map(|&(m, f)| (m as f64, f as f64)).collect();
+        let mut whole = Population::new(initial);
+        let mut real = Population::<f64>::from_counts(scaled);
+        for _ in 0..100 {
+            whole = whole.advance_year();
+            real = real.advance_year();
+        }
+        let (w, r) = (whole.total_pop() as f64, real.total_pop());
+        assert!((w - r).abs() / w < 1e-4, "{} vs {}", w, r);
+    }
+
+    #[test]
+    fn wide_counts_outgrow_usize() {
+        let initial = vec![ ( 1u128 << 60, 1u128 << 60 ); 30 ];
+        let real = initial.iter().map(|&(m, f)| (m as f64, f as f64)).collect();
+        let mut wide = Population::<u128>::from_counts(initial).with_fertility_rate(6.0);
+        let mut real = Population::<f64>::from_counts(real).with_fertility_rate(6.0);
+        for _ in 0..100 {
+            wide = wide.advance_year();
+            real = real.advance_year();
+        }
+        let (w, r) = (wide.total_pop() as f64, real.total_pop());
+        assert!(w > usize::MAX as f64 * 100.0);
+        assert!((w - r).abs() / r < 1e-9, "{} vs {}", w, r);
+        assert_eq!(wide.checked_total_pop(), Some(wide.total_pop()));
+    }
+
+    #[test]
+    fn real_cohorts_die_out() {
+        let mut pop = Population::<f64>::from_counts(vec![ ( 1e6, 1e6 ) ]).with_fertility_rate(0.0);
+        let mut years = 0;
+        while pop.total_pop() > 0.0 {
+            pop = pop.advance_year();
+            years += 1;
+        }
+        assert!(years > 100 && years < 150, "{}", years);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflowed usize")]
+    fn narrow_counts_never_wrap() {
+        let mut pop = Population::new(vec![ ( 1 << 55, 1 << 55 ); 30 ]).with_fertility_rate(6.0);
+        for _ in 0..100 { pop = pop.advance_year(); }
+    }
+
+    #[test]
+    fn open_age_group_bounds_cohorts() {
+        let initial = vec![ ( 100_000, 100_000 ); 60 ];
+        let mut open = Population::new(initial.clone()).at_replacement().with_terminal_age(90);
+        let mut closed = Population::new(initial).at_replacement();
+        for _ in 0..300 {
+            open = open.advance_year();
+            closed = closed.advance_year();
+        }
+        assert!(open.cohorts().len() <= 91, "{} cohorts", open.cohorts().len());
+        assert_eq!(open.age_sex_distribution().len(), 91);
+        let (o, c) = (open.total_pop() as f64, closed.total_pop() as f64);
+        assert!((o - c).abs() / c < 1e-3, "{} vs {}", o, c);
+    }
+
+    #[test]
+    fn nobody_outlives_max_age() {
+        let mut pop = Population::<f64>::from_counts(vec![ ( 1e6, 1e6 ); 60 ]).with_max_age(105);
+        for _ in 0..300 { pop = pop.advance_year(); }
+        assert!(pop.cohorts().len() <= 106, "{} cohorts", pop.cohorts().len());
+        assert_eq!(pop.age_sex_distribution().len(), 106);
+    }
 }
@@ -0,0 +1,192 @@
+/*!
+Callbacks into a running simulation.
+
+Observers are attached to a population and told about each step as it happens, with read-only
+views of the state. Every callback does nothing by default, so an observer only implements what
+it needs:
+
+```
+use pop_sim::population::Population;
+use pop_sim::population::clock::Clock;
+use pop_sim::population::observer::Observer;
+
+struct BirthCounter { births: usize }
+
+impl Observer for BirthCounter {
+    fn on_births(&mut self, _clock: &Clock, males: usize, females: usize) {
+        self.births += males + females;
+    }
+}
+
+let pop = Population::new(vec![ ( 1_000, 1_000 ); 30 ])
+    .with_observer(BirthCounter { births: 0 })
+    .advance_year();
+```
+
+A year runs `on_year_start`, then for each of its steps `on_births`, and `on_cohort_deaths` for
+every cohort that has been born (with `on_cohort_extinct` for any that die out), then
+`on_year_end`. Observers aren't part of checkpoints.
+*/
+
+use std::fmt;
+
+use super::Population;
+use super::clock::Clock;
+use super::cohort::Cohort;
+use super::count::Count;
+
+pub trait Observer<C = usize>: Send {
+    /// Before the first step of a year.
+    fn on_year_start(&mut self, _pop: &Population<C>) {}
+
+    /// A step's newborns, before they face mortality.
+    fn on_births(&mut self, _clock: &Clock, _males: C, _females: C) {}
+
+    /// A cohort's deaths in a step, with the cohort as it is afterwards.
+    fn on_cohort_deaths(&mut self, _clock: &Clock, _cohort: &Cohort<C>, _males: C, _females: C) {
+    }
+
+    /// The last members of a cohort have died. The cohort is removed after this step.
+    fn on_cohort_extinct(&mut self, _clock: &Clock, _cohort: &Cohort<C>) {}
+
+    /// After the last step of a year, once the clock has moved on to the next.
+    fn on_year_end(&mut self, _pop: &Population<C>) {}
+}
+
+/// The observers attached to a population.
+pub struct Observers<C>(Vec<Box<dyn Observer<C>>>);
+
+impl<C: Count> Observers<C> {
+    pub fn push<O: Observer<C> + 'static>(&mut self, observer: O) {
+        self.0.push(Box::new(observer));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn year_start(&mut self, pop: &Population<C>) {
+        for observer in self.0.iter_mut() { observer.on_year_start(pop); }
+    }
+
+    pub fn births(&mut self, clock: &Clock, males: C, females: C) {
+        for observer in self.0.iter_mut() { observer.on_births(clock, males, females); }
+    }
+
+    pub fn cohort_deaths(&mut self, clock: &Clock, cohort: &Cohort<C>, males: C, females: C) {
+        for observer in self.0.iter_mut() {
+            observer.on_cohort_deaths(clock, cohort, males, females);
+        }
+    }
+
+    pub fn cohort_extinct(&mut self, clock: &Clock, cohort: &Cohort<C>) {
+        for observer in self.0.iter_mut() { observer.on_cohort_extinct(clock, cohort); }
+    }
+
+    pub fn year_end(&mut self, pop: &Population<C>) {
+        for observer in self.0.iter_mut() { observer.on_year_end(pop); }
+    }
+}
+
+impl<C> Default for Observers<C> {
+    fn default() -> Observers<C> {
+        Observers(Vec::new())
+    }
+}
+
+impl<C> fmt::Debug for Observers<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+/// Prints a line whenever a cohort dies out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtinctionLogger;
+
+impl<C: Count> Observer<C> for ExtinctionLogger {
+    fn on_cohort_extinct(&mut self, _clock: &Clock, cohort: &Cohort<C>) {
+        println!("Everyone's dead in gen {:?}", cohort.birth_year);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::Observer;
+    use population::Population;
+    use population::clock::Clock;
+    use population::cohort::Cohort;
+
+    #[derive(Default, Debug)]
+    struct Tally {
+        years_started: Vec<i32>,
+        years_ended: Vec<i32>,
+        births: usize,
+        deaths: usize,
+        extinct: Vec<i32>,
+    }
+
+    struct Shared(Arc<Mutex<Tally>>);
+
+    impl Observer for Shared {
+        fn on_year_start(&mut self, pop: &Population) {
+            self.0.lock().unwrap().years_started.push(pop.current_year());
+        }
+
+        fn on_births(&mut self, _clock: &Clock, males: usize, females: usize) {
+            self.0.lock().unwrap().births += males + females;
+        }
+
+        fn on_cohort_deaths(&mut self, _: &Clock, _: &Cohort, males: usize, females: usize) {
+            self.0.lock().unwrap().deaths += males + females;
+        }
+
+        fn on_cohort_extinct(&mut self, _clock: &Clock, cohort: &Cohort) {
+            assert!(cohort.members.total() == 0);
+            self.0.lock().unwrap().extinct.push(cohort.birth_year);
+        }
+
+        fn on_year_end(&mut self, pop: &Population) {
+            self.0.lock().unwrap().years_ended.push(pop.current_year());
+        }
+    }
+
+    #[test]
+    fn sees_every_event() {
+        for &steps in [1, 4].iter() {
+            let tally = Arc::new(Mutex::new(Tally::default()));
+            let mut pop = Population::new(vec![ ( 20, 20 ); 80 ])
+                .with_steps_per_year(steps)
+                .with_observer(Shared(tally.clone()));
+            let (mut births, mut deaths) = (0, 0);
+            for _ in 0..40 {
+                pop = pop.advance_year();
+                births += pop.births();
+                deaths += pop.deaths();
+            }
+
+            let tally = tally.lock().unwrap();
+            assert_eq!(tally.years_started, (0..40).collect::<Vec<_>>());
+            assert_eq!(tally.years_ended, (1..41).collect::<Vec<_>>());
+            assert_eq!((tally.births, tally.deaths), (births, deaths));
+            assert!(!tally.extinct.is_empty());
+        }
+    }
+
+    #[test]
+    fn stochastic_runs_are_observed() {
+        let tally = Arc::new(Mutex::new(Tally::default()));
+        let mut pop = Population::new(vec![ ( 50, 50 ); 60 ])
+            .stochastic(11)
+            .with_observer(Shared(tally.clone()));
+        let mut births = 0;
+        for _ in 0..30 {
+            pop = pop.advance_year();
+            births += pop.births();
+        }
+        assert_eq!(tally.lock().unwrap().births, births);
+    }
+}
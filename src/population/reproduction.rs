@@ -0,0 +1,140 @@
+use super::cohort::fertility::Fertility;
+use super::cohort::heligman_pollard::HPMortalityModel;
+use ::optimize::bisect;
+
+/**
+Summary measures of how a fertility and mortality regime reproduces itself.
+
+These follow the same timing as `Population::advance_year`: a girl born in year `y` is exposed to
+the fertility rate for age `a` in year `y + a`, provided she survived the mortality of ages
+`0..a`. The sex ratio is the proportion of births that are male, so only `1 - sex_ratio` of
+births count towards replacing mothers.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reproduction {
+    /// Daughters per woman, ignoring mortality.
+    pub gross_reproduction_rate: f64,
+    /// Daughters per newborn girl, after mortality.
+    pub net_reproduction_rate: f64,
+    /// The growth rate `r` of the stable population, solving `Σ e^(-ra) l(a) m(a) = 1`.
+    pub intrinsic_growth_rate: f64,
+    /// The mean length of a generation, `ln(NRR) / r`.
+    pub generation_length: f64,
+    /// The total fertility rate that would give an NRR of exactly one.
+    pub replacement_tfr: f64,
+}
+
+impl Reproduction {
+    pub fn new(
+        fertility: &Fertility,
+        female_mortality: &HPMortalityModel,
+        total_fertility_rate: f32,
+        sex_ratio: f64,
+    ) -> Reproduction {
+        let daughters = 1.0 - sex_ratio;
+        let schedule = net_maternity(fertility, female_mortality);
+
+        let gross_reproduction_rate = total_fertility_rate as f64 * daughters;
+        let net_per_tfr = daughters * schedule.iter().map(|&(_, m)| m).sum::<f64>();
+        let net_reproduction_rate = net_per_tfr * total_fertility_rate as f64;
+
+        let maternity: Vec<(f64, f64)> = schedule.iter()
+            .map(|&(age, m)| (age, m * daughters * total_fertility_rate as f64))
+            .collect();
+        let intrinsic_growth_rate = lotka_r(&maternity);
+
+        let mean_age = if net_reproduction_rate > 0.0 {
+            maternity.iter().map(|&(age, m)| age * m).sum::<f64>() / net_reproduction_rate
+        } else { 0.0 };
+        let generation_length = if intrinsic_growth_rate.abs() < 1e-9 || !intrinsic_growth_rate.is_finite() {
+            mean_age
+        } else {
+            net_reproduction_rate.ln() / intrinsic_growth_rate
+        };
+
+        Reproduction {
+            gross_reproduction_rate,
+            net_reproduction_rate,
+            intrinsic_growth_rate,
+            generation_length,
+            replacement_tfr: 1.0 / net_per_tfr,
+        }
+    }
+}
+
+/// `l(a) · f(a)` for every fertile age, per unit of TFR: the expected births to a newborn girl at
+/// each age.
+fn net_maternity(fertility: &Fertility, female_mortality: &HPMortalityModel) -> Vec<(f64, f64)> {
+    let mut survivorship = 1.0;
+    let mut schedule = Vec::new();
+    for age in 0..fertility.max_age() + 1 {
+        let rate = fertility.birth_rate(age, 1.0);
+        if rate > 0.0 {
+            schedule.push((age as f64, survivorship * rate));
+        }
+        survivorship *= 1.0 - female_mortality.at_age(age as i32) as f64;
+    }
+    schedule
+}
+
+/// Solve the discrete Lotka equation for `r`. Returns negative infinity if there are no births at
+/// all, since no growth rate can make the population replace itself.
+fn lotka_r(maternity: &[(f64, f64)]) -> f64 {
+    let total: f64 = maternity.iter().map(|&(_, m)| m).sum();
+    if total.is_nan() || total <= 0.0 { return f64::NEG_INFINITY; }
+
+    let characteristic = |r: f64| {
+        maternity.iter().map(|&(age, m)| (-r * age).exp() * m).sum::<f64>() - 1.0
+    };
+
+    let (mut lower, mut upper) = (-0.1, 0.1);
+    while characteristic(lower) < 0.0 { lower *= 2.0; }
+    while characteristic(upper) > 0.0 { upper *= 2.0; }
+
+    bisect(characteristic, lower, upper, 1e-12)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Reproduction;
+    use population::Population;
+    use population::cohort::fertility::Fertility;
+    use population::cohort::heligman_pollard::HPMortalityModel;
+
+    fn baseline(tfr: f32) -> Reproduction {
+        Reproduction::new(&Fertility::new(15, 50), &HPMortalityModel::baseline_female(), tfr, 0.5)
+    }
+
+    #[test]
+    fn replacement_is_stationary() {
+        let replacement = baseline(2.0).replacement_tfr;
+        let at_replacement = baseline(replacement as f32);
+        assert!((at_replacement.net_reproduction_rate - 1.0).abs() < 1e-6);
+        assert!(at_replacement.intrinsic_growth_rate.abs() < 1e-6);
+        assert!(at_replacement.generation_length > 15.0 && at_replacement.generation_length < 50.0);
+    }
+
+    #[test]
+    fn growth_rate_follows_tfr() {
+        let low = baseline(1.5);
+        let high = baseline(3.0);
+        assert!(low.intrinsic_growth_rate < 0.0);
+        assert!(high.intrinsic_growth_rate > 0.0);
+        assert!((high.gross_reproduction_rate - 1.5).abs() < 1e-6);
+        assert!(high.net_reproduction_rate < high.gross_reproduction_rate);
+    }
+
+    #[test]
+    fn matches_simulated_growth() {
+        let mut pop = Population::new(vec![ ( 1_000_000, 1_000_000 ) ]).with_fertility_rate(3.0);
+        let expected = pop.reproduction().intrinsic_growth_rate;
+
+        for _ in 0..400 { pop = pop.advance_year(); }
+        let before = pop.total_pop() as f64;
+        pop = pop.advance_year();
+        let simulated = (pop.total_pop() as f64 / before).ln();
+
+        assert!((simulated - expected).abs() < 1e-3, "{} vs {}", simulated, expected);
+    }
+}
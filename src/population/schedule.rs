@@ -0,0 +1,175 @@
+use super::clock::Clock;
+use super::cohort::fertility::Fertility;
+use super::cohort::heligman_pollard::HPMortalityModel;
+
+/// Ages covered by the precomputed tables. Older cohorts fall back to evaluating the models.
+const TABLE_AGES: usize = 150;
+/// The survivors summed for an open age group stop here, or once they're negligible.
+const OPEN_GROUP_LIMIT: i32 = 250;
+
+/**
+The fertility and mortality regime of a population. Every cohort of a population shares the same
+schedule, so it's kept once, at the population level, rather than copied into each cohort.
+
+With a `terminal_age`, everyone that age or older is treated as one open age group (such as
+100+) with a single survival rate: the rate a stationary population of the group would have,
+`T(x+1) / T(x)` where `T(x)` sums the survivors at every age from `x`. The population merges its
+oldest cohorts into that group, which bounds the number of cohorts it keeps. The merged group
+has its oldest cohort's birth year, so the terminal age needs to be past the fertile ages, and a
+`max_age` no lower than it; `Scenario::validate` checks both.
+
+With a `max_age`, no one lives past that age: the mortality of anyone that age or older is one.
+It's the same hard limit within an open group's survival rate, but a merged group no longer knows
+its members' ages, so it isn't cut off.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Schedule {
+    pub fertility: Fertility,
+    pub male_mortality: HPMortalityModel,
+    pub female_mortality: HPMortalityModel,
+    #[serde(default)]
+    pub terminal_age: Option<i32>,
+    #[serde(default)]
+    pub max_age: Option<i32>,
+}
+
+impl Schedule {
+    pub fn new(
+        fertility: Fertility,
+        male_mortality: HPMortalityModel,
+        female_mortality: HPMortalityModel,
+    ) -> Schedule {
+        Schedule { fertility, male_mortality, female_mortality, terminal_age: None, max_age: None }
+    }
+
+    pub fn with_terminal_age(mut self, terminal_age: i32) -> Schedule {
+        self.terminal_age = Some(terminal_age);
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: i32) -> Schedule {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// The annual probability of a male of this age dying, including the maximum age.
+    pub fn male_mortality_at(&self, age: i32) -> f32 {
+        self.limited(age, self.male_mortality.at_age(age))
+    }
+
+    /// The annual probability of a female of this age dying, including the maximum age.
+    pub fn female_mortality_at(&self, age: i32) -> f32 {
+        self.limited(age, self.female_mortality.at_age(age))
+    }
+
+    fn limited(&self, age: i32, mortality: f32) -> f32 {
+        match self.max_age {
+            Some(max_age) if age >= max_age => 1.0,
+            _ => mortality,
+        }
+    }
+
+    /// The annual probability of dying in the open age group, if there is one.
+    pub fn open_mortality<F: Fn(i32) -> f32>(&self, mortality: F) -> Option<f64> {
+        let terminal_age = self.terminal_age?;
+        let (mut alive, mut total) = (1.0f64, 0.0f64);
+        for age in terminal_age..OPEN_GROUP_LIMIT {
+            total += alive;
+            alive *= 1.0 - mortality(age) as f64;
+            if alive < 1e-15 { break; }
+        }
+        Some(1.0 / total)
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Schedule {
+        Schedule {
+            fertility: Fertility::new(15, 50),
+            male_mortality: HPMortalityModel::baseline_male(),
+            female_mortality: HPMortalityModel::baseline_female(),
+            terminal_age: None,
+            max_age: None,
+        }
+    }
+}
+
+/**
+A schedule evaluated once per age, already converted to the length of a step.
+
+The Heligman-Pollard formula is relatively expensive (several `powf`s per evaluation), and it would
+otherwise be evaluated for every cohort in every step. Values are computed with exactly the same
+arithmetic as evaluating the models directly, so using the tables never changes a result.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Tables {
+    schedule: Schedule,
+    clock: Option<Clock>,
+    male_qx: Vec<f64>,
+    female_qx: Vec<f64>,
+    male_open_q: f64,
+    female_open_q: f64,
+    fx: Vec<f64>,
+}
+
+impl Tables {
+    pub fn new(schedule: &Schedule, clock: &Clock) -> Tables {
+        let ages = 0..TABLE_AGES as i32;
+        let male_open = schedule.open_mortality(|age| schedule.male_mortality_at(age));
+        let female_open = schedule.open_mortality(|age| schedule.female_mortality_at(age));
+        Tables {
+            schedule: schedule.clone(),
+            clock: Some(*clock),
+            male_qx: ages.clone()
+                .map(|age| clock.step_probability(schedule.male_mortality_at(age) as f64))
+                .collect(),
+            female_qx: ages
+                .map(|age| clock.step_probability(schedule.female_mortality_at(age) as f64))
+                .collect(),
+            male_open_q: clock.step_probability(male_open.unwrap_or(1.0)),
+            female_open_q: clock.step_probability(female_open.unwrap_or(1.0)),
+            fx: (0..schedule.fertility.max_age() + 1)
+                .map(|age| schedule.fertility.birth_rate(age, 1.0))
+                .collect(),
+        }
+    }
+
+    /// Whether these tables were built for steps of the given clock's length.
+    pub fn fits(&self, clock: &Clock) -> bool {
+        self.clock.is_some_and(|built| built.steps_per_year == clock.steps_per_year)
+    }
+
+    fn clock(&self) -> Clock {
+        self.clock.unwrap_or_else(|| Clock::new(0, 1))
+    }
+
+    /// The probability of a male of this age dying within one step.
+    pub fn male_q(&self, age: i32) -> f64 {
+        if self.is_open(age) { return self.male_open_q; }
+        match self.male_qx.get(age as usize) {
+            Some(q) => *q,
+            None => self.clock().step_probability(self.schedule.male_mortality_at(age) as f64),
+        }
+    }
+
+    /// The probability of a female of this age dying within one step.
+    pub fn female_q(&self, age: i32) -> f64 {
+        if self.is_open(age) { return self.female_open_q; }
+        match self.female_qx.get(age as usize) {
+            Some(q) => *q,
+            None => self.clock().step_probability(self.schedule.female_mortality_at(age) as f64),
+        }
+    }
+
+    /// Whether this age falls in the open age group.
+    pub fn is_open(&self, age: i32) -> bool {
+        self.schedule.terminal_age.is_some_and(|terminal_age| age >= terminal_age)
+    }
+
+    /// Births per woman of this age within one step.
+    pub fn birth_rate(&self, age: i32, total_fertility_rate: f32) -> f64 {
+        let tfr = total_fertility_rate as f64;
+        let annual = self.fx.get(age as usize).map_or(0.0, |rate| rate * tfr);
+        self.clock().step_rate(annual)
+    }
+}
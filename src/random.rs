@@ -0,0 +1,215 @@
+//! A small, seedable random number generator for stochastic runs.
+//!
+//! The generator is xoshiro256** seeded through SplitMix64. Its whole state is four integers, so
+//! it serializes along with the population and a run can be reproduced exactly from its seed.
+//! Binomial and Poisson draws are exact at every size: large draws are split recursively through
+//! beta and gamma variates until what remains is small enough for inversion.
+
+use std::f64::consts::PI;
+
+/// Below this expected count we sample by inversion; above it we split the draw.
+const INVERSION_LIMIT: f64 = 30.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Random {
+    state: [u64; 4],
+}
+
+fn split_mix(seed: &mut u64) -> u64 {
+    *seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        let mut seed = seed;
+        let state = [
+            split_mix(&mut seed),
+            split_mix(&mut seed),
+            split_mix(&mut seed),
+            split_mix(&mut seed),
+        ];
+        Random { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// Uniform on `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Standard normal, by the Box-Muller transform.
+    pub fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+
+    /// Gamma with unit scale, by Marsaglia and Tsang's method.
+    pub fn gamma(&mut self, shape: f64) -> f64 {
+        if shape < 1.0 {
+            let u = 1.0 - self.uniform();
+            return self.gamma(shape + 1.0) * u.powf(1.0 / shape);
+        }
+        let d = shape - 1.0 / 3.0;
+        let c = 1.0 / (9.0 * d).sqrt();
+        loop {
+            let x = self.normal();
+            let v = (1.0 + c * x).powi(3);
+            if v <= 0.0 { continue; }
+            let u = 1.0 - self.uniform();
+            if u < 1.0 - 0.0331 * x.powi(4) || u.ln() < 0.5 * x * x + d * (1.0 - v + v.ln()) {
+                return d * v;
+            }
+        }
+    }
+
+    pub fn beta(&mut self, a: f64, b: f64) -> f64 {
+        let x = self.gamma(a);
+        let y = self.gamma(b);
+        x / (x + y)
+    }
+
+    /// The number of successes in `trials` independent trials with success chance `p`.
+    pub fn binomial(&mut self, trials: u64, p: f64) -> u64 {
+        if trials == 0 || p <= 0.0 { return 0; }
+        if p >= 1.0 { return trials; }
+        if p > 0.5 { return trials - self.binomial(trials, 1.0 - p); }
+
+        let mut n = trials;
+        let mut p = p;
+        let mut successes = 0;
+        // Place the median order statistic of n uniforms, which splits the trials into two
+        // smaller binomials, only one of which we still need.
+        while n as f64 * p.min(1.0 - p) > INVERSION_LIMIT {
+            let i = n.div_ceil(2);
+            let x = self.beta(i as f64, (n + 1 - i) as f64);
+            if x < p {
+                successes += i;
+                n -= i;
+                p = (p - x) / (1.0 - x);
+            } else {
+                n = i - 1;
+                p /= x;
+            }
+        }
+
+        if p > 0.5 {
+            successes + n - self.binomial_inversion(n, 1.0 - p)
+        } else {
+            successes + self.binomial_inversion(n, p)
+        }
+    }
+
+    fn binomial_inversion(&mut self, n: u64, p: f64) -> u64 {
+        if n == 0 || p <= 0.0 { return 0; }
+        let q = 1.0 - p;
+        let s = p / q;
+        let a = (n + 1) as f64 * s;
+        let mut r = q.powf(n as f64);
+        let mut u = self.uniform();
+        let mut x = 0;
+        while u > r && x < n {
+            u -= r;
+            x += 1;
+            r *= a / x as f64 - s;
+        }
+        x
+    }
+
+    /// A Poisson count with the given mean.
+    pub fn poisson(&mut self, mean: f64) -> u64 {
+        let mut mean = mean;
+        let mut count = 0;
+        while mean > INVERSION_LIMIT {
+            let m = (mean * 7.0 / 8.0).floor() as u64;
+            let x = self.gamma(m as f64);
+            if x < mean {
+                count += m;
+                mean -= x;
+            } else {
+                return count + self.binomial(m - 1, mean / x);
+            }
+        }
+        if mean.is_nan() || mean <= 0.0 { return count; }
+
+        let limit = (-mean).exp();
+        let mut product = self.uniform();
+        while product > limit {
+            count += 1;
+            product *= self.uniform();
+        }
+        count
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Random;
+
+    fn mean_and_variance<F: FnMut() -> f64>(draws: usize, mut f: F) -> (f64, f64) {
+        let samples: Vec<f64> = (0..draws).map(|_| f()).collect();
+        let mean = samples.iter().sum::<f64>() / draws as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / draws as f64;
+        (mean, variance)
+    }
+
+    #[test]
+    fn reproducible_from_seed() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+        let mut c = Random::new(43);
+        let xs: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        let ys: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+        let zs: Vec<u64> = (0..10).map(|_| c.next_u64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs, zs);
+    }
+
+    #[test]
+    fn binomial_moments() {
+        let mut rng = Random::new(7);
+        for &(n, p) in [(20, 0.3), (1_000, 0.02), (1_000_000, 0.4), (5_000_000, 0.97)].iter() {
+            let (mean, variance) = mean_and_variance(4000, || rng.binomial(n, p) as f64);
+            let expected_mean = n as f64 * p;
+            let expected_variance = n as f64 * p * (1.0 - p);
+            assert!((mean - expected_mean).abs() < 4.0 * (expected_variance / 4000.0).sqrt());
+            assert!((variance / expected_variance - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn poisson_moments() {
+        let mut rng = Random::new(11);
+        for &lambda in [0.5, 12.0, 250.0, 1e7].iter() {
+            let (mean, variance) = mean_and_variance(4000, || rng.poisson(lambda) as f64);
+            assert!((mean - lambda).abs() < 4.0 * (lambda / 4000.0).sqrt());
+            assert!((variance / lambda - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        let mut rng = Random::new(0);
+        assert_eq!(rng.binomial(0, 0.5), 0);
+        assert_eq!(rng.binomial(100, 0.0), 0);
+        assert_eq!(rng.binomial(100, 1.0), 100);
+        assert_eq!(rng.poisson(0.0), 0);
+    }
+}
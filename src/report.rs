@@ -0,0 +1,505 @@
+/*!
+A single HTML page describing a run, for readers who won't open a CSV.
+
+The page has everything inline, charts included, so it can be mailed or put on a shared drive as
+it is. It shows:
+
+- warnings, such as cohorts dying out, and the whole population dying out;
+- the key `Indicators` at a few milestone years;
+- the total population, births and deaths over the run, and age pyramids at the milestones;
+- the scenario: the TFR, sex ratio and stepping, the fertility schedule, the Heligman-Pollard
+  coefficients, and events, which are changes to the TFR or sex ratio part way through;
+- male and female period life tables.
+
+A `Report` records a run through its observer, the way an `Exporter` does:
+
+```
+use pop_sim::population::Population;
+use pop_sim::report::Report;
+
+let report = Report::new("Baseline").with_milestones(vec![0, 10, 19]).observer();
+let mut pop = Population::new(vec![ ( 1_000, 1_000 ); 50 ]).with_observer(report.clone());
+for _ in 0..20 {
+    pop = pop.advance_year();
+}
+let html = report.html(&pop);
+assert!(html.contains("<svg"));
+```
+
+Milestones are completed years, as in exports: the population at the end of the year, and the
+births and deaths during it.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::iter;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chart::Chart;
+use format::whole;
+use population::Population;
+use population::clock::Clock;
+use population::cohort::Cohort;
+use population::cohort::heligman_pollard::PARAMETERS;
+use population::count::Count;
+use population::indicators::Indicators;
+use population::life_table::{LifeTable, LifeTableRow};
+use population::observer::Observer;
+
+/// Milestones chosen when none are given.
+pub const DEFAULT_MILESTONES: usize = 5;
+/// Cohort extinctions listed one by one; the rest are only counted.
+pub const LISTED_EXTINCTIONS: usize = 10;
+
+const STYLE: &str = "\
+body { font-family: Helvetica, Arial, sans-serif; color: #222; max-width: 960px; margin: 24px auto;
+       padding: 0 16px; line-height: 1.45; }
+h1 { margin-bottom: 4px; }
+h2 { border-bottom: 1px solid #ddd; padding-bottom: 4px; margin-top: 36px; }
+table { border-collapse: collapse; margin: 12px 0; font-size: 14px; }
+th, td { border: 1px solid #ddd; padding: 4px 10px; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+thead th { background: #f3f3f1; }
+figure { margin: 16px 0; }
+figure svg { max-width: 100%; height: auto; }
+.warnings { background: #fff6e5; border: 1px solid #e8c27a; padding: 8px 16px; }
+.note { color: #666; font-size: 13px; }";
+
+#[derive(Debug)]
+pub struct ReportError { problem: String }
+
+impl ReportError {
+    pub fn new<S: Into<String>>(problem: S) -> ReportError {
+        ReportError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for ReportError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub title: String,
+    pub milestones: Vec<i32>,
+}
+
+impl Report {
+    pub fn new<S: Into<String>>(title: S) -> Report {
+        Report { title: title.into(), milestones: Vec::new() }
+    }
+
+    pub fn with_milestones(mut self, mut milestones: Vec<i32>) -> Report {
+        milestones.sort();
+        milestones.dedup();
+        self.milestones = milestones;
+        self
+    }
+
+    /// `count` milestones spread evenly from `first` to `last`, including both.
+    pub fn spread(first: i32, last: i32, count: usize) -> Vec<i32> {
+        if count < 2 || last <= first { return vec![last]; }
+        let mut years: Vec<i32> = (0..count)
+            .map(|index| first + ((last - first) as f64 * index as f64 / (count - 1) as f64)
+                .round() as i32)
+            .collect();
+        years.dedup();
+        years
+    }
+
+    /// An observer that records the run for the report.
+    pub fn observer<C: Count>(self) -> ReportObserver<C> {
+        ReportObserver(Arc::new(Mutex::new(Recording {
+            report: self,
+            totals: Vec::new(),
+            flows: Vec::new(),
+            milestones: Vec::new(),
+            extinctions: Vec::new(),
+            had_deaths: false,
+            events: Vec::new(),
+            first_rates: None,
+            rates: None,
+        })))
+    }
+}
+
+struct Milestone<C> {
+    year: i32,
+    indicators: Indicators,
+    distribution: Vec<(C, C)>,
+}
+
+struct Recording<C> {
+    report: Report,
+    /// The total at the start of each year, and after the last.
+    totals: Vec<(i32, C)>,
+    flows: Vec<(i32, C, C)>,
+    milestones: Vec<Milestone<C>>,
+    /// The year each cohort died out in, and the year it was born.
+    extinctions: Vec<(i32, i32)>,
+    /// Whether the cohort deaths were last reported for had any. One that dies out without any
+    /// was born empty, and isn't worth a warning.
+    had_deaths: bool,
+    events: Vec<String>,
+    /// The TFR and sex ratio the run started with, and those the last year started with.
+    first_rates: Option<(f32, f64)>,
+    rates: Option<(f32, f64)>,
+}
+
+/// A report attached to a population. Keep a clone to write the report after the run.
+#[derive(Clone)]
+pub struct ReportObserver<C>(Arc<Mutex<Recording<C>>>);
+
+impl<C: Count> Observer<C> for ReportObserver<C> {
+    fn on_year_start(&mut self, pop: &Population<C>) {
+        let mut recording = self.0.lock().unwrap();
+        let year = pop.current_year();
+        if recording.totals.is_empty() { recording.totals.push((year, pop.total_pop())); }
+        let rates = (pop.total_fertility_rate(), pop.sex_ratio());
+        if let Some((tfr, sex_ratio)) = recording.rates {
+            if tfr != rates.0 {
+                recording.events.push(format!(
+                    "The TFR went from {:.2} to {:.2} at the start of {}.", tfr, rates.0, year));
+            }
+            if sex_ratio != rates.1 {
+                recording.events.push(format!(
+                    "The proportion of births that are male went from {:.3} to {:.3} at the start \
+                     of {}.", sex_ratio, rates.1, year));
+            }
+        }
+        recording.rates = Some(rates);
+        recording.first_rates = recording.first_rates.or(Some(rates));
+    }
+
+    fn on_cohort_deaths(&mut self, _clock: &Clock, _cohort: &Cohort<C>, males: C, females: C) {
+        self.0.lock().unwrap().had_deaths = !males.plus(females).is_empty();
+    }
+
+    fn on_cohort_extinct(&mut self, clock: &Clock, cohort: &Cohort<C>) {
+        let mut recording = self.0.lock().unwrap();
+        if recording.had_deaths { recording.extinctions.push((clock.year, cohort.birth_year)); }
+    }
+
+    fn on_year_end(&mut self, pop: &Population<C>) {
+        let mut recording = self.0.lock().unwrap();
+        let year = pop.current_year() - 1;
+        recording.totals.push((pop.current_year(), pop.total_pop()));
+        recording.flows.push((year, pop.births(), pop.deaths()));
+        if recording.report.milestones.contains(&year) {
+            recording.milestones.push(Milestone {
+                year,
+                indicators: pop.indicators(&[]),
+                distribution: pop.age_sex_distribution(),
+            });
+        }
+    }
+}
+
+impl<C: Count> ReportObserver<C> {
+    /// The page, for the run recorded so far ending with `pop`.
+    pub fn html(&self, pop: &Population<C>) -> String {
+        let recording = self.0.lock().unwrap();
+        let title = escape(&recording.report.title);
+        let mut html = String::new();
+        writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+        writeln!(html, "<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>", title, STYLE)
+            .unwrap();
+        writeln!(html, "<h1>{}</h1>", title).unwrap();
+        html.push_str(&summary(&recording));
+        html.push_str(&warnings(&recording, pop));
+        html.push_str(&milestones(&recording));
+        html.push_str(&charts(&recording));
+        html.push_str(&scenario(&recording, pop));
+        html.push_str(&life_tables(pop));
+        writeln!(html, "<p class=\"note\">Made with PopSim.</p>\n</body>\n</html>").unwrap();
+        html
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, pop: &Population<C>) -> Result<(), ReportError> {
+        let path = path.as_ref();
+        fs::write(path, self.html(pop)).map_err(|e| {
+            ReportError::new(format!("Couldn't write {}: {}", path.display(), e))
+        })
+    }
+}
+
+fn summary<C: Count>(recording: &Recording<C>) -> String {
+    let (first, last) = match (recording.totals.first(), recording.totals.last()) {
+        (Some(first), Some(last)) if recording.totals.len() > 1 => (first, last),
+        _ => return String::from("<p>Nothing has been run yet.</p>\n"),
+    };
+    let years = last.0 - first.0;
+    format!("<p>{} year{} from the start of {} to the end of {}. The population went from {} to \
+             {}.</p>\n",
+            years, if years == 1 { "" } else { "s" }, first.0, last.0 - 1,
+            whole(first.1.to_f64()), whole(last.1.to_f64()))
+}
+
+fn warnings<C: Count>(recording: &Recording<C>, pop: &Population<C>) -> String {
+    let mut items = Vec::new();
+    if recording.totals.len() > 1 && pop.total_pop().is_empty() {
+        let died_out = recording.totals.iter().find(|&&(_, total)| total.is_empty());
+        if let Some(&(year, _)) = died_out {
+            items.push(format!("Everyone had died by the start of {}.", year));
+        }
+    }
+    let extinctions = &recording.extinctions;
+    if !extinctions.is_empty() {
+        items.push(format!("{} cohort{} died out during the run.", extinctions.len(),
+                           if extinctions.len() == 1 { "" } else { "s" }));
+        for &(year, born) in extinctions.iter().take(LISTED_EXTINCTIONS) {
+            items.push(format!("The last of those born in {} died in {}, aged {}.",
+                               born, year, year - born));
+        }
+        if extinctions.len() > LISTED_EXTINCTIONS {
+            items.push(format!("And {} more.", extinctions.len() - LISTED_EXTINCTIONS));
+        }
+    }
+    if items.is_empty() { return String::new(); }
+    let items: Vec<String> = items.iter().map(|item| format!("<li>{}</li>", item)).collect();
+    format!("<div class=\"warnings\">\n<h2>Warnings</h2>\n<ul>\n{}\n</ul>\n</div>\n",
+            items.join("\n"))
+}
+
+fn milestones<C: Count>(recording: &Recording<C>) -> String {
+    let mut html = String::from("<h2>Key indicators</h2>\n");
+    if recording.milestones.is_empty() {
+        html.push_str("<p>None of the milestone years were in the run.</p>\n");
+        return html;
+    }
+    html.push_str("<p class=\"note\">For each milestone year: the population at its end, and the \
+                   births and deaths during it. Rates are per 1,000 people, dependency ratios \
+                   per 100 aged 15 to 64, and sex ratios are males per 100 females.</p>\n");
+    let mut headers = vec![String::from("Indicator")];
+    headers.extend(recording.milestones.iter().map(|milestone| milestone.year.to_string()));
+    let row = |name: &str, value: &dyn Fn(&Indicators) -> String| -> Vec<String> {
+        let mut row = vec![String::from(name)];
+        row.extend(recording.milestones.iter().map(|milestone| value(&milestone.indicators)));
+        row
+    };
+    let rows = vec![
+        row("Population", &|i| whole(i.population)),
+        row("Crude birth rate", &|i| decimal(i.crude_birth_rate, 1)),
+        row("Crude death rate", &|i| decimal(i.crude_death_rate, 1)),
+        row("Growth rate", &|i| decimal(i.crude_growth_rate, 1)),
+        row("Realized TFR", &|i| decimal(i.realized_tfr, 2)),
+        row("Mean age", &|i| decimal(i.mean_age, 1)),
+        row("Median age", &|i| decimal(i.median_age, 1)),
+        row("Youth dependency ratio", &|i| decimal(i.youth_dependency, 1)),
+        row("Old-age dependency ratio", &|i| decimal(i.old_age_dependency, 1)),
+        row("Total dependency ratio", &|i| decimal(i.total_dependency, 1)),
+        row("Sex ratio", &|i| decimal(i.sex_ratio, 1)),
+        row("Male life expectancy", &|i| decimal(Some(i.male_life_expectancy), 1)),
+        row("Female life expectancy", &|i| decimal(Some(i.female_life_expectancy), 1)),
+    ];
+    html.push_str(&table(&headers, &rows));
+    html
+}
+
+fn charts<C: Count>(recording: &Recording<C>) -> String {
+    let mut html = String::new();
+    if recording.flows.is_empty() { return html; }
+    html.push_str("<h2>Population over time</h2>\n");
+    html.push_str(&figure(&Chart::totals("Total population", &recording.totals)));
+    html.push_str(&figure(&Chart::flows("Births and deaths", &recording.flows)));
+    if !recording.milestones.is_empty() {
+        html.push_str("<h2>Age pyramids</h2>\n");
+        for milestone in recording.milestones.iter() {
+            let title = format!("Population by age, end of {}", milestone.year);
+            html.push_str(&figure(&Chart::pyramid(&title, &milestone.distribution)));
+        }
+    }
+    html
+}
+
+fn scenario<C: Count>(recording: &Recording<C>, pop: &Population<C>) -> String {
+    let schedule = pop.schedule();
+    let (tfr, sex_ratio) = recording.first_rates
+        .unwrap_or((pop.total_fertility_rate(), pop.sex_ratio()));
+    let mut html = String::from("<h2>Scenario</h2>\n");
+    let steps = pop.steps_per_year();
+    let limit = |age: Option<i32>| age.map_or(String::from("None"), |age| age.to_string());
+    let rows = vec![
+        vec![String::from("Total fertility rate"), format!("{:.2}", tfr)],
+        vec![String::from("Proportion of births that are male"), format!("{:.3}", sex_ratio)],
+        vec![String::from("Steps per year"), steps.to_string()],
+        vec![String::from("Births and deaths"),
+             String::from(if pop.is_stochastic() { "Random" } else { "Expected values" })],
+        vec![String::from("Open age group from"), limit(schedule.terminal_age)],
+        vec![String::from("Maximum age"), limit(schedule.max_age)],
+    ];
+    html.push_str(&table(&[String::from("Parameter"), String::from("Value")], &rows));
+
+    let fertility = &schedule.fertility;
+    writeln!(html, "<h3>Fertility</h3>\n<p>Births per 1,000 women a year, by age, at a TFR of \
+                    {:.2}.</p>", tfr).unwrap();
+    let rows: Vec<Vec<String>> = (fertility.min_age()..fertility.max_age() + 1)
+        .step_by(5)
+        .map(|start| {
+            let end = (start + 4).min(fertility.max_age());
+            let rates: Vec<f64> = (start..end + 1)
+                .map(|age| fertility.birth_rate(age, tfr as f64))
+                .collect();
+            let mean = rates.iter().sum::<f64>() / rates.len() as f64;
+            let ages = if start == end { start.to_string() } else { format!("{}-{}", start, end) };
+            vec![ages, format!("{:.1}", 1000.0 * mean)]
+        })
+        .collect();
+    html.push_str(&table(&[String::from("Age"), String::from("Rate")], &rows));
+
+    html.push_str("<h3>Mortality</h3>\n<p>Heligman-Pollard coefficients.</p>\n");
+    let (male, female) =
+        (schedule.male_mortality.parameters(), schedule.female_mortality.parameters());
+    let rows: Vec<Vec<String>> = PARAMETERS.iter().enumerate().map(|(index, name)| {
+        let letter = (b'A' + index as u8) as char;
+        vec![format!("{} ({})", name.replace('_', " "), letter), format!("{}", male[index]),
+             format!("{}", female[index])]
+    }).collect();
+    let headers = [String::from("Coefficient"), String::from("Males"), String::from("Females")];
+    html.push_str(&table(&headers, &rows));
+
+    html.push_str("<h3>Events</h3>\n");
+    if recording.events.is_empty() {
+        html.push_str("<p>The rates didn't change during the run.</p>\n");
+    } else {
+        let items: Vec<String> =
+            recording.events.iter().map(|event| format!("<li>{}</li>", event)).collect();
+        writeln!(html, "<ul>\n{}\n</ul>", items.join("\n")).unwrap();
+    }
+    html
+}
+
+fn life_tables<C: Count>(pop: &Population<C>) -> String {
+    let schedule = pop.schedule();
+    let (male, female) = (LifeTable::male(schedule), LifeTable::female(schedule));
+    let mut html = String::from("<h2>Life tables</h2>\n");
+    writeln!(html, "<p class=\"note\">The chance of dying before the next birthday (q), the \
+                    survivors of 100,000 births (l), and the years left to live (e). Life \
+                    expectancy at birth is {:.1} for males and {:.1} for females.</p>",
+             male.life_expectancy(), female.life_expectancy()).unwrap();
+    let headers: Vec<String> = ["Age", "Male q", "Male l", "Male e", "Female q", "Female l",
+                                "Female e"].iter().map(|&name| String::from(name)).collect();
+    let last = male.rows.len().max(female.rows.len()) as i32 - 1;
+    let ages = [0, 1].iter().cloned().chain((5..).step_by(5))
+        .take_while(|&age| age < last)
+        .chain(iter::once(last));
+    let columns = |row: Option<&LifeTableRow>| match row {
+        Some(row) => vec![format!("{:.5}", row.q), whole(row.l), format!("{:.1}", row.e)],
+        None => vec![String::new(); 3],
+    };
+    let rows: Vec<Vec<String>> = ages.map(|age| {
+        let open = age == last && male.open;
+        let mut row = vec![if open { format!("{}+", age) } else { age.to_string() }];
+        row.extend(columns(male.at(age)));
+        row.extend(columns(female.at(age)));
+        row
+    }).collect();
+    html.push_str(&table(&headers, &rows));
+    html
+}
+
+fn figure(chart: &Chart) -> String {
+    format!("<figure>\n{}</figure>\n", chart.svg())
+}
+
+fn table(headers: &[String], rows: &[Vec<String>]) -> String {
+    let mut html = String::from("<table>\n<thead><tr>");
+    for header in headers {
+        write!(html, "<th>{}</th>", escape(header)).unwrap();
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            write!(html, "<td>{}</td>", escape(cell)).unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+    html
+}
+
+/// A value to `places` decimal places, or a dash if it's undefined.
+fn decimal(value: Option<f64>, places: usize) -> String {
+    value.map_or(String::from("–"), |value| format!("{:.*}", places, value))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Report;
+    use population::Population;
+
+    #[test]
+    fn milestones_spread_over_the_run() {
+        assert_eq!(Report::spread(2020, 2120, 5), vec![2020, 2045, 2070, 2095, 2120]);
+        assert_eq!(Report::spread(0, 2, 5), vec![0, 1, 2]);
+        assert_eq!(Report::spread(7, 7, 5), vec![7]);
+        let report = Report::new("x").with_milestones(vec![5, 1, 5]);
+        assert_eq!(report.milestones, vec![1, 5]);
+    }
+
+    #[test]
+    fn reports_are_self_contained() {
+        let report = Report::new("Tom & Jerry's <run>")
+            .with_milestones(vec![0, 30, 59, 500])
+            .observer();
+        let mut pop = Population::new(vec![ ( 3, 3 ); 70 ])
+            .with_terminal_age(90)
+            .with_observer(report.clone());
+        for year in 0..60 {
+            if year == 20 { pop = pop.with_fertility_rate(3.0); }
+            pop = pop.advance_year();
+        }
+        let html = report.html(&pop);
+        assert!(html.starts_with("<!DOCTYPE html>") && html.trim_end().ends_with("</html>"));
+        assert!(html.contains("<h1>Tom &amp; Jerry's &lt;run&gt;</h1>"));
+        // Totals, births and deaths, and a pyramid for each milestone in the run.
+        assert_eq!(html.matches("<svg").count(), 5);
+        assert!(!html.contains("src=") && !html.contains("href="));
+        assert!(html.contains("<th>59</th>") && !html.contains("<th>500</th>"));
+        assert!(html.contains("The TFR went from 2.03 to 3.00 at the start of 20."));
+        assert!(html.contains("adult mortality increase (H)"));
+        assert!(html.contains("<td>90+</td>"));
+    }
+
+    #[test]
+    fn dying_out_is_a_warning() {
+        let report = Report::new("Childless").observer();
+        let mut pop = Population::new(vec![ ( 2, 2 ); 5 ])
+            .with_fertility_rate(0.0)
+            .with_observer(report.clone());
+        for _ in 0..150 {
+            pop = pop.advance_year();
+        }
+        let html = report.html(&pop);
+        assert!(html.contains("<h2>Warnings</h2>"));
+        assert!(html.contains("Everyone had died by the start of"));
+        assert!(html.contains("5 cohorts died out during the run."));
+        assert!(html.contains("None of the milestone years were in the run."));
+    }
+
+    #[test]
+    fn empty_runs_still_report() {
+        let report = Report::new("Nothing").observer();
+        let pop = Population::new(vec![]);
+        let html = report.html(&pop);
+        assert!(html.contains("Nothing has been run yet."));
+        assert!(!html.contains("<svg"));
+    }
+}
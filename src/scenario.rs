@@ -0,0 +1,366 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_yaml;
+
+use census::{Census, Interpolation};
+use population::Population;
+use population::cohort::fertility::{Fertility, DEFAULT_SKEW};
+use population::cohort::heligman_pollard::{HPMortalityModel, PARAMETERS};
+use population::schedule::Schedule;
+
+#[derive(Debug)]
+pub struct ScenarioError { problem: String }
+
+impl ScenarioError {
+    pub fn new<S: Into<String>>(problem: S) -> ScenarioError {
+        ScenarioError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for ScenarioError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+fn default_fertility_rate() -> f32 { 2.028 }
+fn default_sex_ratio() -> f64 { 0.5 }
+fn default_years() -> usize { 100 }
+fn default_steps_per_year() -> u32 { 1 }
+fn default_start_year() -> i32 { 0 }
+
+/// The fertility curve's ages, as `Schedule::default` has them.
+const DEFAULT_MIN_AGE: usize = 15;
+const DEFAULT_MAX_AGE: usize = 50;
+
+/**
+Everything needed to start a run, in a form that can be written by hand as YAML:
+
+```yaml
+initial: [[10000, 10000], [9800, 9900]]
+total_fertility_rate: 2.1
+sex_ratio: 0.51
+years: 200
+steps_per_year: 12
+seed: 42
+start_year: 2020
+terminal_age: 100
+male_mortality:
+  infant_mortality: 0.0004
+  first_year_mortality: 0.0192
+  infant_mortality_dropoff: 0.1048
+  accident_severity: 0.001
+  accident_spread: 9.0
+  accident_midpoint: 21.0
+  adult_mortality: 0.0001
+  adult_mortality_increase: 1.12
+fertility_ages: [15, 50]
+fertility_skew: -3.0
+```
+
+`initial` follows `Population::new`, youngest first. A `seed` switches the run to stochastic mode.
+A census table can stand in for `initial`; see `Scenario::from_census`. Without `male_mortality`
+or `female_mortality`, the sex has the baseline Heligman-Pollard parameters, and without
+`fertility_ages` or `fertility_skew`, the fertility curve has the defaults shown.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub initial: Vec<(usize, usize)>,
+    #[serde(default = "default_fertility_rate")]
+    pub total_fertility_rate: f32,
+    #[serde(default = "default_sex_ratio")]
+    pub sex_ratio: f64,
+    #[serde(default = "default_years")]
+    pub years: usize,
+    #[serde(default = "default_steps_per_year")]
+    pub steps_per_year: u32,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default = "default_start_year")]
+    pub start_year: i32,
+    #[serde(default)]
+    pub terminal_age: Option<i32>,
+    #[serde(default)]
+    pub max_age: Option<i32>,
+    #[serde(default)]
+    pub male_mortality: Option<HPMortalityModel>,
+    #[serde(default)]
+    pub female_mortality: Option<HPMortalityModel>,
+    /// The ages the fertility curve starts and ends at.
+    #[serde(default)]
+    pub fertility_ages: Option<(usize, usize)>,
+    #[serde(default)]
+    pub fertility_skew: Option<f64>,
+}
+
+impl Scenario {
+    pub fn new(initial: Vec<(usize, usize)>) -> Scenario {
+        Scenario {
+            initial,
+            total_fertility_rate: default_fertility_rate(),
+            sex_ratio: default_sex_ratio(),
+            years: default_years(),
+            steps_per_year: default_steps_per_year(),
+            seed: None,
+            start_year: default_start_year(),
+            terminal_age: None,
+            max_age: None,
+            male_mortality: None,
+            female_mortality: None,
+            fertility_ages: None,
+            fertility_skew: None,
+        }
+    }
+
+    /// A scenario starting from a census table, in its reference year.
+    pub fn from_census(census: &Census, interpolation: Interpolation) -> Scenario {
+        let mut scenario = Scenario::new(census.counts(interpolation));
+        scenario.start_year = census.reference_year;
+        scenario
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Scenario, ScenarioError> {
+        let scenario: Scenario = serde_yaml::from_str(yaml)
+            .map_err(|e| ScenarioError::new(format!("Couldn't read scenario: {}", e)))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, ScenarioError> {
+        let path = path.as_ref();
+        let mut yaml = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut yaml))
+            .map_err(|e| ScenarioError::new(format!("Couldn't open {}: {}", path.display(), e)))?;
+        Scenario::from_yaml(&yaml)
+    }
+
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        // Deserializing a model doesn't check it, so give it the checks building one has.
+        let models = [("male", &self.male_mortality), ("female", &self.female_mortality)];
+        for &(sex, model) in models.iter() {
+            if let Some(ref model) = *model {
+                HPMortalityModel::from_parameters(model.parameters())
+                    .map_err(|e| ScenarioError::new(format!("{}_mortality: {}", sex, e)))?;
+            }
+        }
+        let last_fertile_age = self.fertility().1;
+        if self.total_fertility_rate.is_nan() || self.total_fertility_rate < 0.0 {
+            Err(ScenarioError::new("total_fertility_rate must not be negative."))
+        } else if !(self.sex_ratio >= 0.0 && self.sex_ratio <= 1.0) {
+            Err(ScenarioError::new("sex_ratio must be a proportion between 0 and 1."))
+        } else if self.steps_per_year == 0 {
+            Err(ScenarioError::new("steps_per_year must be at least one."))
+        } else if self.terminal_age.is_some_and(|age| age < 1) {
+            Err(ScenarioError::new("terminal_age must be at least one."))
+        } else if self.terminal_age.is_some_and(|age| age as usize <= last_fertile_age) {
+            // The open group has its oldest cohort's birth year, so none of it can be fertile.
+            Err(ScenarioError::new(format!(
+                "terminal_age must be past the last fertile age, {}.", last_fertile_age)))
+        } else if self.max_age.is_some_and(|max_age| {
+            self.terminal_age.is_some_and(|terminal_age| max_age < terminal_age)
+        }) {
+            Err(ScenarioError::new("max_age must not be below terminal_age."))
+        } else if self.fertility_ages.is_some_and(|(min_age, max_age)| min_age >= max_age) {
+            Err(ScenarioError::new("fertility_ages must start before they end."))
+        } else if !self.schedule().fertility.birth_rate(self.fertility().0, 1.0).is_finite() {
+            Err(ScenarioError::new("fertility_skew leaves no births in the fertility ages."))
+        } else {
+            Ok(())
+        }
+    }
+
+    /**
+    This scenario with one parameter changed, for sweeping over them. The parameters are
+    `total_fertility_rate`, `sex_ratio`, `fertility_min_age`, `fertility_max_age` (rounded to
+    whole years) and `fertility_skew`, and the Heligman-Pollard parameters named in
+    `heligman_pollard::PARAMETERS`: by name alone for both sexes, or for one with `male.` or
+    `female.` in front, as in `female.adult_mortality`.
+    */
+    pub fn with_parameter(&self, name: &str, value: f64) -> Result<Scenario, ScenarioError> {
+        let mut scenario = self.clone();
+        let (min_age, max_age, _) = self.fertility();
+        let age = || if value >= 0.0 { Ok(value.round() as usize) } else {
+            Err(ScenarioError::new(format!("{} can't be negative.", name)))
+        };
+        match name {
+            "total_fertility_rate" => scenario.total_fertility_rate = value as f32,
+            "sex_ratio" => scenario.sex_ratio = value,
+            "fertility_min_age" => scenario.fertility_ages = Some((age()?, max_age)),
+            "fertility_max_age" => scenario.fertility_ages = Some((min_age, age()?)),
+            "fertility_skew" => scenario.fertility_skew = Some(value),
+            _ => {
+                let (males, females, parameter) = if let Some(rest) = name.strip_prefix("male.") {
+                    (true, false, rest)
+                } else if let Some(rest) = name.strip_prefix("female.") {
+                    (false, true, rest)
+                } else {
+                    (true, true, name)
+                };
+                if !PARAMETERS.contains(&parameter) {
+                    return Err(ScenarioError::new(format!(
+                        "There's no scenario parameter called {}.", name)));
+                }
+                let set = |model: HPMortalityModel| {
+                    model.with_parameter(parameter, value as f32)
+                        .map_err(|e| ScenarioError::new(e.to_string()))
+                };
+                if males {
+                    scenario.male_mortality = Some(set(self.schedule().male_mortality)?);
+                }
+                if females {
+                    scenario.female_mortality = Some(set(self.schedule().female_mortality)?);
+                }
+            },
+        }
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// This scenario with a parameter `with_parameter` takes multiplied by `factor`. A
+    /// Heligman-Pollard parameter named for both sexes is multiplied for each.
+    pub fn with_scaled_parameter(&self, name: &str, factor: f64)
+        -> Result<Scenario, ScenarioError>
+    {
+        if PARAMETERS.contains(&name) {
+            let male = format!("male.{}", name);
+            let female = format!("female.{}", name);
+            return self.with_parameter(&male, self.parameter(&male)? * factor)?
+                .with_parameter(&female, self.parameter(&female)? * factor);
+        }
+        self.with_parameter(name, self.parameter(name)? * factor)
+    }
+
+    /// The value of a parameter `with_parameter` takes. A Heligman-Pollard parameter needs a
+    /// sex, unless both have the same value.
+    pub fn parameter(&self, name: &str) -> Result<f64, ScenarioError> {
+        let (min_age, max_age, skew) = self.fertility();
+        let schedule = self.schedule();
+        let hp = |model: &HPMortalityModel, parameter: &str| {
+            PARAMETERS.iter().position(|&named| named == parameter)
+                .map(|index| model.parameters()[index] as f64)
+                .ok_or_else(|| ScenarioError::new(format!(
+                    "There's no scenario parameter called {}.", name)))
+        };
+        match name {
+            "total_fertility_rate" => Ok(self.total_fertility_rate as f64),
+            "sex_ratio" => Ok(self.sex_ratio),
+            "fertility_min_age" => Ok(min_age as f64),
+            "fertility_max_age" => Ok(max_age as f64),
+            "fertility_skew" => Ok(skew),
+            _ => if let Some(parameter) = name.strip_prefix("male.") {
+                hp(&schedule.male_mortality, parameter)
+            } else if let Some(parameter) = name.strip_prefix("female.") {
+                hp(&schedule.female_mortality, parameter)
+            } else {
+                let (male, female) = (hp(&schedule.male_mortality, name)?,
+                                      hp(&schedule.female_mortality, name)?);
+                if male == female { Ok(male) } else {
+                    Err(ScenarioError::new(format!(
+                        "{} differs between the sexes; name one, as in male.{}.", name, name)))
+                }
+            },
+        }
+    }
+
+    /// The fertility curve's first and last ages, and skew.
+    fn fertility(&self) -> (usize, usize, f64) {
+        let (min_age, max_age) = self.fertility_ages.unwrap_or((DEFAULT_MIN_AGE, DEFAULT_MAX_AGE));
+        (min_age, max_age, self.fertility_skew.unwrap_or(DEFAULT_SKEW))
+    }
+
+    /// The fertility and mortality regime the scenario starts with.
+    pub fn schedule(&self) -> Schedule {
+        let mut schedule = Schedule::default();
+        if self.fertility_ages.is_some() || self.fertility_skew.is_some() {
+            let (min_age, max_age, skew) = self.fertility();
+            schedule.fertility = Fertility::skewed(min_age, max_age, skew);
+        }
+        if let Some(ref model) = self.male_mortality {
+            schedule.male_mortality = model.clone();
+        }
+        if let Some(ref model) = self.female_mortality {
+            schedule.female_mortality = model.clone();
+        }
+        if let Some(age) = self.terminal_age { schedule = schedule.with_terminal_age(age); }
+        if let Some(age) = self.max_age { schedule = schedule.with_max_age(age); }
+        schedule
+    }
+
+    /// The population at the start of the scenario.
+    pub fn population(&self) -> Population {
+        let pop = Population::new(self.initial.clone())
+            .with_fertility_rate(self.total_fertility_rate)
+            .with_sex_ratio(self.sex_ratio)
+            .with_start_year(self.start_year)
+            .with_steps_per_year(self.steps_per_year)
+            .with_schedule(self.schedule());
+        match self.seed {
+            Some(seed) => pop.stochastic(seed),
+            None => pop,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Scenario;
+
+    #[test]
+    fn open_group_is_past_the_fertile_ages() {
+        let mut scenario = Scenario::new(vec![ (1000, 1000); 60 ]);
+        scenario.terminal_age = Some(50);
+        assert!(scenario.validate().is_err());
+        scenario.terminal_age = Some(51);
+        assert!(scenario.validate().is_ok());
+        scenario.fertility_ages = Some((15, 55));
+        assert!(scenario.validate().is_err());
+        scenario.fertility_ages = None;
+        assert!(scenario.with_parameter("fertility_max_age", 55.0).is_err());
+    }
+
+    #[test]
+    fn max_age_is_not_below_the_terminal_age() {
+        let mut scenario = Scenario::new(vec![ (1000, 1000); 60 ]);
+        scenario.terminal_age = Some(90);
+        scenario.max_age = Some(89);
+        assert!(scenario.validate().is_err());
+        scenario.max_age = Some(90);
+        assert!(scenario.validate().is_ok());
+        scenario.terminal_age = None;
+        scenario.max_age = Some(40);
+        assert!(scenario.validate().is_ok());
+    }
+
+    #[test]
+    fn mortality_parameters_are_in_range() {
+        let scenario = |sex: &str, first_year_mortality: f32| format!("\
+initial: [[1000, 1000]]
+{}_mortality:
+  infant_mortality: 0.0004
+  first_year_mortality: {}
+  infant_mortality_dropoff: 0.1048
+  accident_severity: 0.0009
+  accident_spread: 11.6
+  accident_midpoint: 22.0
+  adult_mortality: 0.0001
+  adult_mortality_increase: 1.12
+", sex, first_year_mortality);
+        for &sex in ["male", "female"].iter() {
+            assert!(Scenario::from_yaml(&scenario(sex, 0.0192)).is_ok());
+            assert!(Scenario::from_yaml(&scenario(sex, -3.0)).is_err());
+            assert!(Scenario::from_yaml(&scenario(sex, 5.0)).is_err());
+        }
+    }
+}
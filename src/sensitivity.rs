@@ -0,0 +1,538 @@
+/*!
+Global sensitivity analysis: which of a scenario's parameters its projections depend on most.
+
+Each input is a parameter `Scenario::with_parameter` takes, varied over a range, either of its
+values or, if it's `relative`, of factors on the scenario's own value. Every input moves at once,
+across the whole of every range, and the outcomes are measured at the end of the run: the
+population, the median age and the total dependency ratio.
+
+There are two methods:
+
+- Morris's elementary effects screen the inputs cheaply. Each of `trajectories` walks through a
+  grid of `levels` in the unit cube of the ranges, moving one input at a time by the same step,
+  and the change in an outcome over the step is that input's elementary effect there. The mean
+  of their absolute values, μ*, ranks the inputs; their mean μ says whether the effect is mostly
+  one way, and their standard deviation σ how much it depends on the other inputs. Effects are
+  per whole range, so they're comparable between inputs. It takes `trajectories` × (inputs + 1)
+  runs.
+- Sobol indices split the variance of an outcome between the inputs, by Saltelli's scheme with
+  `samples` base settings: the first-order index is the share an input explains alone, and the
+  total index the share it's involved in at all, by Jansen's estimator. The total index ranks
+  the inputs. It takes `samples` × (inputs + 2) runs, and sampling error falls with their square
+  root, so indices near zero can come out a little negative.
+
+The engine has no migration yet, so migration can't be an input.
+
+```
+use pop_sim::scenario::Scenario;
+use pop_sim::sensitivity::{Input, Sensitivity};
+
+let mut scenario = Scenario::new(vec![ ( 1_000, 1_000 ); 60 ]);
+scenario.years = 30;
+
+let analysis = Sensitivity::morris(10, 4, 1)
+    .with_input(Input::range("total_fertility_rate", 1.5, 2.5))
+    .with_input(Input::relative("adult_mortality", 0.8, 1.2));
+let result = analysis.run(&scenario).unwrap();
+println!("{}", result.to_table());
+# assert_eq!(result.effects.len(), 6);
+```
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+
+use population::cohort::heligman_pollard::PARAMETERS;
+use random::Random;
+use scenario::Scenario;
+use sweep::{in_parallel, permutation};
+
+/// Trajectories for Morris's method, and levels in their grid, unless asked otherwise.
+pub const DEFAULT_TRAJECTORIES: usize = 10;
+pub const DEFAULT_LEVELS: usize = 4;
+/// Base settings for Sobol indices, unless asked otherwise.
+pub const DEFAULT_SAMPLES: usize = 256;
+
+#[derive(Debug)]
+pub struct SensitivityError { problem: String }
+
+impl SensitivityError {
+    pub fn new<S: Into<String>>(problem: S) -> SensitivityError {
+        SensitivityError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for SensitivityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for SensitivityError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+/// A parameter to vary, and over what.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Input {
+    pub parameter: String,
+    pub low: f64,
+    pub high: f64,
+    /// Whether `low` and `high` are factors on the scenario's value, rather than values.
+    #[serde(default)]
+    pub relative: bool,
+}
+
+impl Input {
+    pub fn range(parameter: &str, low: f64, high: f64) -> Input {
+        Input { parameter: String::from(parameter), low, high, relative: false }
+    }
+
+    pub fn relative(parameter: &str, low: f64, high: f64) -> Input {
+        Input { parameter: String::from(parameter), low, high, relative: true }
+    }
+
+    /// The scenario with this input at `position`, from zero at `low` to one at `high`.
+    fn apply(&self, scenario: &Scenario, position: f64) -> Result<Scenario, SensitivityError> {
+        let value = self.low + (self.high - self.low) * position;
+        let scenario = if self.relative {
+            scenario.with_scaled_parameter(&self.parameter, value)
+        } else {
+            scenario.with_parameter(&self.parameter, value)
+        };
+        scenario.map_err(|e| {
+            SensitivityError::new(format!("{} at {}: {}", self.parameter, value, e))
+        })
+    }
+}
+
+/// What's measured at the end of each run.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Population,
+    MedianAge,
+    /// The total dependency ratio.
+    DependencyRatio,
+}
+
+pub const OUTCOMES: [Outcome; 3] =
+    [Outcome::Population, Outcome::MedianAge, Outcome::DependencyRatio];
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Outcome::Population => "population",
+            Outcome::MedianAge => "median_age",
+            Outcome::DependencyRatio => "dependency_ratio",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    Morris { trajectories: usize, levels: usize },
+    Sobol { samples: usize },
+}
+
+/// How much an outcome depends on an input, by the method's measures; see the module.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Measures {
+    Morris { mu_star: f64, mu: f64, sigma: f64 },
+    Sobol { first_order: f64, total_order: f64 },
+}
+
+impl Measures {
+    /// The measure the inputs are ranked by: μ*, or the total index.
+    pub fn importance(&self) -> f64 {
+        match *self {
+            Measures::Morris { mu_star, .. } => mu_star,
+            Measures::Sobol { total_order, .. } => total_order,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Effect {
+    pub outcome: Outcome,
+    pub input: String,
+    /// The input's place among the inputs for this outcome, from one for the most important.
+    pub rank: usize,
+    pub measures: Measures,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensitivityResult {
+    pub method: Method,
+    pub runs: usize,
+    /// Ranked within each outcome, in the order of `OUTCOMES`.
+    pub effects: Vec<Effect>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sensitivity {
+    pub method: Method,
+    pub inputs: Vec<Input>,
+    pub seed: u64,
+    pub threads: usize,
+}
+
+impl Sensitivity {
+    pub fn morris(trajectories: usize, levels: usize, seed: u64) -> Sensitivity {
+        Sensitivity::new(Method::Morris { trajectories, levels }, seed)
+    }
+
+    pub fn sobol(samples: usize, seed: u64) -> Sensitivity {
+        Sensitivity::new(Method::Sobol { samples }, seed)
+    }
+
+    fn new(method: Method, seed: u64) -> Sensitivity {
+        Sensitivity { method, inputs: Vec::new(), seed, threads: 4 }
+    }
+
+    pub fn with_input(mut self, input: Input) -> Sensitivity {
+        self.inputs.push(input);
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Sensitivity {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// The inputs to vary if none are given: the eight Heligman-Pollard parameters, the TFR, and
+    /// the fertility curve's skew and ages, each 10% either side of the scenario's value. The
+    /// increase in adult mortality is an annual factor near one, compounded over a lifetime, so
+    /// it's varied 1% either side.
+    pub fn default_inputs() -> Vec<Input> {
+        PARAMETERS.iter().cloned()
+            .chain(vec!["total_fertility_rate", "fertility_skew", "fertility_min_age",
+                        "fertility_max_age"])
+            .map(|parameter| match parameter {
+                "adult_mortality_increase" => Input::relative(parameter, 0.99, 1.01),
+                _ => Input::relative(parameter, 0.9, 1.1),
+            })
+            .collect()
+    }
+
+    /// Measure how the outcomes of `scenario` depend on the inputs. Every setting is checked
+    /// before any runs.
+    pub fn run(&self, scenario: &Scenario) -> Result<SensitivityResult, SensitivityError> {
+        let k = self.inputs.len();
+        if k == 0 {
+            return Err(SensitivityError::new("A sensitivity analysis needs at least one input."));
+        }
+        let mut random = Random::new(self.seed);
+        let points = match self.method {
+            Method::Morris { trajectories, levels } => {
+                // With an odd number of levels, the step falls between them.
+                if trajectories == 0 || levels < 2 || levels % 2 == 1 {
+                    return Err(SensitivityError::new(
+                        "Morris's method needs a trajectory and an even number of levels."));
+                }
+                morris_points(k, trajectories, levels, &mut random)
+            },
+            Method::Sobol { samples } => {
+                if samples < 2 {
+                    return Err(SensitivityError::new("Sobol indices need at least two samples."));
+                }
+                sobol_points(k, samples, &mut random)
+            },
+        };
+        let scenarios = points.iter().map(|point| {
+            self.inputs.iter().zip(point.iter())
+                .try_fold(scenario.clone(), |scenario, (input, &position)| {
+                    input.apply(&scenario, position)
+                })
+        }).collect::<Result<Vec<Scenario>, SensitivityError>>()?;
+        let outcomes = in_parallel(scenarios, self.threads, outcomes);
+
+        let mut effects = Vec::new();
+        for (index, &outcome) in OUTCOMES.iter().enumerate() {
+            let values: Vec<f64> = outcomes.iter().map(|values| values[index]).collect();
+            let measures = match self.method {
+                Method::Morris { levels, .. } => morris(k, &points, &values, levels),
+                Method::Sobol { samples } => sobol(k, samples, &values),
+            };
+            let mut ranked: Vec<(String, Measures)> = self.inputs.iter()
+                .map(|input| input.parameter.clone())
+                .zip(measures)
+                .collect();
+            // Undefined measures, from outcomes that weren't, go last.
+            ranked.sort_by(|a, b| {
+                let (a, b) = (a.1.importance(), b.1.importance());
+                b.partial_cmp(&a).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+            });
+            effects.extend(ranked.into_iter().enumerate().map(|(rank, (input, measures))| {
+                Effect { outcome, input, rank: rank + 1, measures }
+            }));
+        }
+        Ok(SensitivityResult { method: self.method, runs: points.len(), effects })
+    }
+}
+
+/// The outcomes at the end of a scenario, in the order of `OUTCOMES`. Any that isn't defined,
+/// such as the median age of an empty population, is NaN.
+fn outcomes(scenario: &Scenario) -> Vec<f64> {
+    let mut pop = scenario.population();
+    for _ in 0..scenario.years {
+        pop = pop.advance_year();
+    }
+    let indicators = pop.indicators(&[]);
+    vec![
+        indicators.population,
+        indicators.median_age.unwrap_or(f64::NAN),
+        indicators.total_dependency.unwrap_or(f64::NAN),
+    ]
+}
+
+/// Trajectories through a grid of `levels` in the unit cube: a start, then each input in a
+/// random order stepped by `Δ = levels / (2 (levels - 1))`, up or down as the grid allows. With
+/// an even number of levels that's half of them, so every level has just one way to go.
+fn morris_points(k: usize, trajectories: usize, levels: usize, random: &mut Random)
+    -> Vec<Vec<f64>>
+{
+    let delta = morris_delta(levels);
+    let mut points = Vec::with_capacity(trajectories * (k + 1));
+    for _ in 0..trajectories {
+        let mut point: Vec<f64> = (0..k)
+            .map(|_| (random.uniform() * levels as f64).floor().min((levels - 1) as f64)
+                 / (levels - 1) as f64)
+            .collect();
+        points.push(point.clone());
+        for input in permutation(k, random) {
+            let up = point[input] + delta <= 1.0 + 1e-12;
+            let down = point[input] - delta >= -1e-12;
+            point[input] += if up && (!down || random.uniform() < 0.5) { delta } else { -delta };
+            points.push(point.clone());
+        }
+    }
+    points
+}
+
+fn morris_delta(levels: usize) -> f64 {
+    levels as f64 / (2.0 * (levels - 1) as f64)
+}
+
+/// μ*, μ and σ for each input, from the outcome at every point of the trajectories.
+fn morris(k: usize, points: &[Vec<f64>], values: &[f64], levels: usize) -> Vec<Measures> {
+    let delta = morris_delta(levels);
+    let mut effects = vec![Vec::new(); k];
+    for (trajectory, outcomes) in points.chunks(k + 1).zip(values.chunks(k + 1)) {
+        for step in 0..k {
+            let (before, after) = (&trajectory[step], &trajectory[step + 1]);
+            let input = (0..k).find(|&input| before[input] != after[input]).unwrap_or(0);
+            let change = (after[input] - before[input]) / delta;
+            effects[input].push((outcomes[step + 1] - outcomes[step]) / (change * delta));
+        }
+    }
+    effects.iter().map(|effects| {
+        let n = effects.len() as f64;
+        let mu = effects.iter().sum::<f64>() / n;
+        let mu_star = effects.iter().map(|effect| effect.abs()).sum::<f64>() / n;
+        let sigma = if effects.len() > 1 {
+            (effects.iter().map(|effect| (effect - mu).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else { 0.0 };
+        Measures::Morris { mu_star, mu, sigma }
+    }).collect()
+}
+
+/// Saltelli's settings: `samples` rows of A, then of B, then of A with each input in turn taken
+/// from B.
+fn sobol_points(k: usize, samples: usize, random: &mut Random) -> Vec<Vec<f64>> {
+    let mut draw = || (0..samples)
+        .map(|_| (0..k).map(|_| random.uniform()).collect::<Vec<f64>>())
+        .collect::<Vec<_>>();
+    let (a, b) = (draw(), draw());
+    let mut points = a.clone();
+    points.extend(b.iter().cloned());
+    for input in 0..k {
+        points.extend(a.iter().zip(b.iter()).map(|(a, b)| {
+            let mut point = a.clone();
+            point[input] = b[input];
+            point
+        }));
+    }
+    points
+}
+
+/// First-order (Saltelli 2010) and total (Jansen) indices for each input. The outcomes are
+/// taken from their mean first, which leaves the indices as they were but keeps the first-order
+/// estimate from drowning in the size of an outcome like the population.
+fn sobol(k: usize, samples: usize, values: &[f64]) -> Vec<Measures> {
+    let n = samples as f64;
+    let (a, rest) = values.split_at(samples);
+    let (b, mixed) = rest.split_at(samples);
+    let mean = values[..2 * samples].iter().sum::<f64>() / (2.0 * n);
+    let variance = values[..2 * samples].iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>() / (2.0 * n - 1.0);
+    (0..k).map(|input| {
+        let ab = &mixed[input * samples..(input + 1) * samples];
+        let first = (0..samples).map(|j| (b[j] - mean) * (ab[j] - a[j])).sum::<f64>() / n;
+        let total = (0..samples).map(|j| (a[j] - ab[j]).powi(2)).sum::<f64>() / (2.0 * n);
+        if variance > 0.0 {
+            Measures::Sobol { first_order: first / variance, total_order: total / variance }
+        } else {
+            Measures::Sobol { first_order: 0.0, total_order: 0.0 }
+        }
+    }).collect()
+}
+
+impl SensitivityResult {
+    /// The ranked table as CSV, with the method's measures.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(match self.method {
+            Method::Morris { .. } => "outcome,rank,input,mu_star,mu,sigma\n",
+            Method::Sobol { .. } => "outcome,rank,input,first_order,total_order\n",
+        });
+        for effect in self.effects.iter() {
+            write!(csv, "{},{},{}", effect.outcome.name(), effect.rank, effect.input).unwrap();
+            match effect.measures {
+                Measures::Morris { mu_star, mu, sigma } =>
+                    writeln!(csv, ",{},{},{}", mu_star, mu, sigma).unwrap(),
+                Measures::Sobol { first_order, total_order } =>
+                    writeln!(csv, ",{},{}", first_order, total_order).unwrap(),
+            }
+        }
+        csv
+    }
+
+    /// The ranked table with its columns lined up, an outcome at a time, for reading.
+    pub fn to_table(&self) -> String {
+        let width = self.effects.iter().map(|effect| effect.input.len()).max().unwrap_or(5);
+        let mut table = String::new();
+        for &outcome in OUTCOMES.iter() {
+            if !table.is_empty() { table.push('\n'); }
+            writeln!(table, "{}", outcome.name()).unwrap();
+            let heading = match self.method {
+                Method::Morris { .. } => ["μ*", "μ", "σ"],
+                Method::Sobol { .. } => ["first", "total", ""],
+            };
+            writeln!(table, "{:>4}  {:<width$}  {:>12}  {:>12}  {:>12}", "rank", "input",
+                     heading[0], heading[1], heading[2], width = width).unwrap();
+            for effect in self.effects.iter().filter(|effect| effect.outcome == outcome) {
+                let (first, second, third) = match effect.measures {
+                    Measures::Morris { mu_star, mu, sigma } =>
+                        (number(mu_star), number(mu), number(sigma)),
+                    Measures::Sobol { first_order, total_order } =>
+                        (number(first_order), number(total_order), String::new()),
+                };
+                writeln!(table, "{:>4}  {:<width$}  {:>12}  {:>12}  {:>12}", effect.rank,
+                         effect.input, first, second, third, width = width).unwrap();
+            }
+        }
+        table
+    }
+}
+
+/// Four significant figures, whatever the size.
+fn number(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() { return value.to_string(); }
+    let digits = (3 - value.abs().log10().floor() as i32).max(0) as usize;
+    format!("{:.*}", digits, value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{morris_points, Input, Measures, Outcome, Sensitivity};
+    use random::Random;
+    use scenario::Scenario;
+
+    fn scenario() -> Scenario {
+        let mut scenario = Scenario::new(vec![ ( 1_000, 1_000 ); 60 ]);
+        scenario.years = 25;
+        scenario
+    }
+
+    fn analysis(analysis: Sensitivity) -> Sensitivity {
+        analysis
+            .with_input(Input::range("total_fertility_rate", 1.0, 3.0))
+            .with_input(Input::relative("female.accident_severity", 0.9, 1.1))
+            .with_input(Input::range("sex_ratio", 0.49, 0.51))
+    }
+
+    #[test]
+    fn morris_ranks_the_inputs() {
+        let result = analysis(Sensitivity::morris(6, 4, 2)).run(&scenario()).unwrap();
+        assert_eq!(result.runs, 6 * 4);
+        assert_eq!(result.effects.len(), 9);
+        let first = &result.effects[0];
+        assert_eq!((first.outcome, first.rank), (Outcome::Population, 1));
+        assert_eq!(first.input, "total_fertility_rate");
+        match first.measures {
+            // More births, every time.
+            Measures::Morris { mu_star, mu, .. } => assert!(mu_star > 0.0 && mu == mu_star),
+            _ => panic!("{:?}", first.measures),
+        }
+        // A higher TFR makes for a younger population too.
+        let median = result.effects.iter()
+            .find(|effect| effect.outcome == Outcome::MedianAge)
+            .unwrap();
+        assert_eq!((median.rank, median.input.as_str()), (1, "total_fertility_rate"));
+
+        let csv = result.to_csv();
+        assert!(csv.starts_with("outcome,rank,input,mu_star,mu,sigma\n"));
+        assert_eq!(csv.lines().count(), 10);
+        assert!(result.to_table().contains("dependency_ratio"));
+        let again = analysis(Sensitivity::morris(6, 4, 2)).with_threads(1).run(&scenario());
+        assert_eq!(again.unwrap(), result);
+    }
+
+    #[test]
+    fn morris_stays_on_the_grid() {
+        let mut random = Random::new(4);
+        for &levels in [2, 4, 6, 8].iter() {
+            let points = morris_points(5, 20, levels, &mut random);
+            for position in points.iter().flat_map(|point| point.iter()) {
+                assert!(*position >= 0.0 && *position <= 1.0, "{}", position);
+                let level = position * (levels - 1) as f64;
+                assert!((level - level.round()).abs() < 1e-9, "{}", position);
+            }
+        }
+        for &levels in [1, 3, 5].iter() {
+            let odd = analysis(Sensitivity::morris(2, levels, 1)).run(&scenario());
+            assert!(odd.is_err(), "{}", levels);
+        }
+    }
+
+    #[test]
+    fn sobol_splits_the_variance() {
+        let result = analysis(Sensitivity::sobol(64, 5)).run(&scenario()).unwrap();
+        assert_eq!(result.runs, 64 * 5);
+        let population: Vec<_> = result.effects.iter()
+            .filter(|effect| effect.outcome == Outcome::Population)
+            .collect();
+        assert_eq!(population[0].input, "total_fertility_rate");
+        match population[0].measures {
+            Measures::Sobol { first_order, total_order } => {
+                assert!(first_order > 0.8 && first_order < 1.2, "{}", first_order);
+                assert!(total_order > 0.8 && total_order < 1.2, "{}", total_order);
+            },
+            _ => panic!("{:?}", population[0].measures),
+        }
+        assert!(population[2].measures.importance() < 0.05);
+
+        let empty = Sensitivity::sobol(64, 5).run(&scenario());
+        assert!(empty.is_err());
+        let bad = Sensitivity::sobol(4, 5).with_input(Input::range("sex_ratio", 0.5, 2.0));
+        assert!(bad.run(&scenario()).is_err());
+    }
+
+    #[test]
+    fn default_inputs_apply_to_the_scenario() {
+        let inputs = Sensitivity::default_inputs();
+        assert_eq!(inputs.len(), 12);
+        for input in inputs.iter() {
+            for &position in [0.0, 1.0].iter() {
+                assert!(input.apply(&scenario(), position).is_ok(), "{:?}", input);
+            }
+        }
+    }
+}
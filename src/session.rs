@@ -0,0 +1,246 @@
+//! The state behind the viewer window and the terminal dashboard, kept apart from both so it can
+//! run without either.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use serde_json;
+
+use population::{completed_ages, Population};
+use population::count::Count;
+
+/// Points kept for the total population chart.
+pub const MAX_TOTALS: usize = 2000;
+pub const MAX_YEARS_PER_SECOND: f64 = 100.0;
+
+#[derive(Debug)]
+pub struct SessionError { problem: String }
+
+impl SessionError {
+    pub fn new<S: Into<String>>(problem: S) -> SessionError {
+        SessionError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for SessionError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+/// Messages from the page, as JSON such as `{"command": "fertility_rate", "value": 2.1}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    Refresh,
+    Play,
+    Pause,
+    Step,
+    Speed { years_per_second: f64 },
+    FertilityRate { value: f32 },
+    SexRatio { value: f64 },
+}
+
+/// Everything the page draws.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub year: i32,
+    pub playing: bool,
+    pub years_per_second: f64,
+    pub total_fertility_rate: f32,
+    pub sex_ratio: f64,
+    /// Males and females at each completed age.
+    pub males: Vec<f64>,
+    pub females: Vec<f64>,
+    /// The total population at the start of recent years.
+    pub totals: Vec<(i32, f64)>,
+}
+
+#[derive(Debug)]
+pub struct Session<C: Count = usize> {
+    /// Only ever empty while a year is being run.
+    pop: Option<Population<C>>,
+    playing: bool,
+    years_per_second: f64,
+    totals: VecDeque<(i32, f64)>,
+}
+
+impl<C: Count> Session<C> {
+    pub fn new(pop: Population<C>) -> Session<C> {
+        let mut totals = VecDeque::new();
+        totals.push_back((pop.current_year(), pop.total_pop().to_f64()));
+        Session { pop: Some(pop), playing: false, years_per_second: 5.0, totals }
+    }
+
+    pub fn population(&self) -> &Population<C> {
+        self.pop.as_ref().unwrap()
+    }
+
+    pub fn into_population(self) -> Population<C> {
+        self.pop.unwrap()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn years_per_second(&self) -> f64 {
+        self.years_per_second
+    }
+
+    /// The total population at the start of recent years.
+    pub fn totals(&self) -> &VecDeque<(i32, f64)> {
+        &self.totals
+    }
+
+    /// The time between years while playing.
+    pub fn interval(&self) -> Duration {
+        let millis = (1000.0 / self.years_per_second).round() as u64;
+        Duration::from_millis(millis)
+    }
+
+    /// Act on a message from the page.
+    pub fn handle(&mut self, message: &str) -> Result<(), SessionError> {
+        let command = serde_json::from_str(message).map_err(|e| {
+            SessionError::new(format!("Couldn't read the message {}: {}", message, e))
+        })?;
+        self.apply(command)
+    }
+
+    pub fn apply(&mut self, command: Command) -> Result<(), SessionError> {
+        match command {
+            Command::Refresh => {},
+            Command::Play => self.playing = true,
+            Command::Pause => self.playing = false,
+            Command::Step => { self.playing = false; self.advance(); },
+            Command::Speed { years_per_second } => {
+                if !(years_per_second > 0.0 && years_per_second <= MAX_YEARS_PER_SECOND) {
+                    return Err(SessionError::new(format!(
+                        "The speed must be above 0 and at most {} years a second.",
+                        MAX_YEARS_PER_SECOND)));
+                }
+                self.years_per_second = years_per_second;
+            },
+            Command::FertilityRate { value } => {
+                if value.is_nan() || value < 0.0 {
+                    return Err(SessionError::new("The fertility rate can't be negative."));
+                }
+                self.update(|pop| pop.with_fertility_rate(value));
+            },
+            Command::SexRatio { value } => {
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(SessionError::new("The sex ratio must be between 0 and 1."));
+                }
+                self.update(|pop| pop.with_sex_ratio(value));
+            },
+        }
+        Ok(())
+    }
+
+    /// Run a year if playing, and say whether one ran.
+    pub fn tick(&mut self) -> bool {
+        if self.playing { self.advance(); }
+        self.playing
+    }
+
+    fn update<F: FnOnce(Population<C>) -> Population<C>>(&mut self, change: F) {
+        let pop = self.pop.take().unwrap();
+        self.pop = Some(change(pop));
+    }
+
+    fn advance(&mut self) {
+        self.update(Population::advance_year);
+        let pop = self.population();
+        let (year, total) = (pop.current_year(), pop.total_pop());
+        if self.totals.len() == MAX_TOTALS { self.totals.pop_front(); }
+        self.totals.push_back((year, total.to_f64()));
+        // Nothing more will happen once everyone's dead.
+        if total.is_empty() { self.playing = false; }
+    }
+
+    pub fn frame(&self) -> Frame {
+        let pop = self.population();
+        let distribution = completed_ages(&pop.age_sex_distribution());
+        Frame {
+            year: pop.current_year(),
+            playing: self.playing,
+            years_per_second: self.years_per_second,
+            total_fertility_rate: pop.total_fertility_rate(),
+            sex_ratio: pop.sex_ratio(),
+            males: distribution.iter().map(|&(males, _)| males.to_f64()).collect(),
+            females: distribution.iter().map(|&(_, females)| females.to_f64()).collect(),
+            totals: self.totals.iter().cloned().collect(),
+        }
+    }
+
+    /// A script for the page that draws the current frame.
+    pub fn render(&self) -> String {
+        format!("render({})", serde_json::to_string(&self.frame()).unwrap())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Session, MAX_TOTALS};
+    use population::Population;
+
+    #[test]
+    fn commands_drive_the_population() {
+        let mut session = Session::new(Population::new(vec![ ( 500, 500 ); 40 ]));
+        assert!(!session.tick());
+        session.handle(r#"{"command": "step"}"#).unwrap();
+        assert_eq!(session.population().current_year(), 1);
+
+        session.handle(r#"{"command": "fertility_rate", "value": 3.5}"#).unwrap();
+        session.handle(r#"{"command": "sex_ratio", "value": 0.6}"#).unwrap();
+        session.handle(r#"{"command": "speed", "years_per_second": 20}"#).unwrap();
+        session.handle(r#"{"command": "play"}"#).unwrap();
+        assert!(session.tick() && session.tick());
+        session.handle(r#"{"command": "pause"}"#).unwrap();
+        assert!(!session.tick());
+
+        let frame = session.frame();
+        assert_eq!(frame.year, 3);
+        assert_eq!((frame.total_fertility_rate, frame.sex_ratio), (3.5, 0.6));
+        assert_eq!(session.interval().as_millis(), 50);
+        assert_eq!(frame.totals.len(), 4);
+        let pop = session.into_population();
+        assert_eq!(frame.totals[3], (3, pop.total_pop() as f64));
+        assert_eq!(frame.males.iter().sum::<f64>(), pop.total_male() as f64);
+    }
+
+    #[test]
+    fn bad_messages_change_nothing() {
+        let mut session = Session::new(Population::new(vec![ ( 500, 500 ); 40 ]));
+        for message in [
+            "play",
+            r#"{"command": "rewind"}"#,
+            r#"{"command": "sex_ratio", "value": 1.5}"#,
+            r#"{"command": "fertility_rate", "value": -1}"#,
+            r#"{"command": "speed", "years_per_second": 0}"#,
+        ].iter() {
+            assert!(session.handle(message).is_err(), "{}", message);
+        }
+        assert!(!session.is_playing());
+        assert_eq!(session.population().sex_ratio(), 0.5);
+    }
+
+    #[test]
+    fn playing_stops_when_everyone_is_dead() {
+        let mut session = Session::new(Population::new(vec![ ( 5, 5 ) ]).with_fertility_rate(0.0));
+        session.handle(r#"{"command": "play"}"#).unwrap();
+        let mut years = 0;
+        while session.tick() { years += 1; }
+        assert!(years > 0 && years < 200);
+        assert!(session.frame().totals.len() <= MAX_TOTALS);
+    }
+}
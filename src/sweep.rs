@@ -0,0 +1,461 @@
+/*!
+Parameter sweeps: one base scenario run at many settings of its parameters, in parallel.
+
+A sweep names the parameters to vary, which can be any that `Scenario::with_parameter` takes, and
+how to choose their settings:
+
+```yaml
+design: grid
+parameters:
+  - parameter: total_fertility_rate
+    values: [1.6, 1.8, 2.1]
+  - parameter: adult_mortality_increase
+    range: [1.08, 1.12]
+    steps: 5
+```
+
+A `grid` runs every combination of the parameters' values, which are either listed or spread
+evenly over a `range` in `steps`. A `latin_hypercube` draws `samples` settings from the ranges,
+with `seed`, so that each parameter's range is cut into that many equal strata and every stratum
+is sampled exactly once. A `list` runs the listed values of every parameter together, the first
+of each, then the second, and so on.
+
+Runs are spread over a fixed number of threads, like an `Ensemble`'s replicates, and the results
+are kept in the order of the settings. A stochastic scenario runs every setting with the same
+seed, so that differences between runs come from the parameters rather than the draws.
+
+```
+use pop_sim::scenario::Scenario;
+use pop_sim::sweep::{Axis, Design, Sweep};
+
+let mut scenario = Scenario::new(vec![ ( 1_000, 1_000 ); 40 ]);
+scenario.years = 20;
+
+let sweep = Sweep::new(Design::Grid)
+    .with_axis(Axis::values("total_fertility_rate", vec![1.5, 2.0, 2.5]))
+    .with_axis(Axis::range("adult_mortality_increase", 1.08, 1.12, 5));
+let result = sweep.run(&scenario).unwrap();
+println!("{}", result.to_csv());
+# assert_eq!(result.runs.len(), 15);
+```
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use serde_yaml;
+
+use population::indicators::Indicators;
+use random::Random;
+use scenario::Scenario;
+
+/// Steps across a range, if a grid doesn't say.
+pub const DEFAULT_STEPS: usize = 5;
+
+#[derive(Debug)]
+pub struct SweepError { problem: String }
+
+impl SweepError {
+    pub fn new<S: Into<String>>(problem: S) -> SweepError {
+        SweepError { problem: problem.into() }
+    }
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)
+    }
+}
+
+impl Error for SweepError {
+    fn description(&self) -> &str {
+        &self.problem
+    }
+}
+
+/// How a sweep chooses the settings to run; see the module.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Design {
+    Grid,
+    LatinHypercube,
+    List,
+}
+
+/// A parameter to vary, with its values or its range.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Axis {
+    pub parameter: String,
+    #[serde(default)]
+    pub values: Vec<f64>,
+    #[serde(default)]
+    pub range: Option<(f64, f64)>,
+    #[serde(default)]
+    pub steps: Option<usize>,
+}
+
+impl Axis {
+    pub fn values(parameter: &str, values: Vec<f64>) -> Axis {
+        Axis { parameter: String::from(parameter), values, range: None, steps: None }
+    }
+
+    pub fn range(parameter: &str, low: f64, high: f64, steps: usize) -> Axis {
+        Axis {
+            parameter: String::from(parameter),
+            values: Vec::new(),
+            range: Some((low, high)),
+            steps: Some(steps),
+        }
+    }
+
+    /// The listed values, or the range's steps.
+    fn grid(&self) -> Result<Vec<f64>, SweepError> {
+        if !self.values.is_empty() { return Ok(self.values.clone()); }
+        let (low, high) = self.range.ok_or_else(|| SweepError::new(format!(
+            "{} needs values or a range.", self.parameter)))?;
+        match self.steps.unwrap_or(DEFAULT_STEPS) {
+            0 => Err(SweepError::new(format!("{} needs at least one step.", self.parameter))),
+            1 => Ok(vec![low]),
+            steps => Ok((0..steps)
+                .map(|step| low + (high - low) * step as f64 / (steps - 1) as f64)
+                .collect()),
+        }
+    }
+}
+
+fn default_design() -> Design { Design::Grid }
+fn default_threads() -> usize { 4 }
+fn default_every() -> usize { 1 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sweep {
+    #[serde(default = "default_design")]
+    pub design: Design,
+    pub parameters: Vec<Axis>,
+    /// Settings drawn by a Latin hypercube.
+    #[serde(default)]
+    pub samples: usize,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    /// Years between rows of the results, which always end with the last year.
+    #[serde(default = "default_every")]
+    pub every: usize,
+}
+
+/// The years one setting ran for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SweepRun {
+    /// The setting, in the order of the sweep's parameters.
+    pub values: Vec<f64>,
+    pub rows: Vec<SweepRow>,
+}
+
+/// A year one setting completed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SweepRow {
+    pub year: i32,
+    pub births: f64,
+    pub deaths: f64,
+    pub indicators: Indicators,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    pub parameters: Vec<String>,
+    pub runs: Vec<SweepRun>,
+}
+
+impl Sweep {
+    pub fn new(design: Design) -> Sweep {
+        Sweep {
+            design,
+            parameters: Vec::new(),
+            samples: 0,
+            seed: 0,
+            threads: default_threads(),
+            every: default_every(),
+        }
+    }
+
+    pub fn with_axis(mut self, axis: Axis) -> Sweep {
+        self.parameters.push(axis);
+        self
+    }
+
+    /// Draw `samples` settings for a Latin hypercube, with `seed`.
+    pub fn with_samples(mut self, samples: usize, seed: u64) -> Sweep {
+        self.samples = samples;
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Sweep {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_every(mut self, years: usize) -> Sweep {
+        self.every = years.max(1);
+        self
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Sweep, SweepError> {
+        let sweep: Sweep = serde_yaml::from_str(yaml)
+            .map_err(|e| SweepError::new(format!("Couldn't read sweep: {}", e)))?;
+        sweep.points()?;
+        Ok(sweep)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Sweep, SweepError> {
+        let path = path.as_ref();
+        let mut yaml = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut yaml))
+            .map_err(|e| SweepError::new(format!("Couldn't open {}: {}", path.display(), e)))?;
+        Sweep::from_yaml(&yaml)
+    }
+
+    /// Every setting the sweep runs, each in the order of its parameters.
+    pub fn points(&self) -> Result<Vec<Vec<f64>>, SweepError> {
+        if self.parameters.is_empty() {
+            return Err(SweepError::new("A sweep needs at least one parameter."));
+        }
+        if self.every == 0 {
+            return Err(SweepError::new("every must be at least one year."));
+        }
+        match self.design {
+            Design::Grid => {
+                let mut points = vec![Vec::new()];
+                for axis in self.parameters.iter() {
+                    let values = axis.grid()?;
+                    points = points.iter()
+                        .flat_map(|point| values.iter().map(move |&value| {
+                            let mut point: Vec<f64> = point.clone();
+                            point.push(value);
+                            point
+                        }))
+                        .collect();
+                }
+                Ok(points)
+            },
+            Design::List => {
+                let count = self.parameters[0].values.len();
+                if let Some(axis) = self.parameters.iter().find(|axis| axis.values.len() != count) {
+                    return Err(SweepError::new(format!(
+                        "A list sweep needs as many values for {} as for {}.",
+                        axis.parameter, self.parameters[0].parameter)));
+                }
+                Ok((0..count)
+                    .map(|index| self.parameters.iter().map(|axis| axis.values[index]).collect())
+                    .collect())
+            },
+            Design::LatinHypercube => {
+                if self.samples == 0 {
+                    return Err(SweepError::new("A Latin hypercube needs a number of samples."));
+                }
+                let mut random = Random::new(self.seed);
+                let mut columns = Vec::new();
+                for axis in self.parameters.iter() {
+                    let (low, high) = axis.range.ok_or_else(|| SweepError::new(format!(
+                        "{} needs a range for a Latin hypercube.", axis.parameter)))?;
+                    let strata = permutation(self.samples, &mut random);
+                    columns.push(strata.into_iter().map(|stratum| {
+                        let within = (stratum as f64 + random.uniform()) / self.samples as f64;
+                        low + (high - low) * within
+                    }).collect::<Vec<f64>>());
+                }
+                Ok((0..self.samples)
+                    .map(|index| columns.iter().map(|column| column[index]).collect())
+                    .collect())
+            },
+        }
+    }
+
+    /// Run `scenario` at every setting. Every setting is checked before any runs.
+    pub fn run(&self, scenario: &Scenario) -> Result<SweepResult, SweepError> {
+        let points = self.points()?;
+        let scenarios = points.iter().map(|point| {
+            self.parameters.iter().zip(point.iter())
+                .try_fold(scenario.clone(), |scenario, (axis, &value)| {
+                    scenario.with_parameter(&axis.parameter, value)
+                })
+                .map_err(|e| SweepError::new(format!("At {:?}: {}", point, e)))
+        }).collect::<Result<Vec<Scenario>, SweepError>>()?;
+        let every = self.every;
+        let runs = in_parallel(scenarios, self.threads, move |scenario| rows(scenario, every));
+
+        Ok(SweepResult {
+            parameters: self.parameters.iter().map(|axis| axis.parameter.clone()).collect(),
+            runs: runs.into_iter().zip(points)
+                .map(|(rows, values)| SweepRun { values, rows })
+                .collect(),
+        })
+    }
+}
+
+/// `run` each scenario, spread over a fixed number of threads, with the results in order.
+pub(crate) fn in_parallel<T, F>(scenarios: Vec<Scenario>, threads: usize, run: F) -> Vec<T>
+    where T: Send + 'static, F: Fn(&Scenario) -> T + Send + Sync + 'static
+{
+    let scenarios = Arc::new(scenarios);
+    let run = Arc::new(run);
+    let threads = threads.max(1);
+    let handles: Vec<_> = (0..threads).map(|worker| {
+        let (scenarios, run) = (scenarios.clone(), run.clone());
+        thread::spawn(move || {
+            (worker..scenarios.len())
+                .step_by(threads)
+                .map(|index| (index, run(&scenarios[index])))
+                .collect::<Vec<_>>()
+        })
+    }).collect();
+    let mut results: Vec<(usize, T)> = handles.into_iter()
+        .flat_map(|handle| handle.join().expect("worker panicked"))
+        .collect();
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// The numbers from zero to `count` in a random order, by Fisher-Yates.
+pub(crate) fn permutation(count: usize, random: &mut Random) -> Vec<usize> {
+    let mut numbers: Vec<usize> = (0..count).collect();
+    for index in (1..count).rev() {
+        let other = (random.uniform() * (index + 1) as f64) as usize;
+        numbers.swap(index, other.min(index));
+    }
+    numbers
+}
+
+fn rows(scenario: &Scenario, every: usize) -> Vec<SweepRow> {
+    let mut pop = scenario.population();
+    let mut rows = Vec::new();
+    for year in 1..scenario.years + 1 {
+        pop = pop.advance_year();
+        if year % every == 0 || year == scenario.years {
+            rows.push(SweepRow {
+                year: pop.current_year() - 1,
+                births: pop.births() as f64,
+                deaths: pop.deaths() as f64,
+                indicators: pop.indicators(&[]),
+            });
+        }
+    }
+    rows
+}
+
+impl SweepResult {
+    /// A tidy table: a row for each year of each run, with the run's number and setting first.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("run");
+        for parameter in self.parameters.iter() {
+            csv.push(',');
+            csv.push_str(parameter);
+        }
+        csv.push_str(",year,total,births,deaths,crude_birth_rate,crude_death_rate,mean_age,\
+                      median_age,youth_dependency,old_age_dependency,total_dependency,sex_ratio,\
+                      male_life_expectancy,female_life_expectancy,realized_tfr\n");
+        let real = |value: Option<f64>| value.map_or(String::new(), |value| value.to_string());
+        for (run, result) in self.runs.iter().enumerate() {
+            for row in result.rows.iter() {
+                let i = &row.indicators;
+                write!(csv, "{}", run).unwrap();
+                for value in result.values.iter() {
+                    write!(csv, ",{}", value).unwrap();
+                }
+                writeln!(csv, ",{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                         row.year, i.population, row.births, row.deaths,
+                         real(i.crude_birth_rate), real(i.crude_death_rate), real(i.mean_age),
+                         real(i.median_age), real(i.youth_dependency),
+                         real(i.old_age_dependency), real(i.total_dependency),
+                         real(i.sex_ratio), i.male_life_expectancy, i.female_life_expectancy,
+                         real(i.realized_tfr)).unwrap();
+            }
+        }
+        csv
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Axis, Design, Sweep};
+    use scenario::Scenario;
+
+    fn scenario() -> Scenario {
+        let mut scenario = Scenario::new(vec![ ( 500, 500 ); 60 ]);
+        scenario.years = 10;
+        scenario
+    }
+
+    #[test]
+    fn designs_choose_settings() {
+        let grid = Sweep::new(Design::Grid)
+            .with_axis(Axis::values("total_fertility_rate", vec![1.5, 2.5]))
+            .with_axis(Axis::range("adult_mortality_increase", 1.08, 1.12, 3));
+        let points = grid.points().unwrap();
+        assert_eq!(points.len(), 6);
+        assert_eq!(points[0], vec![1.5, 1.08]);
+        assert!((points[4][1] - 1.10).abs() < 1e-12 && points[4][0] == 2.5);
+
+        let list = Sweep::new(Design::List)
+            .with_axis(Axis::values("total_fertility_rate", vec![1.5, 2.5]))
+            .with_axis(Axis::values("sex_ratio", vec![0.5, 0.52]));
+        assert_eq!(list.points().unwrap(), vec![vec![1.5, 0.5], vec![2.5, 0.52]]);
+        let uneven = list.clone().with_axis(Axis::values("female.adult_mortality", vec![0.1]));
+        assert!(uneven.points().is_err());
+
+        let hypercube = Sweep::new(Design::LatinHypercube)
+            .with_axis(Axis::range("total_fertility_rate", 1.0, 3.0, 0))
+            .with_axis(Axis::range("sex_ratio", 0.4, 0.6, 0))
+            .with_samples(10, 3);
+        let points = hypercube.points().unwrap();
+        assert_eq!(points, hypercube.points().unwrap());
+        // One sample in every tenth of each range.
+        for (parameter, low, width) in [(0, 1.0, 0.2), (1, 0.4, 0.02)].iter() {
+            let mut strata: Vec<usize> = points.iter()
+                .map(|point| ((point[*parameter] - low) / width) as usize)
+                .collect();
+            strata.sort();
+            assert_eq!(strata, (0..10).collect::<Vec<_>>());
+        }
+        assert!(Sweep::new(Design::Grid).points().is_err());
+    }
+
+    #[test]
+    fn runs_every_setting_in_order() {
+        let sweep = Sweep::new(Design::Grid)
+            .with_axis(Axis::values("total_fertility_rate", vec![1.0, 2.0, 3.0]))
+            .with_axis(Axis::values("male.adult_mortality_increase", vec![1.08, 1.12]))
+            .with_every(4);
+        let one = sweep.clone().with_threads(1).run(&scenario()).unwrap();
+        let many = sweep.with_threads(5).run(&scenario()).unwrap();
+        assert_eq!(one, many);
+        assert_eq!(one.runs.len(), 6);
+        let years: Vec<i32> = one.runs[0].rows.iter().map(|row| row.year).collect();
+        assert_eq!(years, vec![3, 7, 9]);
+        // More children with a higher TFR, and fewer men with faster ageing.
+        let last = |run: usize| one.runs[run].rows[2].indicators.clone();
+        assert!(last(2).population > last(0).population);
+        assert!(last(1).male_life_expectancy < last(0).male_life_expectancy);
+        assert_eq!(last(1).female_life_expectancy, last(0).female_life_expectancy);
+
+        let csv = one.to_csv();
+        assert_eq!(csv.lines().count(), 1 + 6 * 3);
+        assert!(csv.starts_with("run,total_fertility_rate,male.adult_mortality_increase,year,"));
+        assert!(csv.lines().nth(1).unwrap().starts_with("0,1,1.08,3,"));
+
+        let bad = Sweep::new(Design::Grid).with_axis(Axis::values("lifespan", vec![80.0]));
+        assert!(bad.run(&scenario()).is_err());
+        let yaml = "design: latin_hypercube\nsamples: 4\nparameters:\n  \
+                    - parameter: sex_ratio\n    range: [0.45, 0.55]\n";
+        assert_eq!(Sweep::from_yaml(yaml).unwrap().points().unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,223 @@
+/*!
+A dashboard for the terminal, for watching and steering a run over SSH.
+
+It shows the year, the total population with a sparkline of its recent history, crude birth and
+death rates, and an age pyramid in five-year groups, or wider ones if the terminal is short. The
+keys are:
+
+```text
+space  play or pause     s  step a year     + -  faster or slower
+] [    raise or lower the TFR by 0.1        q    quit
+```
+
+The dashboard drives the same `Session` as the viewer window. Drawing is kept apart from the
+terminal, in `draw`, so that it can be checked without one.
+*/
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crossterm::{cursor, event, execute, queue, style, terminal};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use format::whole;
+use population::{completed_ages, Population};
+use population::count::Count;
+use session::{Command, Session};
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const KEYS: &str = "space play/pause  s step  +/- speed  [/] TFR  q quit";
+/// How long to wait for a key while paused.
+const IDLE: Duration = Duration::from_millis(250);
+/// Lines that aren't the pyramid.
+const HEADER: usize = 6;
+
+/// Holds the terminal in raw mode on the alternate screen, and puts it back when dropped, so an
+/// error or a panic doesn't leave the shell unusable.
+struct Screen;
+
+impl Screen {
+    fn enter<W: Write>(out: &mut W) -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        let screen = Screen;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        // Nothing more can be done if these fail.
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Run the dashboard until it's quit, and return the population as it was then.
+pub fn run<C: Count>(pop: Population<C>) -> io::Result<Population<C>> {
+    let mut session = Session::new(pop);
+    let mut out = io::stdout();
+    let screen = Screen::enter(&mut out)?;
+    dashboard(&mut session, &mut out)?;
+    drop(screen);
+    Ok(session.into_population())
+}
+
+fn dashboard<C: Count, W: Write>(session: &mut Session<C>, out: &mut W) -> io::Result<()> {
+    let mut message = String::new();
+    loop {
+        let (width, height) = terminal::size()?;
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        let lines = draw(session, width as usize, height as usize, &message);
+        for (row, line) in lines.iter().enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16), style::Print(line))?;
+        }
+        out.flush()?;
+
+        let wait = if session.is_playing() { session.interval() } else { IDLE };
+        if !event::poll(wait)? {
+            session.tick();
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+        let command = match key {
+            KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. } |
+            KeyEvent { code: KeyCode::Char('q'), .. } |
+            KeyEvent { code: KeyCode::Esc, .. } => return Ok(()),
+            KeyEvent { code, .. } => match code {
+                KeyCode::Char(' ') | KeyCode::Char('p') =>
+                    if session.is_playing() { Command::Pause } else { Command::Play },
+                KeyCode::Char('s') | KeyCode::Right => Command::Step,
+                KeyCode::Char('+') | KeyCode::Char('=') =>
+                    Command::Speed { years_per_second: session.years_per_second() * 2.0 },
+                KeyCode::Char('-') =>
+                    Command::Speed { years_per_second: session.years_per_second() / 2.0 },
+                KeyCode::Char(']') => Command::FertilityRate {
+                    value: session.population().total_fertility_rate() + 0.1,
+                },
+                KeyCode::Char('[') => Command::FertilityRate {
+                    value: (session.population().total_fertility_rate() - 0.1).max(0.0),
+                },
+                _ => continue,
+            },
+        };
+        message = match session.apply(command) {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        };
+    }
+}
+
+/// The dashboard's lines for a terminal `width` by `height`, with `message` at the bottom.
+pub fn draw<C: Count>(session: &Session<C>, width: usize, height: usize, message: &str)
+    -> Vec<String>
+{
+    let pop = session.population();
+    let totals = session.totals();
+    let total = totals.back().map(|&(_, total)| total).unwrap_or(0.0);
+    // Rates per thousand of the population midway through the last year.
+    let middle = match totals.len() {
+        0 | 1 => total,
+        n => (totals[n - 2].1 + total) / 2.0,
+    };
+    let rate = |count: C| if middle > 0.0 { 1000.0 * count.to_f64() / middle } else { 0.0 };
+
+    let mut lines = vec![
+        format!("PopSim  year {}  {}  {} years/s", pop.current_year(),
+                if session.is_playing() { "playing" } else { "paused" },
+                session.years_per_second()),
+        format!("Population {}  TFR {:.2}  sex ratio {:.3}", whole(total),
+                pop.total_fertility_rate(), pop.sex_ratio()),
+        format!("Births {:.1} per 1000  deaths {:.1} per 1000", rate(pop.births()),
+                rate(pop.deaths())),
+        sparkline(totals.iter().map(|&(_, total)| total), width),
+    ];
+    let rows = height.saturating_sub(HEADER);
+    lines.extend(pyramid(&completed_ages(&pop.age_sex_distribution()), width, rows));
+    lines.resize(height.saturating_sub(1), String::new());
+    lines.push(if message.is_empty() { String::from(KEYS) } else { String::from(message) });
+    lines.into_iter()
+        .map(|line| line.chars().take(width).collect())
+        .collect()
+}
+
+/// The latest `width` values, scaled from the smallest to the largest.
+fn sparkline<I: Iterator<Item = f64>>(values: I, width: usize) -> String {
+    let values: Vec<f64> = values.collect();
+    let values = &values[values.len().saturating_sub(width)..];
+    let low = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let high = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    values.iter().map(|&value| {
+        let level = if high > low { (value - low) / (high - low) } else { 0.5 };
+        SPARKS[((level * (SPARKS.len() - 1) as f64).round() as usize).min(SPARKS.len() - 1)]
+    }).collect()
+}
+
+/// Males to the left of the ages and females to the right, oldest first, in at most `rows` lines.
+fn pyramid<C: Count>(distribution: &[(C, C)], width: usize, rows: usize) -> Vec<String> {
+    if rows == 0 { return Vec::new(); }
+    let ages = distribution.len().max(1);
+    // Five-year groups if they fit, or wider multiples of five if they don't.
+    let group = ages.div_ceil(rows).div_ceil(5) * 5;
+    let groups: Vec<(f64, f64)> = distribution.chunks(group).map(|chunk| {
+        chunk.iter().fold((0.0, 0.0), |(m, f), &(males, females)| {
+            (m + males.to_f64(), f + females.to_f64())
+        })
+    }).collect();
+    let most = groups.iter().fold(0.0f64, |most, &(m, f)| most.max(m).max(f));
+    let label_width = 9;
+    let side = width.saturating_sub(label_width) / 2;
+    let bar = |count: f64| {
+        if most > 0.0 { (count / most * side as f64).round() as usize } else { 0 }
+    };
+
+    groups.iter().enumerate().rev().map(|(index, &(males, females))| {
+        let start = index * group;
+        let label = format!("{}-{}", start, start + group - 1);
+        format!("{:>side$}{:^label_width$}{:<side$}",
+                "#".repeat(bar(males)), label, "#".repeat(bar(females)),
+                side = side, label_width = label_width)
+            .trim_end()
+            .to_string()
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{draw, sparkline};
+    use session::{Command, Session};
+    use population::Population;
+
+    #[test]
+    fn draws_to_fit_the_terminal() {
+        let mut session = Session::new(Population::new(vec![ ( 1_000, 1_000 ); 70 ]));
+        for _ in 0..10 {
+            session.apply(Command::Step).unwrap();
+        }
+        let lines = draw(&session, 60, 24, "");
+        assert_eq!(lines.len(), 24);
+        assert!(lines.iter().all(|line| line.chars().count() <= 60));
+        assert!(lines[0].contains("year 10") && lines[0].contains("paused"));
+        assert!(lines[2].starts_with("Births "));
+        assert_eq!(lines[3].chars().count(), 11);
+        // The pyramid, oldest at the top, with as many males as females at the base.
+        let base = lines.iter().find(|line| line.contains(" 0-")).unwrap();
+        let (males, females) = base.split_at(base.find("0-").unwrap());
+        assert_eq!(males.matches('#').count(), females.matches('#').count());
+        assert!(lines[23].contains("q quit"));
+
+        let tiny = draw(&session, 20, 3, "The fertility rate can't be negative.");
+        assert_eq!(tiny.len(), 3);
+        assert_eq!(tiny[2], "The fertility rate c");
+    }
+
+    #[test]
+    fn sparklines() {
+        assert_eq!(sparkline(vec![1.0, 2.0, 3.0, 4.0, 5.0].into_iter(), 3), "▁▅█");
+        assert_eq!(sparkline(vec![7.0].into_iter(), 10), "▅");
+    }
+}